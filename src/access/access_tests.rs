@@ -0,0 +1,148 @@
+#[cfg(test)]
+mod access_test {
+    use access::*;
+    use config::OscAccessParams;
+    use rosc::{OscMessage, OscPacket, OscType};
+    use std::net::IpAddr;
+    use std::time::{Duration, Instant};
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    fn change_scene() -> OscMessage {
+        OscMessage {
+            addr: "/ChangeScene".to_string(),
+            args: Some( vec![ OscType::Int(1), OscType::Long(60_000) ] ),
+        }
+    }
+
+    fn access(secret: Option<&str>, rate_limit: Option<f64>) -> Access {
+        new(&OscAccessParams {
+            control:    Some (vec!["192.168.1.0/24".to_string(), "10.0.0.5".to_string()]),
+            sync:       None,
+            query:      Some (vec![]),
+            input:      Some (vec!["192.168.1.0/24".to_string()]),
+            secret:     secret.map(|s| s.to_string()),
+            rate_limit: rate_limit,
+            replay_window_ms: Some (5_000),
+        }).unwrap()
+    }
+
+    fn signed(key: &[u8], message: OscMessage, sent_ms: i64) -> OscMessage {
+        match sign(&Some (key.to_vec()), OscPacket::Message(message), sent_ms) {
+            OscPacket::Message(message) => message,
+            _ => panic!("Expected a message"),
+        }
+    }
+
+    #[test]
+    fn subnets() {
+        let lan = parse_subnet("192.168.1.0/24").unwrap();
+        assert!(contains(&lan, &ip("192.168.1.77")));
+        assert!(!contains(&lan, &ip("192.168.2.1")));
+        assert!(!contains(&lan, &ip("::1")));
+        assert!(contains(&parse_subnet("0.0.0.0/0").unwrap(), &ip("8.8.8.8")));
+        assert!(contains(&parse_subnet("fd00::/8").unwrap(), &ip("fd12::1")));
+        assert!(parse_subnet("10.0.0.0/33").is_err());
+        assert!(parse_subnet("stage-left").is_err());
+    }
+
+    #[test]
+    fn allowlists_by_group() {
+        let mut access = access(None, None);
+        let now = Instant::now();
+        assert!(check(&mut access, change_scene(), ip("10.0.0.5"), now, 0).is_ok());
+        assert_eq!(check(&mut access, change_scene(), ip("10.0.0.6"), now, 0), Err(Rejection::NotAllowed(Group::Control)));
+        // Sync has no allowlist, the empty query allowlist accepts no one
+        let heartbeat = OscMessage { addr: "/MasterAlive".to_string(), args: Some( vec![ OscType::Long(0) ] ) };
+        assert!(check(&mut access, heartbeat, ip("10.0.0.6"), now, 0).is_ok());
+        let request = OscMessage { addr: "/StateRequest".to_string(), args: Some( vec![ OscType::Int(2) ] ) };
+        assert_eq!(check(&mut access, request, ip("10.0.0.5"), now, 0), Err(Rejection::NotAllowed(Group::Query)));
+        let reading = OscMessage { addr: "/input/wind".to_string(), args: Some( vec![ OscType::Float(0.5) ] ) };
+        assert!(check(&mut access, reading.clone(), ip("192.168.1.40"), now, 0).is_ok());
+        assert_eq!(check(&mut access, reading, ip("10.0.0.5"), now, 0), Err(Rejection::NotAllowed(Group::Input)));
+    }
+
+    #[test]
+    fn signatures() {
+        let mut access = access(Some ("shared secret"), None);
+        let now = Instant::now();
+        let from = ip("192.168.1.10");
+        let wall_ms = 1_500_000_000_000;
+        assert_eq!(check(&mut access, change_scene(), from, now, wall_ms), Err(Rejection::Unsigned));
+
+        let message = signed(b"shared secret", change_scene(), wall_ms);
+        assert_eq!(check(&mut access, message.clone(), from, now, wall_ms), Ok(change_scene()));
+
+        let mut tampered = signed(b"shared secret", change_scene(), wall_ms + 1);
+        tampered.args.as_mut().unwrap()[0] = OscType::Int(2);
+        assert_eq!(check(&mut access, tampered, from, now, wall_ms), Err(Rejection::BadSignature));
+
+        let wrong_key = signed(b"guess", change_scene(), wall_ms);
+        assert_eq!(check(&mut access, wrong_key, from, now, wall_ms), Err(Rejection::BadSignature));
+
+        // Sync messages are signed too, query messages aren't
+        let heartbeat = OscMessage { addr: "/MasterAlive".to_string(), args: Some( vec![ OscType::Long(0) ] ) };
+        assert_eq!(check(&mut access, heartbeat.clone(), from, now, wall_ms), Err(Rejection::Unsigned));
+        assert_eq!(check(&mut access, signed(b"shared secret", heartbeat.clone(), wall_ms), from, now, wall_ms), Ok(heartbeat));
+        let request = OscMessage { addr: "/StateRequest".to_string(), args: Some( vec![ OscType::Int(2) ] ) };
+        assert_eq!(signed(b"shared secret", request.clone(), wall_ms), request);
+
+        // Sensors and control surfaces can't sign their input
+        let reading = OscMessage { addr: "/input/wind".to_string(), args: Some( vec![ OscType::Float(0.5) ] ) };
+        assert_eq!(check(&mut access, reading.clone(), from, now, wall_ms), Ok(reading.clone()));
+        assert_eq!(signed(b"shared secret", reading.clone(), wall_ms), reading);
+        let refresh = OscMessage { addr: "/RefreshBackground".to_string(), args: None };
+        assert_eq!(check(&mut access, refresh.clone(), from, now, wall_ms), Ok(refresh));
+    }
+
+    #[test]
+    fn replays() {
+        let mut access = access(Some ("shared secret"), None);
+        let now = Instant::now();
+        let from = ip("192.168.1.10");
+        let wall_ms = 1_500_000_000_000;
+        let message = signed(b"shared secret", change_scene(), wall_ms);
+        assert!(check(&mut access, message.clone(), from, now, wall_ms + 100).is_ok());
+        assert_eq!(check(&mut access, message.clone(), from, now, wall_ms + 200), Err(Rejection::Replayed));
+        // Outside the window, whether seen or not
+        assert_eq!(check(&mut access, message, from, now, wall_ms + 6_000), Err(Rejection::Stale));
+        let early = signed(b"shared secret", change_scene(), wall_ms + 6_000);
+        assert_eq!(check(&mut access, early, from, now, wall_ms), Err(Rejection::Stale));
+
+        // Signatures are forgotten once they're out of the window
+        let later = signed(b"shared secret", change_scene(), wall_ms + 5_001);
+        assert!(check(&mut access, later, from, now, wall_ms + 5_001).is_ok());
+        assert_eq!(access.seen.len(), 1);
+    }
+
+    #[test]
+    fn rate_limits_each_source() {
+        let mut access = access(None, Some (5.0));
+        let start = Instant::now();
+        let from = ip("192.168.1.10");
+        for _ in 0..5 {
+            assert!(check(&mut access, change_scene(), from, start, 0).is_ok());
+        }
+        assert_eq!(check(&mut access, change_scene(), from, start, 0), Err(Rejection::RateLimited));
+        // Another source has its own allowance
+        assert!(check(&mut access, change_scene(), ip("192.168.1.11"), start, 0).is_ok());
+        // One more after a fifth of a second
+        let later = start + Duration::from_millis(200);
+        assert!(check(&mut access, change_scene(), from, later, 200).is_ok());
+        assert_eq!(check(&mut access, change_scene(), from, later, 200), Err(Rejection::RateLimited));
+    }
+
+    #[test]
+    fn counts_rejections() {
+        let mut access = access(None, None);
+        let now = Instant::now();
+        assert!(receive(&mut access, &[0, 1, 2], ip("10.0.0.5"), now, 0).is_none());
+        let packet = ::rosc::encoder::encode(&OscPacket::Message(change_scene())).unwrap();
+        assert!(receive(&mut access, &packet, ip("172.16.0.1"), now, 0).is_none());
+        assert!(receive(&mut access, &packet, ip("10.0.0.5"), now, 0).is_some());
+        assert_eq!(access.rejected.malformed, 1);
+        assert_eq!(access.rejected.not_allowed, 1);
+    }
+}
@@ -0,0 +1,378 @@
+use hmac::{Hmac, Mac};
+use rosc;
+use rosc::{OscMessage, OscPacket, OscType};
+use sha2::Sha256;
+
+use config::{OscAccessParams, Soundscape};
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+mod access_tests;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Sources tracked for rate limiting before quiet ones are forgotten
+const MAX_BUCKETS: usize = 1024;
+// How far a signed message's send time can be from the wall clock, unless configured
+const DEFAULT_REPLAY_WINDOW_MS: i64 = 10_000;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Group {
+    Control,    // changes what plays
+    Sync,       // between units
+    Query,      // asks for state
+    Input,      // sensor readings and background refreshes, from senders which can't sign
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Rejection {
+    NotAllowed(Group),
+    Unsigned,
+    BadSignature,
+    Stale,      // sent outside the replay window
+    Replayed,   // a signature already received
+    RateLimited,
+}
+
+// An address, or the network of addresses sharing its first prefix_len bits
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Subnet {
+    pub address:    IpAddr,
+    pub prefix_len: u8,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Rejected {
+    pub not_allowed:    u64,
+    pub unsigned:       u64,
+    pub bad_signature:  u64,
+    pub stale:          u64,
+    pub replayed:       u64,
+    pub rate_limited:   u64,
+    pub malformed:      u64,
+}
+
+// Tokens refill at the rate limit, up to a second's worth
+struct Bucket {
+    tokens:     f64,
+    updated:    Instant,
+}
+
+// OSC access
+// Filters received messages by their source and address. Each group of addresses can be limited
+// to a list of subnets, and control, query and input messages are rate limited per source. Sync
+// messages arrive every tick from the leader so aren't rate limited.
+// With a secret, control and sync messages must carry the wall clock time they were sent and an
+// HMAC-SHA256 signature of the rest of the message as their last two arguments. Messages sent
+// outside the replay window, or with a signature already received, are replays and rejected, so
+// units' wall clocks need to agree to within the window.
+pub struct Access {
+    pub control:            Option<Vec<Subnet>>,
+    pub sync:               Option<Vec<Subnet>>,
+    pub query:              Option<Vec<Subnet>>,
+    pub input:              Option<Vec<Subnet>>,
+    pub secret:             Option<Vec<u8>>,
+    pub rate_limit:         Option<f64>,
+    pub replay_window_ms:   i64,
+    pub rejected:           Rejected,
+    buckets:                HashMap<IpAddr, Bucket>,
+    seen:                   HashSet<Vec<u8>>, // signatures in the replay window
+    seen_expiry:            VecDeque<(i64, Vec<u8>)>, // the same signatures and when they leave the window
+    last_logged:            Option<Instant>,
+}
+
+pub fn parse_subnet(text: &str) -> Result<Subnet, String> {
+    let (address, prefix) = match text.find('/') {
+        Some (i) => (&text[..i], Some (&text[i + 1..])),
+        None => (text, None),
+    };
+    let address = address.trim().parse::<IpAddr>()
+        .map_err(|e| format!("Unable to read address '{}': {}", text, e))?;
+    let width = match address {
+        IpAddr::V4 (_) => 32,
+        IpAddr::V6 (_) => 128,
+    };
+    let prefix_len = match prefix {
+        Some (prefix) => match prefix.trim().parse::<u8>() {
+            Ok (len) if len <= width => len,
+            _ => return Err(format!("Expected a prefix length up to {} in '{}'", width, text)),
+        },
+        None => width,
+    };
+    Ok(Subnet { address: address, prefix_len: prefix_len })
+}
+
+fn parse_allowlist(list: &Option<Vec<String>>) -> Result<Option<Vec<Subnet>>, String> {
+    match *list {
+        Some (ref list) => list.iter()
+            .map(|text| parse_subnet(text))
+            .collect::<Result<Vec<Subnet>, String>>()
+            .map(Some),
+        None => Ok(None),
+    }
+}
+
+pub fn new(params: &OscAccessParams) -> Result<Access, String> {
+    Ok(Access {
+        control:            parse_allowlist(&params.control)?,
+        sync:               parse_allowlist(&params.sync)?,
+        query:              parse_allowlist(&params.query)?,
+        input:              parse_allowlist(&params.input)?,
+        secret:             params.secret.as_ref().map(|s| s.as_bytes().to_vec()),
+        rate_limit:         params.rate_limit.filter(|rate| *rate > 0.0),
+        replay_window_ms:   params.replay_window_ms.unwrap_or(DEFAULT_REPLAY_WINDOW_MS).max(1),
+        rejected:           Rejected::default(),
+        buckets:            HashMap::new(),
+        seen:               HashSet::new(),
+        seen_expiry:        VecDeque::new(),
+        last_logged:        None,
+    })
+}
+
+// Without an osc_access section every message is accepted
+pub fn from_config(config: &Soundscape) -> Result<Access, String> {
+    match config.osc_access {
+        Some (ref params) => new(params),
+        None => new(&OscAccessParams { control: None, sync: None, query: None, input: None, secret: None, rate_limit: None, replay_window_ms: None }),
+    }
+}
+
+// The key outgoing control and sync messages are signed with
+pub fn secret(config: &Soundscape) -> Option<Vec<u8>> {
+    config.osc_access.as_ref()
+        .and_then(|params| params.secret.as_ref())
+        .map(|s| s.as_bytes().to_vec())
+}
+
+// Anything not known to be sync, query or input is treated as control
+pub fn group(addr: &str) -> Group {
+    match addr {
+        "/MasterAlive" | "/Election" | "/TimeRequest" | "/TimeReply" | "/StateSnapshot" => Group::Sync,
+        "/StateRequest" => Group::Query,
+        "/RefreshBackground" => Group::Input,
+        _ if addr.starts_with("/input/") => Group::Input,
+        _ => Group::Control,
+    }
+}
+
+fn bits(address: &IpAddr) -> (u128, u32) {
+    match *address {
+        IpAddr::V4 (a) => (u32::from(a) as u128, 32),
+        IpAddr::V6 (a) => (u128::from(a), 128),
+    }
+}
+
+pub fn contains(subnet: &Subnet, address: &IpAddr) -> bool {
+    let (network, width) = bits(&subnet.address);
+    let (address, address_width) = bits(address);
+    if width != address_width {
+        return false
+    }
+    let shift = width - subnet.prefix_len as u32;
+    network.checked_shr(shift).unwrap_or(0) == address.checked_shr(shift).unwrap_or(0)
+}
+
+fn allowlist(access: &Access, group: Group) -> &Option<Vec<Subnet>> {
+    match group {
+        Group::Control  => &access.control,
+        Group::Sync     => &access.sync,
+        Group::Query    => &access.query,
+        Group::Input    => &access.input,
+    }
+}
+
+fn signature(secret: &[u8], message: &OscMessage) -> Option<Vec<u8>> {
+    let encoded = rosc::encoder::encode(&OscPacket::Message(message.clone())).ok()?;
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC takes a key of any length");
+    mac.update(&encoded);
+    Some(mac.finalize().into_bytes().to_vec())
+}
+
+fn is_signed(group: Group) -> bool {
+    group == Group::Control || group == Group::Sync
+}
+
+// Add the wall clock time in milliseconds and a signature to control and sync messages when
+// there is a secret
+pub fn sign(secret: &Option<Vec<u8>>, packet: OscPacket, sent_ms: i64) -> OscPacket {
+    match (secret, packet) {
+        (&Some (ref secret), OscPacket::Message(mut message)) => {
+            if is_signed(group(&message.addr)) {
+                message.args.get_or_insert_with(Vec::new).push(OscType::Long(sent_ms));
+                if let Some (code) = signature(secret, &message) {
+                    message.args.get_or_insert_with(Vec::new).push(OscType::Blob(code));
+                }
+            }
+            OscPacket::Message(message)
+        },
+        (_, packet) => packet,
+    }
+}
+
+fn pop_arg(message: &mut OscMessage) -> Option<OscType> {
+    let arg = message.args.as_mut().and_then(|args| args.pop());
+    if message.args.as_ref().map(|args| args.is_empty()).unwrap_or(false) {
+        message.args = None;
+    }
+    arg
+}
+
+// Check the signature in the last argument, then that the send time before it is in the
+// window and hasn't been seen, returning the message without either
+fn verify(access: &mut Access, mut message: OscMessage, wall_ms: i64) -> Result<OscMessage, Rejection> {
+    let code = match pop_arg(&mut message) {
+        Some (OscType::Blob(code)) => code,
+        _ => return Err(Rejection::Unsigned),
+    };
+    let encoded = match rosc::encoder::encode(&OscPacket::Message(message.clone())) {
+        Ok (encoded) => encoded,
+        Err (_) => return Err(Rejection::BadSignature),
+    };
+    let is_valid = match access.secret {
+        Some (ref secret) => {
+            let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC takes a key of any length");
+            mac.update(&encoded);
+            // Compared in constant time
+            mac.verify_slice(&code).is_ok()
+        },
+        None => false,
+    };
+    if !is_valid {
+        return Err(Rejection::BadSignature)
+    }
+
+    let sent_ms = match pop_arg(&mut message) {
+        Some (OscType::Long(sent_ms)) => sent_ms,
+        _ => return Err(Rejection::Unsigned),
+    };
+    let window_ms = access.replay_window_ms;
+    if (wall_ms - sent_ms).abs() > window_ms {
+        return Err(Rejection::Stale)
+    }
+    forget_expired(access, wall_ms);
+    if !access.seen.insert(code.clone()) {
+        return Err(Rejection::Replayed)
+    }
+    // Kept in order of expiry by holding a signature as long as the one received before it
+    let expires_ms = access.seen_expiry.back()
+        .map(|&(expires_ms, _)| expires_ms)
+        .unwrap_or(sent_ms + window_ms)
+        .max(sent_ms + window_ms);
+    access.seen_expiry.push_back((expires_ms, code));
+    Ok(message)
+}
+
+// Signatures sent before the window can't be replayed, as they'd be stale
+fn forget_expired(access: &mut Access, wall_ms: i64) {
+    while access.seen_expiry.front().map(|&(expires_ms, _)| expires_ms < wall_ms).unwrap_or(false) {
+        if let Some ((_, code)) = access.seen_expiry.pop_front() {
+            access.seen.remove(&code);
+        }
+    }
+}
+
+fn take_token(access: &mut Access, rate: f64, from: IpAddr, now: Instant) -> bool {
+    if access.buckets.len() >= MAX_BUCKETS && !access.buckets.contains_key(&from) {
+        access.buckets.retain(|_, bucket| now.duration_since(bucket.updated) < Duration::from_secs(1));
+    }
+    let burst = rate.max(1.0);
+    let bucket = access.buckets.entry(from).or_insert(Bucket { tokens: burst, updated: now });
+    let elapsed = now.duration_since(bucket.updated);
+    let elapsed_s = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+    bucket.tokens = (bucket.tokens + elapsed_s * rate).min(burst);
+    bucket.updated = now;
+    if bucket.tokens >= 1.0 {
+        bucket.tokens -= 1.0;
+        true
+    }
+    else {
+        false
+    }
+}
+
+// Accept or reject a message from a source at the monotonic and wall clock times, accepted
+// messages are returned without a send time and signature
+pub fn check(access: &mut Access, message: OscMessage, from: IpAddr, now: Instant, wall_ms: i64) -> Result<OscMessage, Rejection> {
+    let group = group(&message.addr);
+    if let Some (ref list) = *allowlist(access, group) {
+        if !list.iter().any(|subnet| contains(subnet, &from)) {
+            return Err(Rejection::NotAllowed(group))
+        }
+    }
+
+    // Limited before checking signatures, which cost more
+    if let Some (rate) = access.rate_limit {
+        if group != Group::Sync && !take_token(access, rate, from, now) {
+            return Err(Rejection::RateLimited)
+        }
+    }
+
+    match access.secret.is_some() && is_signed(group) {
+        true    => verify(access, message, wall_ms),
+        false   => Ok(message),
+    }
+}
+
+fn count(access: &mut Access, rejection: &Rejection) {
+    let rejected = &mut access.rejected;
+    match *rejection {
+        Rejection::NotAllowed (_)   => rejected.not_allowed += 1,
+        Rejection::Unsigned         => rejected.unsigned += 1,
+        Rejection::BadSignature     => rejected.bad_signature += 1,
+        Rejection::Stale            => rejected.stale += 1,
+        Rejection::Replayed         => rejected.replayed += 1,
+        Rejection::RateLimited      => rejected.rate_limited += 1,
+    }
+}
+
+pub fn describe_rejected(rejected: &Rejected) -> String {
+    format!(
+        "Rejected so far: {} not allowed, {} unsigned, {} bad signature, {} stale, {} replayed, {} rate limited, {} malformed.",
+        rejected.not_allowed, rejected.unsigned, rejected.bad_signature, rejected.stale, rejected.replayed, rejected.rate_limited, rejected.malformed
+    )
+}
+
+// Log at most once a second, a flood shows up in the counts
+fn should_log(access: &mut Access, now: Instant) -> bool {
+    match access.last_logged {
+        Some (logged) if now.duration_since(logged) < Duration::from_secs(1) => false,
+        _ => {
+            access.last_logged = Some (now);
+            true
+        },
+    }
+}
+
+// Decode and check a received packet, counting and logging anything rejected
+pub fn receive(access: &mut Access, bytes: &[u8], from: IpAddr, now: Instant, wall_ms: i64) -> Option<OscPacket> {
+    let packet = match rosc::decoder::decode(bytes) {
+        Ok (packet) => packet,
+        Err (e) => {
+            access.rejected.malformed += 1;
+            if should_log(access, now) {
+                println!("Rejected malformed OSC packet from {}: {:?}. {}", from, e, describe_rejected(&access.rejected));
+            }
+            return None
+        },
+    };
+
+    match packet {
+        OscPacket::Message(message) => {
+            let addr = message.addr.clone();
+            match check(access, message, from, now, wall_ms) {
+                Ok (message) => Some (OscPacket::Message(message)),
+                Err (rejection) => {
+                    count(access, &rejection);
+                    if should_log(access, now) {
+                        println!("Rejected {} from {}: {:?}. {}", addr, from, rejection, describe_rejected(&access.rejected));
+                    }
+                    None
+                },
+            }
+        },
+        // Bundles aren't routed
+        bundle => Some (bundle),
+    }
+}
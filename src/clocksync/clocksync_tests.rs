@@ -0,0 +1,71 @@
+#[cfg(test)]
+mod clocksync_test {
+    use clocksync::*;
+
+    // A round trip to a leader whose timeline is leader_offset ahead of our audio clock
+    fn round_trip(sync: &mut ClockSync, audio_ms: i64, leader_offset: i64, out_ms: i64, back_ms: i64) -> bool {
+        let t0 = tick(sync, sync.interval_ms, audio_ms).unwrap();
+        let t1 = t0 + out_ms + leader_offset;
+        let t2 = t1 + 1;
+        let t3 = t0 + out_ms + 1 + back_ms;
+        receive_reply(sync, t0, t1, t2, t3)
+    }
+
+    fn following() -> ClockSync {
+        let mut sync = new(1000, 10.0, 500, 10_000);
+        assert!(receive_heartbeat(&mut sync, 7));
+        sync
+    }
+
+    #[test]
+    fn symmetric_delay_cancels() {
+        let mut sync = following();
+        assert!(round_trip(&mut sync, 1_000, 250, 20, 20));
+        assert!(!receive_heartbeat(&mut sync, 7));
+        assert_eq!(best_sample(&sync), Some (Sample { offset_ms: 250.0, delay_ms: 40.0 }));
+        assert_eq!(error_ms(&sync, 240), Some (10.0));
+    }
+
+    #[test]
+    fn least_delay_wins() {
+        let mut sync = following();
+        // Queueing on the way back makes the leader look behind
+        round_trip(&mut sync, 1_000, 250, 5, 85);
+        round_trip(&mut sync, 2_000, 250, 5, 7);
+        round_trip(&mut sync, 3_000, 250, 60, 4);
+        assert_eq!(best_sample(&sync).unwrap().offset_ms, 249.0);
+        assert!(jitter_ms(&sync) > 10.0);
+    }
+
+    #[test]
+    fn stale_replies_are_ignored() {
+        let mut sync = following();
+        let t0 = tick(&mut sync, 1000, 1_000).unwrap();
+        tick(&mut sync, 1000, 2_000).unwrap();
+        assert!(!receive_reply(&mut sync, t0, 1_250, 1_250, 1_040));
+
+        // A new leader starts measuring afresh
+        round_trip(&mut sync, 3_000, 250, 20, 20);
+        assert!(receive_heartbeat(&mut sync, 8));
+        assert!(best_sample(&sync).is_none());
+    }
+
+    #[test]
+    fn slews_within_rate() {
+        let mut sync = following();
+        round_trip(&mut sync, 1_000, 250, 20, 20);
+
+        // 10ms/s takes 30ms out over 3s without moving more than a millisecond a tick
+        let mut offset = 220;
+        for _ in 0..300 {
+            let delta = slew(&mut sync, offset, 10);
+            assert!(delta >= 0 && delta <= 1);
+            offset += delta;
+        }
+        assert_eq!(offset, 250);
+        assert_eq!(slew(&mut sync, offset, 10), 0);
+
+        // Far out is stepped
+        assert_eq!(slew(&mut sync, -1_000, 10), 1_250);
+    }
+}
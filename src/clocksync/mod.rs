@@ -0,0 +1,202 @@
+use rosc::{OscMessage, OscPacket, OscType};
+
+use config::Soundscape;
+
+use std::collections::VecDeque;
+
+mod clocksync_tests;
+
+// Round trips kept for filtering
+const WINDOW: usize = 8;
+// Replies slower than this say little about the leader's clock
+const MAX_DELAY_MS: i64 = 1000;
+
+// One round trip to the leader
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sample {
+    pub offset_ms:  f64, // leader's timeline less this unit's audio clock
+    pub delay_ms:   f64, // round trip time, less the time the leader held the request
+}
+
+// Clock sync
+// Measures the leader's timeline against this unit's audio clock as NTP does. A request stamped
+// t0 on the audio clock is stamped t1 and t2 on the leader's timeline as it is received and
+// answered, and the reply is stamped t3 on arrival. The round trip with the least delay in the
+// window gives the offset, as it has the least room for one way latency to skew it.
+// The transport is slewed toward that offset so the timeline never jumps, unless it is far out
+// such as when a new leader is followed.
+pub struct ClockSync {
+    pub leader:             Option<u32>, // unit id of the leader being measured
+    pub samples:            VecDeque<Sample>,
+    pub pending_request:    Option<i64>, // t0 of the request awaiting a reply
+    pub since_request_ms:   i64,
+    pub since_report_ms:    i64,
+    pub slew_allowance_us:  i64,
+    pub interval_ms:        i64,
+    pub slew_ms_per_s:      f64,
+    pub step_ms:            i64,
+    pub report_ms:          i64,
+}
+
+pub fn new(interval_ms: i64, slew_ms_per_s: f64, step_ms: i64, report_ms: i64) -> ClockSync {
+    ClockSync {
+        leader:             None,
+        samples:            VecDeque::with_capacity(WINDOW),
+        pending_request:    None,
+        since_request_ms:   0,
+        since_report_ms:    0,
+        slew_allowance_us:  0,
+        interval_ms:        interval_ms,
+        slew_ms_per_s:      slew_ms_per_s,
+        step_ms:            step_ms,
+        report_ms:          report_ms,
+    }
+}
+
+pub fn from_config(config: &Soundscape) -> ClockSync {
+    match config.clock_sync {
+        Some (ref params) => new(
+            params.interval_ms.unwrap_or(1000).max(1),
+            params.slew_ms_per_s.unwrap_or(10.0).max(0.0),
+            params.step_ms.unwrap_or(500).max(1),
+            params.report_ms.unwrap_or(10_000).max(1),
+        ),
+        None => new(1000, 10.0, 500, 10_000),
+    }
+}
+
+// Measure against a new leader, or stop measuring with None
+pub fn follow(sync: &mut ClockSync, leader: Option<u32>) {
+    if sync.leader != leader {
+        sync.leader = leader;
+        sync.samples.clear();
+        sync.pending_request = None;
+        // Ask straight away
+        sync.since_request_ms = sync.interval_ms;
+    }
+}
+
+// A heartbeat from the leader, true if the timeline should be set from it as there are no
+// round trips to the leader yet
+pub fn receive_heartbeat(sync: &mut ClockSync, leader: u32) -> bool {
+    follow(sync, Some (leader));
+    sync.samples.is_empty()
+}
+
+// Advance the request timer, returning t0 for a request to send when one is due
+pub fn tick(sync: &mut ClockSync, tick_ms: i64, audio_ms: i64) -> Option<i64> {
+    if sync.leader.is_none() {
+        return None
+    }
+    sync.since_request_ms += tick_ms;
+    if sync.since_request_ms >= sync.interval_ms {
+        // An unanswered request is given up on
+        sync.since_request_ms = 0;
+        sync.pending_request = Some (audio_ms);
+        Some (audio_ms)
+    }
+    else {
+        None
+    }
+}
+
+// A reply to a request, returns false if it isn't for the request awaiting one
+pub fn receive_reply(sync: &mut ClockSync, t0: i64, t1: i64, t2: i64, t3: i64) -> bool {
+    if sync.pending_request != Some (t0) {
+        return false
+    }
+    sync.pending_request = None;
+
+    let delay_ms = (t3 - t0) - (t2 - t1);
+    if delay_ms < 0 || delay_ms > MAX_DELAY_MS {
+        return false
+    }
+    if sync.samples.len() >= WINDOW {
+        sync.samples.pop_front();
+    }
+    sync.samples.push_back(Sample {
+        offset_ms:  ((t1 - t0) + (t2 - t3)) as f64 / 2.0,
+        delay_ms:   delay_ms as f64,
+    });
+    true
+}
+
+// The round trip with the least delay
+pub fn best_sample(sync: &ClockSync) -> Option<Sample> {
+    sync.samples.iter().fold(None, |best: Option<Sample>, sample| match best {
+        Some (b) if b.delay_ms <= sample.delay_ms => Some (b),
+        _ => Some (*sample),
+    })
+}
+
+// Spread of the window's offsets about the best sample's
+pub fn jitter_ms(sync: &ClockSync) -> f64 {
+    match best_sample(sync) {
+        Some (best) => {
+            let sum = sync.samples.iter()
+                .map(|s| (s.offset_ms - best.offset_ms).powi(2))
+                .sum::<f64>();
+            (sum / sync.samples.len() as f64).sqrt()
+        },
+        None => 0.0,
+    }
+}
+
+// How far the transport's offset is from the leader's timeline
+pub fn error_ms(sync: &ClockSync, offset_ms: i64) -> Option<f64> {
+    best_sample(sync).map(|best| best.offset_ms - offset_ms as f64)
+}
+
+// Whole milliseconds to shift the transport by this tick. Small errors are taken out no
+// faster than the slew rate, so the timeline speeds up or slows down but keeps moving forward.
+pub fn slew(sync: &mut ClockSync, offset_ms: i64, tick_ms: i64) -> i64 {
+    let error = match error_ms(sync, offset_ms) {
+        Some (error) => error,
+        None => return 0,
+    };
+    if error.abs() >= sync.step_ms as f64 {
+        println!("Clock is {:.0}ms from unit {}, stepping the timeline.", error, sync.leader.unwrap_or(0));
+        sync.slew_allowance_us = 0;
+        return error.round() as i64
+    }
+
+    // Allowance builds up over ticks which can't use a whole millisecond, but not beyond one tick's worth
+    let per_tick_us = (sync.slew_ms_per_s * tick_ms as f64).round() as i64;
+    sync.slew_allowance_us = (sync.slew_allowance_us + per_tick_us).min(per_tick_us.max(1000));
+    let limit = sync.slew_allowance_us / 1000;
+    let delta = (error.round() as i64).max(-limit).min(limit);
+    sync.slew_allowance_us -= delta.abs() * 1000;
+    delta
+}
+
+// A line reporting the offset, delay and jitter when one is due
+pub fn report(sync: &mut ClockSync, offset_ms: i64, tick_ms: i64) -> Option<String> {
+    sync.since_report_ms += tick_ms;
+    if sync.since_report_ms < sync.report_ms {
+        return None
+    }
+    sync.since_report_ms = 0;
+    match (sync.leader, best_sample(sync), error_ms(sync, offset_ms)) {
+        (Some (leader), Some (best), Some (error)) => Some (format!(
+            "Clock sync with unit {}: offset {:+.1}ms, delay {:.1}ms, jitter {:.1}ms over {} round trips.",
+            leader, error, best.delay_ms, jitter_ms(sync), sync.samples.len()
+        )),
+        (Some (leader), _, _) => Some (format!("Clock sync with unit {}: no replies yet.", leader)),
+        _ => None,
+    }
+}
+
+pub fn request_packet(unit_id: u32, t0: i64) -> OscPacket {
+    OscPacket::Message(OscMessage {
+        addr: "/TimeRequest".to_string(),
+        args: Some( vec![ OscType::Int(unit_id as i32), OscType::Long(t0) ] ),
+    })
+}
+
+// The reply names the unit which asked, as it goes to every subscriber
+pub fn reply_packet(unit_id: u32, t0: i64, t1: i64, t2: i64) -> OscPacket {
+    OscPacket::Message(OscMessage {
+        addr: "/TimeReply".to_string(),
+        args: Some( vec![ OscType::Int(unit_id as i32), OscType::Long(t0), OscType::Long(t1), OscType::Long(t2) ] ),
+    })
+}
@@ -1,12 +1,12 @@
 #[cfg(test)]
 mod config_test {
     use config::*;
-    use ::epochsy;
 
     fn test_config() -> Soundscape {
         Soundscape {
             listen_addr:            Address { host: "127.0.0.1".to_string(), port: 4000 },
             subscribers:            vec![ Address { host: "127.0.0.1".to_string(), port: 4000 } ],
+            osc_access:             None,
             scenes:                 vec![],
             metro_step_ms:          10,
             voice_limit:            16,
@@ -15,92 +15,137 @@ mod config_test {
             speaker_positions:      Speakers { positions: vec![] },
             ignore_extra_speakers:  Some (true),
             is_fallback_slave:      None,
+            election:               None,
+            clock_sync:             None,
+            timezone:               None,
             daily_schedule:         Some (DailySchedule { start: "17:30:00".to_string(), end: "23:00:00".to_string() }),
+            schedule:               None,
+            limiter:                None,
+            metering:               None,
+            output:                 None,
+            inputs:                 None,
+            playlist:               None,
+            sleep:                  None,
         }
     }
 
-    #[test]
-    fn diag() {
-        let show_diag = true;//false; // toggle to fail and print the following diag info
-        let config = test_config();
-        let today = local_today();
-        println!("today {} ({:?})", from_timestamp(moment(&today) as i64), today);
-        let start = next_start_time(&config, &today);
-        println!("start {} ({:?})", from_timestamp(moment(&start) as i64), start);
-        let end = next_end_time(&config, &start).unwrap();
-        println!("end {} ({:?})", from_timestamp(moment(&end) as i64), end);
-        println!("now {} ({:?})", from_timestamp(moment(&localtime()) as i64), localtime());
-        println!("Is now in schedule? {}", is_in_schedule(&localtime(), &start, &end));
-        assert!(!show_diag);
+    fn test_resource() -> SoundResource {
+        SoundResource {
+            path:           "example/samples/pad-loop1.flac".to_string(),
+            curve:          None,
+            min_threshold:  1.0,
+            max_threshold:  100.0,
+            hysteresis:     None,
+            crossfade:      None,
+            min_on_ms:      None,
+            min_off_ms:     None,
+            gain:           0.0,
+            fade_in_steps:  None,
+            fade_out_steps: None,
+            fade_in_ms:     None,
+            fade_out_ms:    None,
+            fade_shape:     None,
+            reverb:         None,
+            position:       None,
+            modulations:    None,
+            weight:         None,
+            variants:       None,
+            variant_mode:   None,
+            variant_change: None,
+            seed:           None,
+        }
     }
 
     #[test]
-    fn schedules() {
-        let config = test_config();
-        println!("now: {:?}", &epochsy::now());
-        println!("localtime: {:?}", localtime());
-        println!("11:45:26 <=> {:?}", &epochsy::hms(11, 45, 26));
-        println!("midnight today <=> {:?}", epochsy::moment(&to_localtime(&epochsy::floor_to_days(&epochsy::now()))));
-
-        println!("is_in_schedule_now currently? {:?}", is_in_schedule_now(&config, &localtime()));
-
-        let start = next_start_time(&config, &localtime());
-        assert!(start.moment > 0);
-
-        // assert_eq!(from_timestamp(start).timestamp(), start);
-        // let test_now = now();
-        // assert_eq!(from_timestamp(test_now.timestamp()).timestamp(), test_now.timestamp());
-        // println!("{:?} <=> {:?}", test_now, test_now.timestamp());
-
-        let end_time_from_start = next_end_time(&config, &start);
-        assert_ne!(end_time_from_start, None);
-        let end_from_start = end_time_from_start.unwrap();
-        let end_time = next_end_time(&config, &localtime());
-        assert_ne!(end_time, None);
-        let end = end_time.unwrap();
-
-        // assert_eq!(to_localtime(&from_localtime(&start)).moment, start.moment);
-        println!("working with start: {:?} and end: {:?}", start, end );
-        println!("total interval in seconds: {:?}", epochsy::diff(&end, &start));
-
-        // characteristic features
-        assert!(moment(&start) < moment(&end_from_start));
-        // assert expected duration
-        // assert_eq!(moment(&end_from_start) - moment(&start), 23400);
-
-        // assert_eq!(start_local.hour(), 18);
-        // assert_eq!(start_local.minute(), 30);
-        // assert_eq!(start_local.second(), 0);
-        //
-        // assert_eq!(end_local.hour(), 1);
-        // assert_eq!(end_local.minute(), 0);
-        // assert_eq!(end_local.second(), 0);
-
-
-        println!("is_in_schedule currently? {:?} ({:?} to {:?})", is_in_schedule(&localtime(), &start, &end), start, end);
-        assert!(is_in_schedule(&start, &start, &end_from_start));
-        assert!(is_in_schedule(&end_from_start, &start, &end_from_start));
-
-        let before = epochsy::append(&local_today(), &epochsy::hms(15, 39, 0));
-        println!("before: {} ({:?})", from_timestamp(moment(&before) as i64), before);
-        assert!(before.moment < next_start_time(&config, &before).moment);
-        assert!(next_start_time(&config, &before).moment < end_from_start.moment);
+    fn fade_durations() {
+        let mut res = test_resource();
+        assert_eq!(fade_in_ms(&res, 10), 5000.0);
+        assert_eq!(fade_out_ms(&res, 10), 5000.0);
+
+        // steps are kept for older scene files
+        res.fade_in_steps = Some (100);
+        res.fade_out_steps = Some (300);
+        assert_eq!(fade_in_ms(&res, 10), 1000.0);
+        assert_eq!(fade_out_ms(&res, 20), 6000.0);
+
+        // times take precedence over steps
+        res.fade_in_ms = Some (250.0);
+        res.fade_out_ms = Some (750.0);
+        assert_eq!(fade_in_ms(&res, 10), 250.0);
+        assert_eq!(fade_out_ms(&res, 10), 750.0);
+    }
 
-        let after = epochsy::append(&local_today(), &epochsy::hms(25, 31, 0));
-        println!("after: {} ({:?})", from_timestamp(moment(&after) as i64), after);
-        let during = epochsy::append(&local_today(), &epochsy::hms(18, 35, 0));
-        println!("during: {} ({:?})", from_timestamp(moment(&during) as i64), during);
+    #[test]
+    fn resource_curves() {
+        let spline = || StructureParams::BSpline(BSplineParams { points: vec![1.0, 2.0], knots: vec![0.0, 0.0, 1.0, 1.0], degree: 1 });
+        let mut scene = Scene {
+            name:               "curves".to_string(),
+            duration_ms:        1000,
+            cycle_duration_ms:  1000,
+            resources:          vec![],
+            structure:          Some (spline()),
+            curves:             Some (vec![ SceneCurve { name: "density".to_string(), duration_ms: None, curve: spline() } ]),
+            events:             None,
+            bpm:                None,
+            beats_per_bar:      None,
+            quantize:           None,
+            transition:         None,
+        };
+        assert_eq!(scene_curve_names(&scene), vec!["structure".to_string(), "density".to_string()]);
+
+        let mut res = test_resource();
+        assert_eq!(resource_curve(&scene, &res), "structure");
+        res.curve = Some ("density".to_string());
+        assert_eq!(resource_curve(&scene, &res), "density");
+
+        // Without a structure the first named curve is the default
+        scene.structure = None;
+        res.curve = None;
+        assert_eq!(resource_curve(&scene, &res), "density");
+    }
 
-        assert!(!is_in_schedule(&before, &start, &end));
-        assert!(!is_in_schedule(&after, &start, &end));
+    #[test]
+    fn curve_names_and_references() {
+        let reference = |name: &str| CurveParams::Reference { name: name.to_string() };
+        let scene_curve = |name: &str, params| SceneCurve { name: name.to_string(), duration_ms: None, curve: StructureParams::Curve(params) };
+        let mut scene = Scene {
+            name:               "references".to_string(),
+            duration_ms:        1000,
+            cycle_duration_ms:  1000,
+            resources:          vec![],
+            structure:          Some (StructureParams::Curve(CurveParams::Constant { value: 1.0 })),
+            curves:             Some (vec![
+                scene_curve("wind", CurveParams::Expression { op: CurveOperator::Sum, terms: vec![ reference("structure"), reference("gusts") ] }),
+                scene_curve("gusts", CurveParams::Constant { value: 0.5 }),
+            ]),
+            events:             None,
+            bpm:                None,
+            beats_per_bar:      None,
+            quantize:           None,
+            transition:         None,
+        };
+        assert_eq!(check_curves(&scene), Ok(()));
+
+        scene.curves.as_mut().unwrap()[1] = scene_curve("gusts", reference("wind"));
+        assert!(check_curves(&scene).is_err());
+        scene.curves.as_mut().unwrap()[1] = scene_curve("gusts", reference("breeze"));
+        assert!(check_curves(&scene).is_err());
+        scene.curves.as_mut().unwrap()[1] = scene_curve("wind", CurveParams::Constant { value: 0.5 });
+        assert!(check_curves(&scene).is_err());
+
+        // The structure's name is taken even when there is no structure
+        scene.structure = None;
+        scene.curves = Some (vec![ scene_curve("structure", CurveParams::Constant { value: 0.5 }) ]);
+        assert!(check_curves(&scene).is_err());
+    }
 
-        // The start bound is not inclusive.
-        // assert!(is_in_schedule_now(&config, &start));
-        assert!(!is_in_schedule_now(&config, &before));
-        assert!(is_in_schedule_now(&config, &during));
-        assert!(!is_in_schedule_now(&config, &after));
-        let before_end = epochsy::append(&local_today(), &epochsy::hms(20, 29, 0));
-        println!("before_end: {} ({:?})", from_timestamp(moment(&before_end) as i64), before_end);
-        assert!(is_in_schedule_now(&config, &before_end));
+    #[test]
+    fn buffer_size_is_rejected() {
+        let mut config = test_config();
+        assert_eq!(check_output(&config), Ok(()));
+        config.output = Some (OutputParams { device: None, sample_rate: Some (48000), channels: None, buffer_frames: None });
+        assert_eq!(check_output(&config), Ok(()));
+        config.output = Some (OutputParams { device: None, sample_rate: None, channels: None, buffer_frames: Some (256) });
+        assert!(check_output(&config).is_err());
     }
 }
@@ -1,12 +1,14 @@
 use std::fs::File;
 use std::io::prelude::*;
+use std::time::SystemTime;
 use chrono::prelude::*;
 use chrono::Duration;
-use ::epochsy;
-use ::epochsy::{moment, hours, minutes};
 
 use serde_yaml;
 use bspline;
+use rodiox::source::FadeShape;
+use rodiox::source::limiter::LimiterSettings;
+use rodiox::output::OutputSettings;
 
 mod config_tests;
 // Configuration structs
@@ -18,16 +20,171 @@ pub struct BSplineParams {
     pub degree: usize,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    Linear,
+    Step,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Waveform {
+    Sine,
+    Triangle,
+    Saw,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CurveOperator {
+    Sum,
+    Product,
+    Min,
+    Max,
+}
+
+// Curve types, selected with a type field in scene files
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CurveParams {
+    BSpline {
+        points:         Vec<f32>,
+        knots:          Vec<f32>,
+        degree:         usize,
+    },
+    // [time_ms, value] pairs
+    Breakpoints {
+        points:         Vec<[f32; 2]>,
+        interpolation:  Option<Interpolation>,
+    },
+    Lfo {
+        waveform:       Waveform,
+        period_ms:      f32,
+        phase:          Option<f32>,    // fraction of a period, 0 to 1
+        min:            f32,
+        max:            f32,
+    },
+    RandomWalk {
+        seed:           u64,
+        step_ms:        f32,
+        max_step:       f32,
+        min:            f32,
+        max:            f32,
+    },
+    Constant {
+        value:          f32,
+    },
+    Expression {
+        op:             CurveOperator,
+        terms:          Vec<CurveParams>,
+    },
+    // Another curve of the scene, by name, such as structure
+    Reference {
+        name:           String,
+    },
+}
+
+// A structure is either a tagged curve or, for older scene files, untagged B-spline parameters
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StructureParams {
+    Curve(CurveParams),
+    BSpline(BSplineParams),
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SceneCurve {
+    pub name:           String,
+    pub duration_ms:    Option<u64>,   // defaults to the scene's cycle_duration_ms
+    pub curve:          StructureParams,
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct SoundResource {
     pub path:           String,
+    pub curve:          Option<String>,
     pub min_threshold:  f32,
     pub max_threshold:  f32,
+    pub hysteresis:     Option<f32>,    // margin the curve must pass beyond a threshold to deactivate
+    pub crossfade:      Option<f32>,    // width of the gain ramp centred on each threshold
+    pub min_on_ms:      Option<u32>,
+    pub min_off_ms:     Option<u32>,
     pub gain:           f32,
-    pub fade_in_steps:  Option<u32>,
-    pub fade_out_steps: Option<u32>,
+    pub fade_in_steps:  Option<u32>,   // deprecated, use fade_in_ms
+    pub fade_out_steps: Option<u32>,   // deprecated, use fade_out_ms
+    pub fade_in_ms:     Option<f32>,
+    pub fade_out_ms:    Option<f32>,
+    pub fade_shape:     Option<FadeCurve>,
     pub reverb:         Option<ReverbParams>,
     pub position:       Option<[f32; 3]>,
+    pub modulations:    Option<Vec<ModulationParams>>,
+    pub weight:         Option<f32>,    // weight of path among the variants
+    pub variants:       Option<Vec<VariantParams>>,
+    pub variant_mode:   Option<VariantMode>,
+    pub variant_change: Option<VariantChange>,
+    pub seed:           Option<u64>,    // seeds variant selection, random when not set
+}
+
+// An alternative file for a resource, chosen in place of path with the given weight
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct VariantParams {
+    pub path:       String,
+    pub weight:     Option<f32>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VariantMode {
+    Weighted,
+    NoRepeat,
+    ShuffleBag,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VariantChange {
+    Activation,
+    Cycle,      // each time the playing variant reaches its end
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModulationTarget {
+    GainDb,
+    X,
+    Y,
+    Z,
+    ReverbMix,
+    Speed,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseShape {
+    Linear,
+    Exponential,    // slow to start, rising faster towards the top of the input range
+    Logarithmic,    // the inverse of exponential
+    SCurve,
+}
+
+// Maps a curve's value from the input range on to the output range of a target parameter
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModulationParams {
+    pub target:     ModulationTarget,
+    pub curve:      Option<String>,     // defaults to the resource's curve
+    pub input:      [f32; 2],
+    pub output:     [f32; 2],
+    pub shape:      Option<ResponseShape>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FadeCurve {
+    Linear,
+    Exponential,
+    EqualPower,
+    SCurve,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -36,13 +193,154 @@ pub struct ReverbParams {
     pub mix_t:      f32,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StructureStart {
+    Beginning,
+    MatchValue,     // where the curve is closest to the same curve of the previous scene
+}
+
+// How a scene takes over from the scene before it
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransitionParams {
+    pub overlap_ms:         u64,
+    pub shape:              Option<FadeCurve>,
+    pub structure_start:    Option<StructureStart>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Quantize {
+    Beat,
+    Bar,
+}
+
+// One-shot sounds triggered at random with a mean rate, or at fixed times after the scene starts
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct EventParams {
+    pub name:               String,
+    pub samples:            Vec<String>,
+    pub rate_per_min:       Option<f32>,
+    pub curve:              Option<String>,     // scales the rate when set
+    pub input:              Option<[f32; 2]>,   // curve values mapped to a rate scale of 0 to 1
+    pub at_ms:              Option<Vec<i64>>,
+    pub region:             Option<[[f32; 3]; 2]>,  // opposite corners of the box events are placed in
+    pub gain_db:            Option<[f32; 2]>,
+    pub pitch_semitones:    Option<[f32; 2]>,
+    pub reverb:             Option<ReverbParams>,
+    pub seed:               Option<u64>,
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Scene {
     pub name:               String,
     pub duration_ms:        i64,
     pub cycle_duration_ms:  u64,
     pub resources:          Vec<SoundResource>,
-    pub structure:          BSplineParams,
+    pub structure:          Option<StructureParams>,
+    pub curves:             Option<Vec<SceneCurve>>,
+    pub events:             Option<Vec<EventParams>>,
+    pub bpm:                Option<f32>,
+    pub beats_per_bar:      Option<u32>,        // defaults to 4
+    pub quantize:           Option<Quantize>,   // defaults to bar
+    pub transition:         Option<TransitionParams>,
+}
+
+// Name given to a scene's structure curve
+pub const DEFAULT_CURVE: &'static str = "structure";
+
+// Names of the curves declared by a scene, the first is used by resources which don't choose a curve
+pub fn scene_curve_names(scene: &Scene) -> Vec<String> {
+    let mut names = Vec::new();
+    if scene.structure.is_some() {
+        names.push(DEFAULT_CURVE.to_string());
+    }
+    if let Some (ref curves) = scene.curves {
+        for curve in curves {
+            names.push(curve.name.clone());
+        }
+    }
+    names
+}
+
+// Parameters of the curve a scene declares with the name
+pub fn scene_curve_params<'a>(scene: &'a Scene, name: &str) -> Option<&'a StructureParams> {
+    if name == DEFAULT_CURVE {
+        return scene.structure.as_ref()
+    }
+    scene.curves.as_ref()
+        .and_then(|curves| curves.iter().find(|curve| curve.name == name))
+        .map(|curve| &curve.curve)
+}
+
+// Names of the scene curves a curve refers to in its expressions
+pub fn curve_references(params: &StructureParams) -> Vec<String> {
+    fn collect(params: &CurveParams, names: &mut Vec<String>) {
+        match *params {
+            CurveParams::Reference { ref name } => names.push(name.clone()),
+            CurveParams::Expression { ref terms, .. } => {
+                for term in terms {
+                    collect(term, names);
+                }
+            },
+            _ => (),
+        }
+    }
+    let mut names = Vec::new();
+    if let StructureParams::Curve(ref params) = *params {
+        collect(params, &mut names);
+    }
+    names
+}
+
+// Curve names must be unique, refer to curves which exist and not lead back to themselves
+pub fn check_curves(scene: &Scene) -> Result<(), String> {
+    let names = scene_curve_names(scene);
+    if let Some (ref curves) = scene.curves {
+        if curves.iter().any(|curve| curve.name == DEFAULT_CURVE) {
+            return Err(format!("The curve name '{}' is kept for the scene's structure", DEFAULT_CURVE))
+        }
+    }
+    for (i, name) in names.iter().enumerate() {
+        if names[..i].contains(name) {
+            return Err(format!("Curve '{}' is declared more than once", name))
+        }
+    }
+
+    for name in &names {
+        let mut pending = scene_curve_params(scene, name).map(curve_references).unwrap_or(Vec::new());
+        let mut visited: Vec<String> = Vec::new();
+        while let Some (next) = pending.pop() {
+            if &next == name {
+                return Err(format!("Curve '{}' refers back to itself", name))
+            }
+            if visited.contains(&next) {
+                continue
+            }
+            match scene_curve_params(scene, &next) {
+                Some (params) => pending.extend(curve_references(params)),
+                None => return Err(format!("Curve '{}' refers to undefined curve '{}'", name, next)),
+            }
+            visited.push(next);
+        }
+    }
+    Ok(())
+}
+
+// Name of the curve which drives a resource's activation
+pub fn resource_curve(scene: &Scene, res: &SoundResource) -> String {
+    match res.curve {
+        Some (ref name) => name.clone(),
+        None => scene_curve_names(scene).into_iter().next().unwrap_or(DEFAULT_CURVE.to_string()),
+    }
+}
+
+// How long a scene overlaps the scene before it
+pub fn transition_overlap_ms(scene: &Scene) -> i64 {
+    match scene.transition {
+        Some (ref transition) => transition.overlap_ms as i64,
+        None => 0,
+    }
 }
 
 pub fn open_scene(file: &String) -> Scene {
@@ -59,9 +357,56 @@ pub fn open_scene(file: &String) -> Scene {
 
 pub fn check_scene_file(scene_file :&String) -> Result<Scene, &'static str> {
     let scene = open_scene(&scene_file);
+    let curve_names = scene_curve_names(&scene);
+    if curve_names.is_empty() {
+        println!("Scene '{}' has no structure or curves defined", scene_file);
+        return Err("Scene has no curves")
+    }
+    if let Err (e) = check_curves(&scene) {
+        println!("{} in scene '{}'", e, scene_file);
+        return Err("Scene has invalid curves")
+    }
+
     for resource in &scene.resources {
-        File::open(&resource.path)
-            .expect( &format!("Error opening content for resource '{}' from background scene '{}'", resource.path, scene_file) );
+        for path in variant_paths(resource) {
+            File::open(&path)
+                .expect( &format!("Error opening content for resource '{}' from background scene '{}'", path, scene_file) );
+        }
+
+        let curve = resource_curve(&scene, resource);
+        if !curve_names.contains(&curve) {
+            println!("Resource '{}' in scene '{}' uses undefined curve '{}'", resource.path, scene_file, curve);
+            return Err("Resource uses an undefined curve")
+        }
+
+        if let Some (ref modulations) = resource.modulations {
+            for modulation in modulations {
+                if let Some (ref curve) = modulation.curve {
+                    if !curve_names.contains(curve) {
+                        println!("Modulation of {:?} for '{}' in scene '{}' uses undefined curve '{}'", modulation.target, resource.path, scene_file, curve);
+                        return Err("Modulation uses an undefined curve")
+                    }
+                }
+            }
+        }
+    }
+    if let Some (ref events) = scene.events {
+        for event in events {
+            if event.samples.is_empty() {
+                println!("Event '{}' in scene '{}' has no samples", event.name, scene_file);
+                return Err("Event has no samples")
+            }
+            for sample in &event.samples {
+                File::open(sample)
+                    .expect( &format!("Error opening sample '{}' for event '{}' from scene '{}'", sample, event.name, scene_file) );
+            }
+            if let Some (ref curve) = event.curve {
+                if !curve_names.contains(curve) {
+                    println!("Event '{}' in scene '{}' uses undefined curve '{}'", event.name, scene_file, curve);
+                    return Err("Event uses an undefined curve")
+                }
+            }
+        }
     }
     Ok(scene)
 }
@@ -77,16 +422,197 @@ pub struct Speakers {
     pub positions:   Vec<[f32; 3]>,
 }
 
+// A window of time in a day, an end before the start runs past midnight.
+// Times are HH:MM:SS or relative to the sun, such as sunset+00:15 or civil_dusk-00:10
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct DailySchedule {
     pub start:  String,
     pub end:    String,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Day {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct WeeklyWindows {
+    pub days:       Vec<Day>,
+    pub windows:    Vec<DailySchedule>,
+}
+
+// Weekly windows used in place of the regular week between two dates, inclusive
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Season {
+    pub name:       Option<String>,
+    pub from:       String,     // YYYY-MM-DD
+    pub to:         String,
+    pub weekly:     Vec<WeeklyWindows>,
+}
+
+// Replaces the windows of a single date, closed removes them all
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleException {
+    pub date:       String,
+    pub closed:     Option<bool>,
+    pub windows:    Option<Vec<DailySchedule>>,
+}
+
+// Where the installation is, for times relative to the sun. Longitude is positive to the east.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub latitude:   f64,
+    pub longitude:  f64,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleParams {
+    pub location:   Option<Location>,
+    pub weekly:     Option<Vec<WeeklyWindows>>,
+    pub seasons:    Option<Vec<Season>>,
+    pub exceptions: Option<Vec<ScheduleException>>,
+}
+
+// Choosing one unit to lead among the units listed as subscribers. The highest priority leads
+// and the unit id breaks ties, so every unit needs a different id.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ElectionParams {
+    pub unit_id:        u32,
+    pub priority:       u32,
+    pub timeout_ms:     Option<i64>,    // without a leader's heartbeat for this long an election starts
+    pub election_ms:    Option<i64>,    // how long a candidate waits for objections before leading
+    pub failback_ms:    Option<i64>,    // how long a higher priority unit follows before taking the lead back
+}
+
+// How a following unit keeps its timeline on the leader's
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClockSyncParams {
+    pub interval_ms:    Option<i64>,    // time between round trips to the leader
+    pub slew_ms_per_s:  Option<f64>,    // most the timeline is sped up or slowed down by while correcting
+    pub step_ms:        Option<i64>,    // offsets this large are stepped rather than slewed
+    pub report_ms:      Option<i64>,    // time between offset and jitter reports
+}
+
+// What happens to loaded audio while the soundscape sleeps
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SleepMode {
+    Pause,      // keep sources loaded and paused
+    Release,    // drop sources and their decoded audio
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WakeMode {
+    Restart,    // start again from the playlist's first scene
+    Resume,     // carry on with the scene which was playing
+}
+
+// Going to sleep and waking up with the schedule
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct SleepParams {
+    pub fade_ms:        Option<f32>,    // replaces each source's own fade when sleeping and waking
+    pub closing_scene:  Option<String>, // played through before sleeping
+    pub opening_scene:  Option<String>, // played through on waking
+    pub resources:      Option<SleepMode>,
+    pub wake:           Option<WakeMode>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaylistMode {
+    Sequential,
+    Shuffle,
+    Weighted,
+    TimeOfDay,
+}
+
+// Scenes played from a time of day until the start of the next slot
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlaylistSlot {
+    pub start:  String,
+    pub scenes: Vec<String>,    // entries from the scenes list
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlaylistParams {
+    pub mode:       PlaylistMode,
+    pub weights:    Option<Vec<f32>>,   // one per scene, for weighted mode
+    pub slots:      Option<Vec<PlaylistSlot>>,
+    pub seed:       Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct LimiterParams {
+    pub threshold_db:   f32,
+    pub release_ms:     f32,
+    pub lookahead_ms:   Option<f32>,
+    pub linked:         Option<bool>,
+    pub soft_clip:      Option<bool>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct OutputParams {
+    pub device:         Option<String>,
+    pub sample_rate:    Option<u32>,
+    pub channels:       Option<u16>,
+    pub buffer_frames:  Option<u32>, // not supported by the audio backend, rejected when set
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct MeteringParams {
+    pub rate_ms:        u32,
+    pub subscribers:    Vec<Address>,
+    pub sources:        Option<bool>,
+    pub silence_db:     Option<f32>,
+    pub silence_ms:     Option<u32>,
+    pub clip_db:        Option<f32>,
+}
+
+// Who may send which OSC messages. Allowlists hold addresses such as 10.0.0.5 or subnets such as
+// 192.168.1.0/24, a group without an allowlist accepts any source.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct OscAccessParams {
+    pub control:        Option<Vec<String>>,    // scene changes and volume
+    pub sync:           Option<Vec<String>>,    // election, heartbeats, clock sync and state snapshots
+    pub query:          Option<Vec<String>>,    // state requests
+    pub input:          Option<Vec<String>>,    // input values and background refreshes, never signed
+    pub secret:         Option<String>,         // control and sync messages must be signed with this key
+    pub rate_limit:     Option<f64>,            // control, query and input messages per second from each source
+    pub replay_window_ms: Option<i64>,          // how far a signed message's send time can be from the wall clock
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputMode {
+    Replace,
+    Add,
+    Multiply,
+}
+
+// An external control value received as /input/<name>, applied to a scene curve
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputParams {
+    pub name:           String,
+    pub curve:          Option<String>,     // defaults to the scene's first curve
+    pub mode:           InputMode,
+    pub range:          Option<[f32; 2]>,   // received values are clamped to this range
+    pub smoothing_ms:   Option<f32>,
+    pub timeout_ms:     Option<u32>,        // the curve is used alone after this long without input
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Soundscape {
     pub listen_addr:            Address,
     pub subscribers:            Vec<Address>,
+    pub osc_access:             Option<OscAccessParams>,
     pub scenes:                 Vec<String>,
     pub metro_step_ms:          u32,
     // pub structure_duration_ms:  usize,
@@ -95,8 +621,48 @@ pub struct Soundscape {
     pub background_scene:       Option<String>,
     pub speaker_positions:      Speakers,
     pub ignore_extra_speakers:  Option<bool>,
-    pub is_fallback_slave:      Option<bool>,
+    pub is_fallback_slave:      Option<bool>, // without an election section, a fallback slave has the lower priority
+    pub election:               Option<ElectionParams>,
+    pub clock_sync:             Option<ClockSyncParams>,
+    pub timezone:               Option<String>, // IANA zone such as Australia/Sydney, the system's zone if not set
     pub daily_schedule:         Option<DailySchedule>,
+    pub schedule:               Option<ScheduleParams>,
+    pub limiter:                Option<LimiterParams>,
+    pub metering:               Option<MeteringParams>,
+    pub output:                 Option<OutputParams>,
+    pub inputs:                 Option<Vec<InputParams>>,
+    pub playlist:               Option<PlaylistParams>,
+    pub sleep:                  Option<SleepParams>,
+}
+
+// A unit listening locally with no scenes and every optional section left out, for tests to
+// build configs from field by field
+#[cfg(test)]
+pub fn test_soundscape() -> Soundscape {
+    Soundscape {
+        listen_addr:            Address { host: "127.0.0.1".to_string(), port: 4000 },
+        subscribers:            vec![],
+        osc_access:             None,
+        scenes:                 vec![],
+        metro_step_ms:          10,
+        voice_limit:            16,
+        default_level:          1.0,
+        background_scene:       None,
+        speaker_positions:      Speakers { positions: vec![] },
+        ignore_extra_speakers:  None,
+        is_fallback_slave:      None,
+        election:               None,
+        clock_sync:             None,
+        timezone:               None,
+        daily_schedule:         None,
+        schedule:               None,
+        limiter:                None,
+        metering:               None,
+        output:                 None,
+        inputs:                 None,
+        playlist:               None,
+        sleep:                  None,
+    }
 }
 
 pub fn load_from_file(file_name: &String) -> Result<Soundscape, String> {
@@ -109,7 +675,10 @@ pub fn load_from_file(file_name: &String) -> Result<Soundscape, String> {
     match config_file.read_to_string(&mut config_contents) {
         Ok (_) => {
             match serde_yaml::from_str(&config_contents) {
-                Ok (config) => Ok(config),
+                Ok (config) => match check_output(&config) {
+                    Ok (_) => Ok(config),
+                    Err (e) => Err( format!("Error in config from '{}': {}", file_name, e) ),
+                },
                 Err (e) => Err( format!("Error parsing config from '{}': {}", file_name, e) ),
             }
         },
@@ -117,133 +686,123 @@ pub fn load_from_file(file_name: &String) -> Result<Soundscape, String> {
     }
 }
 
+// The audio backend can't choose a buffer size, so asking for one is an error rather than ignored
+pub fn check_output(config: &Soundscape) -> Result<(), String> {
+    match config.output.as_ref().and_then(|params| params.buffer_frames) {
+        Some (frames) => Err(format!(
+            "output.buffer_frames ({}) isn't supported, the output always uses the device's default buffer size. Remove it to continue.",
+            frames
+        )),
+        None => Ok(()),
+    }
+}
+
+// When the file was last changed, None if it can't be read
+pub fn modified_time(file_name: &String) -> Option<SystemTime> {
+    ::std::fs::metadata(file_name).and_then(|m| m.modified()).ok()
+}
+
 pub fn to_b_spline(params: &BSplineParams) -> bspline::BSpline<f32> {
     let points = params.points.to_owned();
     let knots = params.knots.to_owned();
     bspline::BSpline::new(params.degree, points, knots)
 }
 
-pub fn res_to_file(resource: &String) -> Result<File, String> {
-    match File::open(resource) {
-        Ok (file) => Ok(file),
-        Err (e) => return Err( format!("Error opening audio file '{}': {}", resource, e) ),
+// Settings for the master bus limiter, falls back to the limiter defaults when not configured
+pub fn to_limiter_settings(config: &Soundscape) -> LimiterSettings {
+    let defaults = LimiterSettings::default();
+    match config.limiter {
+        Some (ref params) => LimiterSettings {
+            threshold_db:   params.threshold_db,
+            release_ms:     params.release_ms,
+            lookahead_ms:   params.lookahead_ms.unwrap_or(defaults.lookahead_ms),
+            linked:         params.linked.unwrap_or(defaults.linked),
+            soft_clip:      params.soft_clip.unwrap_or(defaults.soft_clip),
+        },
+        None => defaults,
     }
 }
 
-pub fn is_fallback_slave(config :&Soundscape) -> bool {
-    match config.is_fallback_slave {
-        Some (is_slave) => is_slave,
-        None            => false,
+// Output device and format, the default device and format are used for anything not configured
+pub fn to_output_settings(config: &Soundscape) -> OutputSettings {
+    match config.output {
+        Some (ref params) => OutputSettings {
+            device:         params.device.clone(),
+            sample_rate:    params.sample_rate,
+            channels:       params.channels,
+        },
+        None => OutputSettings { device: None, sample_rate: None, channels: None },
     }
 }
 
-pub fn ignore_extra_speakers(config :&Soundscape) -> bool {
-    match config.ignore_extra_speakers {
-        Some (is_ignored)   => is_ignored,
-        None                => false,
+pub fn to_fade_shape(curve: &FadeCurve) -> FadeShape {
+    match *curve {
+        FadeCurve::Linear       => FadeShape::Linear,
+        FadeCurve::Exponential  => FadeShape::Exponential,
+        FadeCurve::EqualPower   => FadeShape::EqualPower,
+        FadeCurve::SCurve       => FadeShape::SCurve,
     }
 }
 
-// convert a NaiveTime to the next epoch seconds occourence
-pub fn next_epoch(from :&epochsy::DateTime, clock_time :&epochsy::DateTime) -> epochsy::DateTime {
-    let cur_days = epochsy::floor_to_days(from);
-    // let from_clock_time = epochsy::reduce(from, &cur_days);
-    let from_clock_time = epochsy::hms(
-            epochsy::hours(from) % 24,
-            epochsy::minutes(from) % 60,
-            moment(from) % 60
-        );
-    // force later to time to be later than from
-    let time = match from_clock_time.moment <= clock_time.moment {
-        true => epochsy::append(&cur_days, clock_time),
-        false => epochsy::append(&epochsy::days_later(&cur_days, 1), clock_time)
-    };
-    epochsy::with_timezone(&time, 0)
-}
-
-// returns the next epoch in seconds when we should start
-pub fn next_start_time(config: &Soundscape, from: &epochsy::DateTime) -> epochsy::DateTime {
-    match config.daily_schedule {
-        Some (ref schedule) => {
-            let time = NaiveTime::parse_from_str(schedule.start.as_str(), "%H:%M:%S")
-                .expect("Unable to use provided time.");
-            next_epoch(
-                from,
-                &epochsy::hms(time.hour() as u64, time.minute() as u64, time.second() as u64)
-            )
-        },
-        None => epochsy::hms(0, 0, 0),
-    }
-}
-
-// Returns the next end time of the provided schedule, returns None if no schedule is defined in config
-pub fn next_end_time(config: &Soundscape, from: &epochsy::DateTime) -> Option<epochsy::DateTime> {
-    match config.daily_schedule {
-        Some ( ref schedule) => {
-            let time = NaiveTime::parse_from_str(schedule.end.as_str(), "%H:%M:%S")
-                .expect("Unable to use provided time.");
-            Some (
-                next_epoch(
-                    from,
-                    &epochsy::hms(time.hour() as u64, time.minute() as u64, time.second() as u64)
-                )
-            )
-        },
-        None => None
-    }
-}
+// Fade durations default to 500 metronome steps, fade_*_steps are kept for older scene files
+const DEFAULT_FADE_STEPS: u32 = 500;
 
-pub fn is_in_schedule(now :&epochsy::DateTime, start: &epochsy::DateTime, end :&epochsy::DateTime) -> bool {
-    if moment(start) <= moment(now) && moment(now) <= moment(end) {
-        true
-    }
-    else {
-        false
+pub fn fade_in_ms(res: &SoundResource, metro_step_ms: u32) -> f32 {
+    match (res.fade_in_ms, res.fade_in_steps) {
+        (Some (ms), _)          => ms,
+        (None, Some (steps))    => (steps * metro_step_ms) as f32,
+        (None, None)            => (DEFAULT_FADE_STEPS * metro_step_ms) as f32,
     }
 }
 
-// Checks to see if we are in a scheduled duration now.
-// Returns true always if no schedule is defined
-pub fn is_in_schedule_now(config: &Soundscape, now: &epochsy::DateTime) -> bool {
-    let start = next_start_time(config, &local_today());
-    let end = match next_end_time(config, &start) {
-    //let end = match next_end_time(config, now) {
-        Some (end) => end,
-        // return true on None because there is no schedule
-        None => return true,
-    };
-    // assert!(moment(&start) <= moment(&end));
-    if moment(now) >= moment(&start) && moment(now) <= moment(&end) {
-    //if moment(&start) > moment(&end) {
-        true
-    }
-    else {
-        false
+pub fn fade_out_ms(res: &SoundResource, metro_step_ms: u32) -> f32 {
+    match (res.fade_out_ms, res.fade_out_steps) {
+        (Some (ms), _)          => ms,
+        (None, Some (steps))    => (steps * metro_step_ms) as f32,
+        (None, None)            => (DEFAULT_FADE_STEPS * metro_step_ms) as f32,
     }
 }
 
-pub fn local_time_zone() -> i32 {
-    Local::now().offset().fix().local_minus_utc() as i32
+// Files a resource chooses between, path is always the first
+pub fn variant_paths(res: &SoundResource) -> Vec<String> {
+    let mut paths = vec![res.path.clone()];
+    if let Some (ref variants) = res.variants {
+        paths.extend(variants.iter().map(|v| v.path.clone()));
+    }
+    paths
 }
 
-pub fn to_localtime(utc: &epochsy::DateTime) -> epochsy::DateTime {
-    // let utc = epochsy::now();
-    let tz = local_time_zone();
-    epochsy::with_timezone(utc, tz)
+pub fn variant_weights(res: &SoundResource) -> Vec<f32> {
+    let mut weights = vec![res.weight.unwrap_or(1.0)];
+    if let Some (ref variants) = res.variants {
+        weights.extend(variants.iter().map(|v| v.weight.unwrap_or(1.0)));
+    }
+    weights
 }
 
-pub fn localtime() -> epochsy::DateTime {
-    to_localtime(&epochsy::now())
+pub fn res_to_file(resource: &String) -> Result<File, String> {
+    match File::open(resource) {
+        Ok (file) => Ok(file),
+        Err (e) => return Err( format!("Error opening audio file '{}': {}", resource, e) ),
+    }
 }
 
-pub fn from_localtime(local: &epochsy::DateTime) -> epochsy::DateTime {
-    epochsy::with_timezone(local, 0)
+pub fn is_fallback_slave(config :&Soundscape) -> bool {
+    match config.is_fallback_slave {
+        Some (is_slave) => is_slave,
+        None            => false,
+    }
 }
 
-pub fn from_timestamp(instant: i64) -> DateTime<Utc> {
-    Utc.timestamp(instant, 0)
+pub fn ignore_extra_speakers(config :&Soundscape) -> bool {
+    match config.ignore_extra_speakers {
+        Some (is_ignored)   => is_ignored,
+        None                => false,
+    }
 }
 
-pub fn local_today() -> epochsy::DateTime {
-    to_localtime(&epochsy::today())
+pub fn parse_time_of_day(time: &String) -> Result<u32, String> {
+    NaiveTime::parse_from_str(time.as_str(), "%H:%M:%S")
+        .map(|t| t.num_seconds_from_midnight())
+        .map_err(|e| format!("Unable to use time '{}': {}", time, e))
 }
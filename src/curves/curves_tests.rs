@@ -0,0 +1,126 @@
+#[cfg(test)]
+mod curves_test {
+    use curves::*;
+    use config::{CurveOperator, CurveParams, Interpolation, Scene, SceneCurve, StructureParams, Waveform};
+
+    fn breakpoints(interpolation: Interpolation) -> Box<dyn Curve> {
+        from_params(&CurveParams::Breakpoints {
+            points:         vec![ [0.0, 1.0], [1000.0, 3.0], [2000.0, 2.0] ],
+            interpolation:  Some (interpolation),
+        }, 2000.0, &[])
+    }
+
+    #[test]
+    fn breakpoints_interpolate() {
+        let linear = breakpoints(Interpolation::Linear);
+        assert_eq!(linear.value_at(0.0), 1.0);
+        assert_eq!(linear.value_at(500.0), 2.0);
+        assert_eq!(linear.value_at(1500.0), 2.5);
+        // values are held beyond the last point
+        assert_eq!(linear.value_at(5000.0), 2.0);
+
+        let step = breakpoints(Interpolation::Step);
+        assert_eq!(step.value_at(500.0), 1.0);
+        assert_eq!(step.value_at(1000.0), 3.0);
+    }
+
+    #[test]
+    fn lfo_waveforms() {
+        let lfo = |waveform, phase| from_params(&CurveParams::Lfo {
+            waveform:   waveform,
+            period_ms:  1000.0,
+            phase:      Some (phase),
+            min:        2.0,
+            max:        4.0,
+        }, 1000.0, &[]);
+
+        let triangle = lfo(Waveform::Triangle, 0.0);
+        assert_eq!(triangle.value_at(0.0), 2.0);
+        assert_eq!(triangle.value_at(500.0), 4.0);
+        assert_eq!(triangle.value_at(1000.0), 2.0);
+
+        let saw = lfo(Waveform::Saw, 0.5);
+        assert_eq!(saw.value_at(0.0), 3.0);
+
+        let sine = lfo(Waveform::Sine, 0.25);
+        assert!((sine.value_at(0.0) - 4.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn random_walks_are_seeded() {
+        let walk = |seed| from_params(&CurveParams::RandomWalk {
+            seed:       seed,
+            step_ms:    100.0,
+            max_step:   0.5,
+            min:        0.0,
+            max:        2.0,
+        }, 10_000.0, &[]);
+
+        let a = walk(7);
+        let b = walk(7);
+        let c = walk(8);
+        let mut differs = false;
+        for i in 0..100 {
+            let t = i as f32 * 100.0 + 37.0;
+            assert_eq!(a.value_at(t), b.value_at(t));
+            assert!(a.value_at(t) >= 0.0 && a.value_at(t) <= 2.0);
+            differs = differs || a.value_at(t) != c.value_at(t);
+        }
+        assert!(differs);
+    }
+
+    #[test]
+    fn expressions_combine_terms() {
+        let constant = |value| CurveParams::Constant { value: value };
+        let expression = |op| from_params(&CurveParams::Expression {
+            op:     op,
+            terms:  vec![ constant(2.0), constant(3.0), constant(-1.0) ],
+        }, 1000.0, &[]);
+
+        assert_eq!(expression(CurveOperator::Sum).value_at(0.0), 4.0);
+        assert_eq!(expression(CurveOperator::Product).value_at(0.0), -6.0);
+        assert_eq!(expression(CurveOperator::Min).value_at(0.0), -1.0);
+        assert_eq!(expression(CurveOperator::Max).value_at(0.0), 3.0);
+    }
+
+    #[test]
+    fn references_to_scene_curves() {
+        let curve = |params| Some (StructureParams::Curve(params));
+        let scene = Scene {
+            name:               "references".to_string(),
+            duration_ms:        4000,
+            cycle_duration_ms:  4000,
+            resources:          vec![],
+            structure:          curve(CurveParams::Breakpoints { points: vec![ [0.0, 0.0], [4000.0, 4.0] ], interpolation: None }),
+            curves:             Some (vec![
+                SceneCurve {
+                    name:           "swell".to_string(),
+                    duration_ms:    Some (1000),
+                    curve:          StructureParams::Curve(CurveParams::Breakpoints { points: vec![ [0.0, 1.0], [1000.0, 2.0] ], interpolation: None }),
+                },
+                SceneCurve {
+                    name:           "density".to_string(),
+                    duration_ms:    None,
+                    curve:          StructureParams::Curve(CurveParams::Expression {
+                        op:     CurveOperator::Product,
+                        terms:  vec![ CurveParams::Reference { name: "structure".to_string() }, CurveParams::Reference { name: "swell".to_string() } ],
+                    }),
+                },
+            ]),
+            events:             None,
+            bpm:                None,
+            beats_per_bar:      None,
+            quantize:           None,
+            transition:         None,
+        };
+
+        let named = scene_curves(&scene);
+        assert_eq!(named.iter().map(|c| c.name.as_str()).collect::<Vec<&str>>(), vec!["structure", "swell", "density"]);
+        let density = from_structure(named[2].params, named[2].duration_ms, &named);
+        assert_eq!(density.value_at(1000.0), 1.0);
+        // The swell repeats every second through the structure's cycle
+        assert_eq!(density.value_at(2500.0), 2.5 * 1.5);
+
+        assert_eq!(from_params(&CurveParams::Reference { name: "missing".to_string() }, 4000.0, &named).value_at(0.0), 0.0);
+    }
+}
@@ -0,0 +1,248 @@
+use bspline;
+use std::f32;
+
+use config;
+use config::{CurveOperator, CurveParams, Interpolation, Scene, StructureParams, Waveform};
+use rng;
+
+mod curves_tests;
+
+// Evaluates a curve at a position, in milliseconds, within a structure's cycle
+pub trait Curve {
+    fn value_at(&self, position_ms: f32) -> f32;
+}
+
+pub struct BSplineCurve {
+    spline: bspline::BSpline<f32>,
+    step_t: f32,
+}
+
+impl Curve for BSplineCurve {
+    fn value_at(&self, position_ms: f32) -> f32 {
+        self.spline.point(self.step_t * position_ms)
+    }
+}
+
+// Spreads the spline's knot domain over the duration of the cycle
+fn b_spline_curve(spline: bspline::BSpline<f32>, duration_ms: f32) -> BSplineCurve {
+    let step_t = spline.knot_domain().1 / duration_ms;
+    BSplineCurve {
+        spline: spline,
+        step_t: step_t,
+    }
+}
+
+pub struct Breakpoints {
+    points:         Vec<[f32; 2]>,
+    interpolation:  Interpolation,
+}
+
+impl Curve for Breakpoints {
+    fn value_at(&self, position_ms: f32) -> f32 {
+        let first = match self.points.first() {
+            Some (point) => point,
+            None => return 0.0,
+        };
+        if position_ms <= first[0] {
+            return first[1];
+        }
+
+        for pair in self.points.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            if position_ms < to[0] {
+                return match self.interpolation {
+                    Interpolation::Step     => from[1],
+                    Interpolation::Linear   => {
+                        let t = (position_ms - from[0]) / (to[0] - from[0]);
+                        from[1] + (to[1] - from[1]) * t
+                    },
+                };
+            }
+        }
+        // Hold the last value
+        self.points[self.points.len() - 1][1]
+    }
+}
+
+pub struct Lfo {
+    waveform:   Waveform,
+    period_ms:  f32,
+    phase:      f32,
+    min:        f32,
+    max:        f32,
+}
+
+impl Curve for Lfo {
+    fn value_at(&self, position_ms: f32) -> f32 {
+        let cycle = position_ms / self.period_ms + self.phase;
+        let x = cycle - cycle.floor();
+        // Unipolar, 0 to 1
+        let unit = match self.waveform {
+            Waveform::Sine      => 0.5 + 0.5 * (x * 2.0 * f32::consts::PI).sin(),
+            Waveform::Triangle  => 1.0 - (2.0 * x - 1.0).abs(),
+            Waveform::Saw       => x,
+        };
+        self.min + (self.max - self.min) * unit
+    }
+}
+
+// A walk generated once from its seed, so it is the same every cycle and on every unit
+pub struct RandomWalk {
+    step_ms:    f32,
+    points:     Vec<f32>,
+}
+
+impl Curve for RandomWalk {
+    fn value_at(&self, position_ms: f32) -> f32 {
+        let position = (position_ms / self.step_ms).max(0.0);
+        let index = position.floor() as usize;
+        if index + 1 >= self.points.len() {
+            return self.points[self.points.len() - 1];
+        }
+        // Smooth step between points so the walk has no corners
+        let t = position - position.floor();
+        let t = t * t * (3.0 - 2.0 * t);
+        self.points[index] + (self.points[index + 1] - self.points[index]) * t
+    }
+}
+
+fn random_walk(seed: u64, step_ms: f32, max_step: f32, min: f32, max: f32, duration_ms: f32) -> RandomWalk {
+    let step_ms = step_ms.max(1.0);
+    let count = (duration_ms / step_ms).ceil() as usize + 2;
+    let mut rng = rng::seeded(seed);
+    let mut value = rng::range(&mut rng, min, max);
+    let mut points = Vec::with_capacity(count);
+    for _ in 0..count {
+        points.push(value);
+        value = (value + rng::range(&mut rng, -max_step, max_step)).max(min).min(max);
+    }
+    RandomWalk {
+        step_ms: step_ms,
+        points: points,
+    }
+}
+
+pub struct Constant {
+    value: f32,
+}
+
+impl Curve for Constant {
+    fn value_at(&self, _position_ms: f32) -> f32 {
+        self.value
+    }
+}
+
+pub struct Expression {
+    op:     CurveOperator,
+    terms:  Vec<Box<dyn Curve>>,
+}
+
+impl Curve for Expression {
+    fn value_at(&self, position_ms: f32) -> f32 {
+        let mut values = self.terms.iter().map(|term| term.value_at(position_ms));
+        let first = match values.next() {
+            Some (value) => value,
+            None => return 0.0,
+        };
+        values.fold(first, |acc, value| match self.op {
+            CurveOperator::Sum      => acc + value,
+            CurveOperator::Product  => acc * value,
+            CurveOperator::Min      => acc.min(value),
+            CurveOperator::Max      => acc.max(value),
+        })
+    }
+}
+
+// Another of the scene's curves, which runs over its own duration from the start of the cycle
+pub struct Reference {
+    curve:          Box<dyn Curve>,
+    duration_ms:    f32,
+}
+
+impl Curve for Reference {
+    fn value_at(&self, position_ms: f32) -> f32 {
+        match self.duration_ms > 0.0 {
+            true    => self.curve.value_at(position_ms % self.duration_ms),
+            false   => self.curve.value_at(position_ms),
+        }
+    }
+}
+
+// A curve declared by a scene, which other curves can refer to by name
+#[derive(Clone)]
+pub struct NamedCurve<'a> {
+    pub name:           String,
+    pub params:         &'a StructureParams,
+    pub duration_ms:    f32,
+}
+
+// The curves declared by a scene, in the order given by config::scene_curve_names
+pub fn scene_curves(scene: &Scene) -> Vec<NamedCurve> {
+    let mut named = Vec::new();
+    if let Some (ref params) = scene.structure {
+        named.push(NamedCurve { name: config::DEFAULT_CURVE.to_string(), params: params, duration_ms: scene.cycle_duration_ms as f32 });
+    }
+    if let Some (ref scene_curves) = scene.curves {
+        for scene_curve in scene_curves {
+            let duration_ms = scene_curve.duration_ms.unwrap_or(scene.cycle_duration_ms);
+            named.push(NamedCurve { name: scene_curve.name.clone(), params: &scene_curve.curve, duration_ms: duration_ms as f32 });
+        }
+    }
+    named
+}
+
+pub fn from_structure(params: &StructureParams, duration_ms: f32, named: &[NamedCurve]) -> Box<dyn Curve> {
+    match *params {
+        StructureParams::Curve(ref curve)   => from_params(curve, duration_ms, named),
+        StructureParams::BSpline(ref spline) => Box::new(b_spline_curve(config::to_b_spline(spline), duration_ms)),
+    }
+}
+
+// References are looked up in named, which config::check_curves has checked
+pub fn from_params(params: &CurveParams, duration_ms: f32, named: &[NamedCurve]) -> Box<dyn Curve> {
+    match *params {
+        CurveParams::BSpline { ref points, ref knots, degree } => {
+            let spline = bspline::BSpline::new(degree, points.to_owned(), knots.to_owned());
+            Box::new(b_spline_curve(spline, duration_ms))
+        },
+        CurveParams::Breakpoints { ref points, interpolation } => {
+            let mut points = points.to_owned();
+            points.sort_by(|a, b| a[0].partial_cmp(&b[0]).unwrap_or(::std::cmp::Ordering::Equal));
+            Box::new(Breakpoints {
+                points:         points,
+                interpolation:  interpolation.unwrap_or(Interpolation::Linear),
+            })
+        },
+        CurveParams::Lfo { waveform, period_ms, phase, min, max } => Box::new(Lfo {
+            waveform:   waveform,
+            period_ms:  period_ms.max(1.0),
+            phase:      phase.unwrap_or(0.0),
+            min:        min,
+            max:        max,
+        }),
+        CurveParams::RandomWalk { seed, step_ms, max_step, min, max } => Box::new(random_walk(seed, step_ms, max_step, min, max, duration_ms)),
+        CurveParams::Constant { value } => Box::new(Constant { value: value }),
+        CurveParams::Expression { op, ref terms } => Box::new(Expression {
+            op:     op,
+            terms:  terms.iter().map(|term| from_params(term, duration_ms, named)).collect(),
+        }),
+        CurveParams::Reference { ref name } => match named.iter().position(|curve| &curve.name == name) {
+            Some (index) => {
+                // The curve can't see itself, so a circular reference can't recurse forever
+                let others: Vec<NamedCurve> = named.iter().enumerate()
+                    .filter(|&(i, _)| i != index)
+                    .map(|(_, curve)| curve.clone())
+                    .collect();
+                let curve = &named[index];
+                Box::new(Reference {
+                    curve:          from_structure(curve.params, curve.duration_ms, &others),
+                    duration_ms:    curve.duration_ms,
+                })
+            },
+            None => {
+                println!("Curve '{}' is not defined, using a value of 0", name);
+                Box::new(Constant { value: 0.0 })
+            },
+        },
+    }
+}
@@ -0,0 +1,132 @@
+#[cfg(test)]
+mod election_test {
+    use election::*;
+
+    // Tick every online unit and deliver what they send, and any replies, to the other online units
+    fn step(units: &mut Vec<Election>, online: &Vec<bool>) {
+        let mut outbox = Vec::new();
+        for (i, unit) in units.iter_mut().enumerate() {
+            if online[i] {
+                if let Some (message) = tick(unit, 10) {
+                    outbox.push((i, message));
+                }
+            }
+        }
+
+        let mut delivered = 0;
+        while let Some ((from, message)) = outbox.pop() {
+            delivered += 1;
+            assert!(delivered < 100, "Units are replying to each other without end");
+            for (i, unit) in units.iter_mut().enumerate() {
+                if i == from || !online[i] {
+                    continue
+                }
+                let reply = match message {
+                    Message::Heartbeat { term, priority, unit_id } => {
+                        receive_heartbeat(unit, term, priority, unit_id);
+                        None
+                    },
+                    Message::Candidate { term, priority, unit_id } => receive_candidate(unit, term, priority, unit_id),
+                };
+                if let Some (reply) = reply {
+                    outbox.push((i, reply));
+                }
+            }
+        }
+    }
+
+    // Run for a while, never allowing more than one leader among the online units
+    fn run(units: &mut Vec<Election>, online: &Vec<bool>, ms: i64) {
+        for _ in 0..(ms / 10) {
+            step(units, online);
+            let leaders = units.iter().enumerate().filter(|&(i, u)| online[i] && is_leader(u)).count();
+            assert!(leaders <= 1, "{} units are leading", leaders);
+        }
+    }
+
+    fn leader(units: &Vec<Election>, online: &Vec<bool>) -> Option<u32> {
+        units.iter().enumerate()
+            .find(|&(i, u)| online[i] && is_leader(u))
+            .map(|(_, u)| u.unit_id)
+    }
+
+    fn units() -> Vec<Election> {
+        vec![new(1, 1, 1000, 250, 5000), new(2, 1, 1000, 250, 5000), new(3, 2, 1000, 250, 5000)]
+    }
+
+    #[test]
+    fn highest_rank_leads() {
+        let mut units = units();
+        let online = vec![true, true, true];
+        run(&mut units, &online, 3000);
+        assert_eq!(leader(&units, &online), Some (3));
+        assert!(units.iter().all(|u| u.term == units[2].term));
+        assert!(is_leader_alive(&units[0]) && is_leader_alive(&units[1]));
+        assert!(is_autonomous(&units[2]));
+    }
+
+    #[test]
+    fn failover_and_failback() {
+        let mut units = units();
+        let mut online = vec![true, true, true];
+        run(&mut units, &online, 3000);
+
+        // The leader drops out, the next in rank takes over
+        online[2] = false;
+        run(&mut units, &online, 2000);
+        assert_eq!(leader(&units, &online), Some (2));
+
+        // It comes back following, then takes the lead back once settled
+        online[2] = true;
+        units[2] = new(3, 2, 1000, 250, 5000);
+        run(&mut units, &online, 3000);
+        assert_eq!(leader(&units, &online), Some (2));
+        assert_eq!(units[2].leader, Some ((1, 2)));
+        run(&mut units, &online, 5000);
+        assert_eq!(leader(&units, &online), Some (3));
+        assert!(units.iter().all(|u| u.term == units[2].term));
+    }
+
+    #[test]
+    fn legacy_heartbeats_rank_lowest() {
+        // Followed by a unit with no other leader, in the current term
+        let mut unit = new(1, 1, 1000, 250, 5000);
+        unit.term = 4;
+        assert!(receive_legacy_heartbeat(&mut unit));
+        assert_eq!(unit.leader, Some (LEGACY_RANK));
+        assert_eq!(unit.term, 4);
+        assert!(is_leader_alive(&unit));
+
+        // Outranked once the unit takes the lead, and ignored from then on
+        let mut ms = 0;
+        while !is_leader(&unit) && ms < 10_000 {
+            receive_legacy_heartbeat(&mut unit);
+            tick(&mut unit, 100);
+            ms += 100;
+        }
+        assert!(is_leader(&unit));
+        assert!(!receive_legacy_heartbeat(&mut unit));
+        assert!(is_leader(&unit));
+
+        // Ignored while following a leader which takes part in elections
+        let mut follower = new(2, 1, 1000, 250, 5000);
+        assert!(receive_heartbeat(&mut follower, 5, 1, 1));
+        assert!(!receive_legacy_heartbeat(&mut follower));
+        assert_eq!(follower.leader, Some ((1, 1)));
+    }
+
+    #[test]
+    fn rival_leaders_resolve() {
+        // Leaders elected on either side of a network split
+        let mut units = vec![new(1, 1, 1000, 250, 5000), new(2, 1, 1000, 250, 5000)];
+        run(&mut units, &vec![true, false], 2000);
+        run(&mut units, &vec![false, true], 2000);
+        assert!(is_leader(&units[0]) && is_leader(&units[1]));
+
+        let online = vec![true, true];
+        step(&mut units, &online);
+        step(&mut units, &online);
+        assert_eq!(leader(&units, &online), Some (2));
+        assert!(!is_leader(&units[0]));
+    }
+}
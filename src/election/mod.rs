@@ -0,0 +1,251 @@
+use rosc::{OscMessage, OscPacket, OscType};
+
+use config;
+use config::Soundscape;
+use rng;
+
+mod election_tests;
+
+// Rank given to a unit without elections, which sends heartbeats with only its time
+pub const LEGACY_RANK: (u32, u32) = (0, 0);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Role {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+// Sent to every other unit
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Message {
+    Heartbeat { term: u64, priority: u32, unit_id: u32 },
+    Candidate { term: u64, priority: u32, unit_id: u32 },
+}
+
+// Election
+// Chooses one unit to lead. Each leadership has a term number, a unit which hasn't heard its
+// leader for a while starts a new term as a candidate and leads unless a higher ranked unit
+// objects. Ranks compare priority then unit id. A higher ranked unit which comes back follows
+// the current leader until it has settled in, then takes the lead back in a new term.
+pub struct Election {
+    pub unit_id:        u32,
+    pub priority:       u32,
+    pub role:           Role,
+    pub term:           u64,
+    pub leader:         Option<(u32, u32)>, // rank of the unit being followed
+    pub since_heard_ms: i64, // since the leader's last heartbeat, or deferring to a candidate
+    pub in_role_ms:     i64, // since the role or leader last changed
+    pub timeout_ms:     i64,
+    pub election_ms:    i64,
+    pub failback_ms:    i64,
+    pub is_legacy_heard: bool, // warned that a unit without elections is leading too
+}
+
+pub fn new(unit_id: u32, priority: u32, timeout_ms: i64, election_ms: i64, failback_ms: i64) -> Election {
+    Election {
+        unit_id:        unit_id,
+        priority:       priority,
+        role:           Role::Follower,
+        term:           0,
+        leader:         None,
+        // Listen for a leader before standing, so a returning unit joins as a follower
+        since_heard_ms: 0,
+        in_role_ms:     0,
+        timeout_ms:     timeout_ms,
+        election_ms:    election_ms,
+        failback_ms:    failback_ms,
+        is_legacy_heard: false,
+    }
+}
+
+// Without an election section a fallback slave ranks below a unit which isn't one,
+// a random id separates units with the same setting
+pub fn from_config(config: &Soundscape) -> Election {
+    match config.election {
+        Some (ref params) => new(
+            params.unit_id,
+            params.priority,
+            params.timeout_ms.unwrap_or(1000).max(1),
+            params.election_ms.unwrap_or(250).max(1),
+            params.failback_ms.unwrap_or(5000).max(0),
+        ),
+        None => {
+            let priority = if config::is_fallback_slave(config) { 0 } else { 1 };
+            new(rng::next_u64(&mut rng::from_time()) as u32, priority, 1000, 250, 5000)
+        },
+    }
+}
+
+fn rank(election: &Election) -> (u32, u32) {
+    (election.priority, election.unit_id)
+}
+
+pub fn is_leader(election: &Election) -> bool {
+    election.role == Role::Leader
+}
+
+// Following a leader which is alive, or waiting on a higher ranked candidate
+pub fn is_leader_alive(election: &Election) -> bool {
+    election.role == Role::Follower && election.since_heard_ms < election.timeout_ms
+}
+
+// Leading or without a leader to follow, so running the soundscape on its own
+pub fn is_autonomous(election: &Election) -> bool {
+    !is_leader_alive(election)
+}
+
+fn heartbeat(election: &Election) -> Message {
+    Message::Heartbeat { term: election.term, priority: election.priority, unit_id: election.unit_id }
+}
+
+fn candidacy(election: &Election) -> Message {
+    Message::Candidate { term: election.term, priority: election.priority, unit_id: election.unit_id }
+}
+
+fn set_role(election: &mut Election, role: Role) {
+    if election.role != role {
+        election.role = role;
+        election.in_role_ms = 0;
+    }
+}
+
+fn stand(election: &mut Election) -> Message {
+    election.term += 1;
+    election.leader = None;
+    set_role(election, Role::Candidate);
+    election.in_role_ms = 0;
+    println!("Unit {} standing for election in term {}.", election.unit_id, election.term);
+    candidacy(election)
+}
+
+fn follow(election: &mut Election, term: u64, leader: Option<(u32, u32)>) {
+    if election.role == Role::Leader {
+        println!("Unit {} stepping down as leader in term {}.", election.unit_id, term);
+    }
+    if leader.is_some() && election.leader != leader {
+        let (priority, unit_id) = leader.unwrap();
+        println!("Unit {} following unit {} (priority {}) in term {}.", election.unit_id, unit_id, priority, term);
+        election.in_role_ms = 0;
+    }
+    set_role(election, Role::Follower);
+    election.term = term;
+    election.leader = leader;
+    election.since_heard_ms = 0;
+}
+
+// Advance the election's timers, returning a message to send
+pub fn tick(election: &mut Election, step_ms: i64) -> Option<Message> {
+    election.since_heard_ms = election.since_heard_ms.saturating_add(step_ms);
+    election.in_role_ms = election.in_role_ms.saturating_add(step_ms);
+
+    match election.role {
+        Role::Follower => {
+            if election.since_heard_ms >= election.timeout_ms {
+                if election.leader.is_some() {
+                    println!("Unit {} lost its leader after {}ms.", election.unit_id, election.since_heard_ms);
+                }
+                Some(stand(election))
+            }
+            else {
+                match election.leader {
+                    Some (leader) if rank(election) > leader && election.in_role_ms >= election.failback_ms => {
+                        println!("Unit {} taking the lead back from unit {}.", election.unit_id, leader.1);
+                        Some(stand(election))
+                    },
+                    _ => None,
+                }
+            }
+        },
+        Role::Candidate => {
+            if election.in_role_ms >= election.election_ms {
+                set_role(election, Role::Leader);
+                println!("Unit {} leading in term {}.", election.unit_id, election.term);
+                Some(heartbeat(election))
+            }
+            else {
+                None
+            }
+        },
+        Role::Leader => Some(heartbeat(election)),
+    }
+}
+
+// A leader's heartbeat, returns true if this unit follows it and should take on its time
+pub fn receive_heartbeat(election: &mut Election, term: u64, priority: u32, unit_id: u32) -> bool {
+    if unit_id == election.unit_id || term < election.term {
+        // Our own heartbeat, or a stale leader which steps down when it hears the current one
+        return false
+    }
+    if term == election.term && election.role == Role::Leader && (priority, unit_id) < rank(election) {
+        // A rival from the same term steps down when it hears us
+        return false
+    }
+    follow(election, term, Some ((priority, unit_id)));
+    true
+}
+
+// A heartbeat from a unit without elections, returns true if this unit follows it and should
+// take on its time. It can't take part in elections, so it ranks lowest in the current term:
+// it is followed while there is no other leader, and any unit which stands outranks it. It
+// never steps down, so it is reported when this unit leads alongside it.
+pub fn receive_legacy_heartbeat(election: &mut Election) -> bool {
+    match election.role {
+        Role::Follower if election.leader.is_none() || election.leader == Some (LEGACY_RANK) || !is_leader_alive(election) => {
+            let term = election.term;
+            follow(election, term, Some (LEGACY_RANK));
+            true
+        },
+        Role::Leader => {
+            if !election.is_legacy_heard {
+                election.is_legacy_heard = true;
+                println!("Unit {} is leading alongside a unit without elections, which can't step down. Update or remove it.", election.unit_id);
+            }
+            false
+        },
+        _ => false,
+    }
+}
+
+// A candidate standing for election, returns a message to send in reply
+pub fn receive_candidate(election: &mut Election, term: u64, priority: u32, unit_id: u32) -> Option<Message> {
+    if unit_id == election.unit_id {
+        return None
+    }
+    if (priority, unit_id) > rank(election) {
+        // Defer to a higher ranked candidate in a current term, giving it time to win
+        if term >= election.term {
+            follow(election, term, None);
+        }
+        return match election.role {
+            Role::Leader => Some(heartbeat(election)),
+            _ => None,
+        }
+    }
+
+    // A lower ranked candidate is answered in a term at least as new as its own
+    election.term = election.term.max(term);
+    match election.role {
+        Role::Leader => Some(heartbeat(election)),
+        Role::Candidate => Some(candidacy(election)),
+        Role::Follower => match is_leader_alive(election) {
+            // The leader, or the higher ranked candidate being waited on, answers
+            true    => None,
+            false   => Some(stand(election)),
+        },
+    }
+}
+
+// Heartbeats carry the leader's timeline position for followers to take on
+pub fn to_packet(message: &Message, elapsed_ms: i64) -> OscPacket {
+    match *message {
+        Message::Heartbeat { term, priority, unit_id } => OscPacket::Message(OscMessage {
+            addr: "/MasterAlive".to_string(),
+            args: Some( vec![ OscType::Long(elapsed_ms), OscType::Long(term as i64), OscType::Int(priority as i32), OscType::Int(unit_id as i32) ] ),
+        }),
+        Message::Candidate { term, priority, unit_id } => OscPacket::Message(OscMessage {
+            addr: "/Election".to_string(),
+            args: Some( vec![ OscType::Long(term as i64), OscType::Int(priority as i32), OscType::Int(unit_id as i32) ] ),
+        }),
+    }
+}
@@ -0,0 +1,80 @@
+#[cfg(test)]
+mod events_test {
+    use events::*;
+    use config::EventParams;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn params() -> EventParams {
+        EventParams {
+            name:               "chirps".to_string(),
+            samples:            vec![],
+            rate_per_min:       None,
+            curve:              Some ("density".to_string()),
+            input:              Some ([1.0, 3.0]),
+            at_ms:              Some (vec![250, 20]),
+            region:             None,
+            gain_db:            None,
+            pitch_semitones:    None,
+            reverb:             None,
+            seed:               Some (1),
+        }
+    }
+
+    #[test]
+    fn timed_events() {
+        let mut event = from_params(&params()).unwrap();
+        let values = vec![ ("density".to_string(), 1.0) ];
+        assert_eq!(advance(&mut event, &values, 100), 1);
+        assert_eq!(advance(&mut event, &values, 100), 0);
+        assert_eq!(advance(&mut event, &values, 100), 1);
+        assert_eq!(advance(&mut event, &values, 100), 0);
+    }
+
+    #[test]
+    fn rate_follows_curve() {
+        let event = from_params(&params()).unwrap();
+        assert_eq!(density(&event, &vec![ ("density".to_string(), 0.0) ]), 0.0);
+        assert_eq!(density(&event, &vec![ ("density".to_string(), 2.0) ]), 0.5);
+        assert_eq!(density(&event, &vec![ ("density".to_string(), 5.0) ]), 1.0);
+
+        // Half the rate at half density
+        let mut params = params();
+        params.rate_per_min = Some (600.0);
+        params.at_ms = None;
+        let mut event = from_params(&params).unwrap();
+        let count: usize = (0..10_000).map(|_| advance(&mut event, &vec![ ("density".to_string(), 2.0) ], 10)).sum();
+        assert!(count > 400 && count < 600, "{}", count);
+    }
+
+    #[test]
+    fn poisson_counts() {
+        // An event per millisecond averages 10 in each 10ms step, several can fall in one step
+        let mut params = params();
+        params.rate_per_min = Some (60_000.0);
+        params.at_ms = None;
+        let mut event = from_params(&params).unwrap();
+        let values = vec![ ("density".to_string(), 3.0) ];
+        let counts: Vec<usize> = (0..1000).map(|_| advance(&mut event, &values, 10)).collect();
+        let total: usize = counts.iter().sum();
+        assert!(total > 9_700 && total < 10_300, "{}", total);
+        assert!(counts.iter().any(|&count| count > 15));
+
+        // A tenth of an event per step
+        params.rate_per_min = Some (600.0);
+        let mut event = from_params(&params).unwrap();
+        let total: usize = (0..10_000).map(|_| advance(&mut event, &values, 10)).sum();
+        assert!(total > 900 && total < 1_100, "{}", total);
+    }
+
+    #[test]
+    fn voice_limit() {
+        let voices = AtomicUsize::new(0);
+        assert!(claim_voice(&voices, 2));
+        assert!(claim_voice(&voices, 2));
+        assert!(!claim_voice(&voices, 2));
+        // A voice ending makes room
+        voices.fetch_sub(1, Ordering::SeqCst);
+        assert!(claim_voice(&voices, 2));
+        assert_eq!(voices.load(Ordering::SeqCst), 2);
+    }
+}
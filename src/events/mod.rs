@@ -0,0 +1,163 @@
+use rodio;
+use rodio::Source;
+use rodio::source::{Buffered, Done};
+
+use rodiox::diffusion_sink::DiffusionSink;
+use rodiox::master_bus::MasterBus;
+use rodiox::source::limiter::db_to_amplitude;
+
+use config;
+use config::{EventParams, Scene};
+use rng;
+use soundscape;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+mod events_tests;
+
+pub type EventSample = Buffered<rodio::Decoder<BufReader<File>>>;
+
+// One-shot event sounds
+// Each trigger plays a sample from the pool once on its own voice, which releases itself when
+// the sample ends. Voices playing are counted so triggers can be dropped at the voice limit.
+pub struct EventSource {
+    pub name:               String,
+    pub samples:            Vec<EventSample>,
+    pub rate_per_ms:        f32,
+    pub curve:              Option<String>,
+    pub input:              Option<[f32; 2]>,
+    pub at_ms:              Vec<i64>,
+    pub region:             [[f32; 3]; 2],
+    pub gain_db:            [f32; 2],
+    pub pitch_semitones:    [f32; 2],
+    pub reverb:             Option<(Duration, f32)>,
+    elapsed_ms:             i64,
+    rng:                    rng::Rng,
+}
+
+fn load_sample(path: &String) -> Result<EventSample, String> {
+    config::res_to_file(path).and_then(|file| {
+        match rodio::Decoder::new( BufReader::new(file) ) {
+            Ok (decoder) => Ok(decoder.buffered()),
+            Err (e) => Err( format!("Error creating audio source for '{}': {}", path, e) ),
+        }
+    })
+}
+
+pub fn from_params(params: &EventParams) -> Result<EventSource, String> {
+    let mut samples = Vec::with_capacity(params.samples.len());
+    for path in &params.samples {
+        samples.push(load_sample(path)?);
+    }
+
+    let mut at_ms = params.at_ms.clone().unwrap_or(Vec::new());
+    at_ms.sort();
+
+    Ok(EventSource {
+        name:               params.name.clone(),
+        samples:            samples,
+        rate_per_ms:        params.rate_per_min.unwrap_or(0.0).max(0.0) / 60_000.0,
+        curve:              params.curve.clone(),
+        input:              params.input,
+        at_ms:              at_ms,
+        region:             params.region.unwrap_or([[0.0, 1.0, 1.0], [0.0, 1.0, 1.0]]),
+        gain_db:            params.gain_db.unwrap_or([0.0, 0.0]),
+        pitch_semitones:    params.pitch_semitones.unwrap_or([0.0, 0.0]),
+        reverb:             params.reverb.as_ref().map(|r| (Duration::from_millis(r.delay_ms), r.mix_t)),
+        elapsed_ms:         0,
+        rng:                match params.seed {
+                                Some (seed) => rng::seeded(seed),
+                                None => rng::from_time(),
+                            },
+    })
+}
+
+pub fn from_scene(scene: &Scene) -> Vec<EventSource> {
+    let mut events = Vec::new();
+    if let Some (ref params) = scene.events {
+        for p in params {
+            match from_params(p) {
+                Ok (event) => events.push(event),
+                Err (e) => println!("Skipping event '{}': {}", p.name, e),
+            }
+        }
+    }
+    events
+}
+
+// Scale applied to the rate from the event's curve, 1 if it doesn't follow a curve
+pub fn density(event: &EventSource, curve_values: &Vec<(String, f32)>) -> f32 {
+    let value = match event.curve {
+        Some (ref curve) => soundscape::curve_value(curve_values, curve),
+        None => return 1.0,
+    };
+    match event.input {
+        Some (input) if input[1] != input[0] => ((value - input[0]) / (input[1] - input[0])).max(0.0).min(1.0),
+        Some (input) => if value >= input[1] { 1.0 } else { 0.0 },
+        None => value.max(0.0),
+    }
+}
+
+// Advance the event's clock, returning how many events are due in this step
+pub fn advance(event: &mut EventSource, curve_values: &Vec<(String, f32)>, step_ms: i64) -> usize {
+    let from = event.elapsed_ms;
+    event.elapsed_ms += step_ms;
+    let mut count = event.at_ms.iter().filter(|&&at| from <= at && at < event.elapsed_ms).count();
+
+    // Poisson arrivals in the step, counted by multiplying uniform draws until they fall to e^-λ
+    let rate = event.rate_per_ms * density(event, curve_values);
+    if rate > 0.0 {
+        let floor = (-(rate as f64) * step_ms as f64).exp();
+        let mut product = rng::next_f32(&mut event.rng) as f64;
+        while product > floor {
+            count += 1;
+            product *= rng::next_f32(&mut event.rng) as f64;
+        }
+    }
+    count
+}
+
+// Count a voice as playing if there is room for it under the limit
+pub fn claim_voice(voices: &AtomicUsize, voice_limit: usize) -> bool {
+    let mut playing = voices.load(Ordering::SeqCst);
+    loop {
+        if playing >= voice_limit {
+            return false
+        }
+        match voices.compare_exchange(playing, playing + 1, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok (_) => return true,
+            Err (current) => playing = current,
+        }
+    }
+}
+
+// Play one event sound from the pool on its own voice, unless voice_limit voices are playing
+pub fn trigger(event: &mut EventSource, master_bus: &MasterBus, speakers: &Vec<[f32; 3]>, level: f32, voices: &Arc<AtomicUsize>, voice_limit: usize) {
+    if event.samples.is_empty() || !claim_voice(voices, voice_limit) {
+        return
+    }
+    let index = (rng::next_u64(&mut event.rng) % event.samples.len() as u64) as usize;
+
+    let mut position = [0f32; 3];
+    for axis in 0..3 {
+        let (a, b) = (event.region[0][axis], event.region[1][axis]);
+        position[axis] = rng::range(&mut event.rng, a.min(b), a.max(b));
+    }
+    let gain_db = rng::range(&mut event.rng, event.gain_db[0], event.gain_db[1]);
+    let semitones = rng::range(&mut event.rng, event.pitch_semitones[0], event.pitch_semitones[1]);
+
+    let mut voice = DiffusionSink::new(master_bus, position, speakers.to_vec());
+    voice.set_volume(level * db_to_amplitude(gain_db));
+    voice.set_speed(2f32.powf(semitones / 12.0));
+    if let Some ((delay, mix)) = event.reverb {
+        voice.set_reverb(delay, mix);
+    }
+    // The voice stops counting once the sample ends
+    voice.append(Done::new(event.samples[index].clone(), voices.clone()));
+    // The voice is removed from the master bus once the sample ends
+    voice.detach();
+}
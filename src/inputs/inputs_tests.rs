@@ -0,0 +1,58 @@
+#[cfg(test)]
+mod inputs_test {
+    use inputs::*;
+    use config::{InputMode, InputParams};
+
+    fn input(mode: InputMode) -> Vec<ControlInput> {
+        vec![ from_params(&InputParams {
+            name:           "crowd".to_string(),
+            curve:          Some ("density".to_string()),
+            mode:           mode,
+            range:          Some ([0.0, 10.0]),
+            smoothing_ms:   None,
+            timeout_ms:     Some (1000),
+        }) ]
+    }
+
+    fn values() -> Vec<(String, f32)> {
+        vec![ ("structure".to_string(), 1.0), ("density".to_string(), 2.0) ]
+    }
+
+    fn applied(inputs: &Vec<ControlInput>) -> Vec<(String, f32)> {
+        let mut values = values();
+        apply(inputs, &mut values);
+        values
+    }
+
+    #[test]
+    fn input_modes() {
+        let mut replace = input(InputMode::Replace);
+        let mut add = input(InputMode::Add);
+        let mut multiply = input(InputMode::Multiply);
+        for inputs in vec![ &mut replace, &mut add, &mut multiply ] {
+            assert!(receive(inputs, "crowd", 3.0));
+            advance(inputs, 10);
+        }
+
+        assert_eq!(applied(&replace)[1].1, 3.0);
+        assert_eq!(applied(&add)[1].1, 5.0);
+        assert_eq!(applied(&multiply)[1].1, 6.0);
+        // other curves are left alone
+        assert_eq!(applied(&replace)[0].1, 1.0);
+    }
+
+    #[test]
+    fn inputs_are_clamped_and_time_out() {
+        let mut inputs = input(InputMode::Replace);
+        assert!(!receive(&mut inputs, "light", 3.0));
+        assert_eq!(applied(&inputs), values());
+
+        receive(&mut inputs, "crowd", 50.0);
+        advance(&mut inputs, 10);
+        assert_eq!(applied(&inputs)[1].1, 10.0);
+
+        advance(&mut inputs, 1000);
+        assert!(is_stale(&inputs[0]));
+        assert_eq!(applied(&inputs), values());
+    }
+}
@@ -0,0 +1,128 @@
+use config::{InputMode, InputParams, Soundscape};
+
+mod inputs_tests;
+
+// External control input
+// Values received over OSC are smoothed towards, and blended with the scene curve by a weight
+// which rises while input arrives and falls back to zero once it goes stale.
+pub struct ControlInput {
+    pub name:           String,
+    pub curve:          Option<String>,
+    pub mode:           InputMode,
+    pub range:          Option<[f32; 2]>,
+    pub smoothing_ms:   f32,
+    pub timeout_ms:     i64,
+    target:             f32,
+    value:              f32,
+    weight:             f32,
+    since_input_ms:     Option<i64>,
+}
+
+pub fn from_params(params: &InputParams) -> ControlInput {
+    ControlInput {
+        name:           params.name.clone(),
+        curve:          params.curve.clone(),
+        mode:           params.mode,
+        range:          params.range,
+        smoothing_ms:   params.smoothing_ms.unwrap_or(0.0).max(0.0),
+        timeout_ms:     params.timeout_ms.unwrap_or(5000) as i64,
+        target:         0.0,
+        value:          0.0,
+        weight:         0.0,
+        since_input_ms: None,
+    }
+}
+
+pub fn from_config(config: &Soundscape) -> Vec<ControlInput> {
+    match config.inputs {
+        Some (ref inputs) => inputs.iter().map(from_params).collect(),
+        None => Vec::new(),
+    }
+}
+
+// Record a value for the named input, returns false if no input has that name
+pub fn receive(inputs: &mut Vec<ControlInput>, name: &str, value: f32) -> bool {
+    match inputs.iter_mut().find(|input| input.name == name) {
+        Some (input) => {
+            let value = match input.range {
+                Some (range) => value.max(range[0]).min(range[1]),
+                None => value,
+            };
+            // Start from the new value rather than gliding from a stale one
+            if input.weight == 0.0 {
+                input.value = value;
+            }
+            input.target = value;
+            input.since_input_ms = Some(0);
+            true
+        },
+        None => false,
+    }
+}
+
+pub fn is_stale(input: &ControlInput) -> bool {
+    match input.since_input_ms {
+        Some (ms) => ms >= input.timeout_ms,
+        None => true,
+    }
+}
+
+// Inputs which are receiving values, with the latest value received
+pub fn overrides(inputs: &Vec<ControlInput>) -> Vec<(String, f32)> {
+    inputs.iter()
+        .filter(|input| !is_stale(input))
+        .map(|input| (input.name.clone(), input.target))
+        .collect()
+}
+
+// Move each input's value and weight towards their targets
+pub fn advance(inputs: &mut Vec<ControlInput>, step_ms: i64) {
+    for input in inputs {
+        if let Some (ref mut ms) = input.since_input_ms {
+            if *ms < input.timeout_ms {
+                *ms += step_ms;
+                if *ms >= input.timeout_ms {
+                    println!("Input '{}' has timed out, falling back to the scene curve.", input.name);
+                }
+            }
+        }
+
+        let weight_target = if is_stale(input) { 0.0 } else { 1.0 };
+        let k = match input.smoothing_ms > 0.0 {
+            true    => 1.0 - (-(step_ms as f32) / input.smoothing_ms).exp(),
+            false   => 1.0,
+        };
+        input.value += (input.target - input.value) * k;
+        input.weight += (weight_target - input.weight) * k;
+        if (input.weight - weight_target).abs() < 0.001 {
+            input.weight = weight_target;
+        }
+    }
+}
+
+fn blend(input: &ControlInput, curve_value: f32) -> f32 {
+    let w = input.weight;
+    match input.mode {
+        InputMode::Replace  => curve_value + (input.value - curve_value) * w,
+        InputMode::Add      => curve_value + input.value * w,
+        InputMode::Multiply => curve_value * (1.0 + (input.value - 1.0) * w),
+    }
+}
+
+// Apply the inputs to the curve values, in the order they are declared.
+// An input without a curve applies to the scene's first curve, an input naming a curve the
+// scene doesn't have is ignored.
+pub fn apply(inputs: &Vec<ControlInput>, values: &mut Vec<(String, f32)>) {
+    for input in inputs {
+        if input.weight == 0.0 {
+            continue
+        }
+        let entry = match input.curve {
+            Some (ref name) => values.iter_mut().find(|&&mut (ref n, _)| n == name),
+            None => values.iter_mut().next(),
+        };
+        if let Some (&mut (_, ref mut value)) = entry {
+            *value = blend(input, *value);
+        }
+    }
+}
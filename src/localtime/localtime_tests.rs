@@ -0,0 +1,61 @@
+#[cfg(test)]
+mod localtime_test {
+    use localtime::*;
+    use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+
+    const ZONES: [&str; 6] = ["UTC", "Australia/Sydney", "Australia/Lord_Howe", "Europe/Berlin", "America/New_York", "Pacific/Auckland"];
+
+    fn zone(index: u8) -> Zone {
+        parse_zone(ZONES[index as usize % ZONES.len()]).unwrap()
+    }
+
+    // Instants from 1970 into the 2100s
+    fn instant(seconds: u32) -> DateTime<Utc> {
+        DateTime::from_utc(NaiveDateTime::from_timestamp(seconds as i64, 0), Utc)
+    }
+
+    fn local(date_time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date_time, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn utc(date_time: &str) -> DateTime<Utc> {
+        DateTime::from_utc(local(date_time), Utc)
+    }
+
+    quickcheck! {
+        // Any instant's wall clock leads back to the same instant, or an hour earlier when it
+        // is the second pass through a repeated hour
+        fn instants_round_trip(seconds: u32, zone_index: u8) -> bool {
+            let zone = zone(zone_index);
+            let instant = instant(seconds);
+            let wall_clock = to_local(&zone, &instant);
+            let back = to_instant(&zone, &wall_clock);
+            to_local(&zone, &back) == wall_clock && back <= instant && instant - back <= Duration::hours(1)
+        }
+
+        // Any wall clock time is reached, skipped times are moved forward by the length of the gap
+        fn wall_clock_is_never_earlier(seconds: u32, zone_index: u8) -> bool {
+            let zone = zone(zone_index);
+            let wall_clock = instant(seconds).naive_utc();
+            let reached = to_local(&zone, &to_instant(&zone, &wall_clock));
+            reached >= wall_clock && reached - wall_clock <= Duration::hours(1)
+        }
+    }
+
+    #[test]
+    fn unknown_zone() {
+        assert!(parse_zone("Mars/Olympus_Mons").is_err());
+        assert_eq!(name(&parse_zone("Australia/Sydney").unwrap()), "Australia/Sydney");
+    }
+
+    #[test]
+    fn daylight_saving_changes() {
+        let berlin = parse_zone("Europe/Berlin").unwrap();
+        // Clocks went forward from 02:00 to 03:00 on 2018-03-25, 02:30 runs as 03:30
+        assert_eq!(to_instant(&berlin, &local("2018-03-25 02:30:00")), utc("2018-03-25 01:30:00"));
+        assert_eq!(to_local(&berlin, &utc("2018-03-25 01:30:00")), local("2018-03-25 03:30:00"));
+        // Clocks went back from 03:00 to 02:00 on 2018-10-28, 02:30 is the first pass
+        assert_eq!(to_instant(&berlin, &local("2018-10-28 02:30:00")), utc("2018-10-28 00:30:00"));
+        assert_eq!(to_local(&berlin, &utc("2018-10-28 01:30:00")), local("2018-10-28 02:30:00"));
+    }
+}
@@ -0,0 +1,80 @@
+use chrono::{DateTime, Duration, Local, LocalResult, NaiveDateTime, Offset, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+
+use config::Soundscape;
+
+mod localtime_tests;
+
+// Local time
+// Converts between instants and the installation's wall clock. The zone is the system's unless
+// an IANA zone is configured, which is looked up in the tz database built into the binary.
+// Offsets are found for each instant, so conversions stay correct either side of DST changes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Zone {
+    System,
+    Named(Tz),
+}
+
+pub fn parse_zone(name: &str) -> Result<Zone, String> {
+    name.parse::<Tz>()
+        .map(Zone::Named)
+        .map_err(|e| format!("Unable to use time zone '{}': {}", name, e))
+}
+
+pub fn from_config(config: &Soundscape) -> Result<Zone, String> {
+    match config.timezone {
+        Some (ref name) => parse_zone(name),
+        None => Ok(Zone::System),
+    }
+}
+
+pub fn name(zone: &Zone) -> String {
+    match *zone {
+        Zone::System => "system local time".to_string(),
+        Zone::Named(tz) => tz.name().to_string(),
+    }
+}
+
+fn local_in<Z: TimeZone>(zone: &Z, instant: &DateTime<Utc>) -> NaiveDateTime {
+    zone.from_utc_datetime(&instant.naive_utc()).naive_local()
+}
+
+// Wall clock times repeated when the clocks go back are taken as the first occurrence, times
+// skipped when the clocks go forward use the offset from before the change (as RFC 5545 does).
+fn instant_in<Z: TimeZone>(zone: &Z, local: &NaiveDateTime) -> DateTime<Utc> {
+    match zone.from_local_datetime(local) {
+        LocalResult::Single (t) => t.with_timezone(&Utc),
+        LocalResult::Ambiguous (a, b) => a.with_timezone(&Utc).min(b.with_timezone(&Utc)),
+        LocalResult::None => {
+            let before = zone.offset_from_utc_datetime(&(*local - Duration::days(1))).fix();
+            DateTime::from_utc(*local - Duration::seconds(before.local_minus_utc() as i64), Utc)
+        },
+    }
+}
+
+pub fn to_local(zone: &Zone, instant: &DateTime<Utc>) -> NaiveDateTime {
+    match *zone {
+        Zone::System => local_in(&Local, instant),
+        Zone::Named(tz) => local_in(&tz, instant),
+    }
+}
+
+pub fn to_instant(zone: &Zone, local: &NaiveDateTime) -> DateTime<Utc> {
+    match *zone {
+        Zone::System => instant_in(&Local, local),
+        Zone::Named(tz) => instant_in(&tz, local),
+    }
+}
+
+pub fn now(zone: &Zone) -> NaiveDateTime {
+    to_local(zone, &Utc::now())
+}
+
+pub fn seconds_of_day(zone: &Zone) -> u32 {
+    now(zone).num_seconds_from_midnight()
+}
+
+// The time of day ahead_ms from now
+pub fn seconds_of_day_in(zone: &Zone, ahead_ms: i64) -> u32 {
+    to_local(zone, &(Utc::now() + Duration::milliseconds(ahead_ms))).num_seconds_from_midnight()
+}
@@ -2,55 +2,90 @@
 extern crate serde_derive;
 extern crate serde_yaml;
 extern crate bspline;
-extern crate timer;
 extern crate chrono;
+use chrono::Utc;
+extern crate chrono_tz;
+#[cfg(test)]
+#[macro_use]
+extern crate quickcheck;
 
 extern crate cgmath;
+extern crate hmac;
+extern crate sha2;
 
 extern crate rosc;
 use rosc::OscPacket;
 use rosc::OscMessage;
 
 extern crate rodio;
+extern crate cpal;
 use rodio::Source;
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::io::BufReader;
 use std::env;
 use std::net::{UdpSocket, SocketAddrV4};
 use std::str::FromStr;
 use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use std::collections::BinaryHeap;
 use std::thread;
 
 mod config;
-mod epochsy;
+mod localtime;
 use config::open_scene;
 mod soundscape;
+mod curves;
+mod rng;
 mod rodiox;
+mod metering;
+mod transport;
+mod inputs;
+mod variants;
+mod events;
+mod tempo;
+mod playlist;
+mod schedule;
+mod sleep;
+mod solar;
+mod election;
+mod clocksync;
+mod snapshot;
+mod access;
+
+mod main_tests;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 enum OscEvent {
     Volume(f32),
-    MasterAlive(i64),
-    SceneChange(usize, i64),
+    Input(String, f32),
+    MasterAlive(i64, u64, u32, u32),
+    LegacyMasterAlive(i64),
+    Election(u64, u32, u32),
+    TimeRequest(u32, i64),
+    TimeReply(u32, i64, i64, i64),
+    StateRequest(u32),
+    StateSnapshot(u32, snapshot::Snapshot),
+    SceneChange(usize, i64, i64),
     RefreshBackground,
     NoAction,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 enum AppMsg {
     Osc(OscEvent),
-    MetroTick,
-    Update,
+    MetroTick(i64),
+    Update(i64),
+    AudioStalled,
     Error
 }
 
 fn main() {
     // Handle args
     let args: Vec<String> = env::args().collect();
-    let usage = format!("Usage {} [soundscape-config.yml]", &args[0]);
+    let usage = format!("Usage {} [soundscape-config.yml | --list-devices]", &args[0]);
 
     let config_file_name =
         if args.len() > 2 {
@@ -58,6 +93,11 @@ fn main() {
             println!("{}", usage);
             ::std::process::exit(1)
         }
+        else if args.len() == 2 && args[1] == "--list-devices" {
+            // list output devices for the output config section
+            rodiox::output::list_devices();
+            ::std::process::exit(0)
+        }
         else if args.len() == 2 {
             // custom configuration
             format!("{}", args[1])
@@ -91,6 +131,16 @@ fn main() {
 
     let osc_socket_out = UdpSocket::bind(osc_out_addr).expect( format!("Unable to provision socket: {}", osc_out_addr).as_str() );
 
+    // Received messages are filtered by source, signature and rate, sent control messages are signed
+    let mut osc_access = match access::from_config(&config) {
+        Ok (osc_access) => osc_access,
+        Err (e) => {
+            println!("Error in osc_access: {}", e);
+            ::std::process::exit(1)
+        },
+    };
+    let osc_secret = access::secret(&config);
+
     // build subscriber addresses
     let mut subscribers: Vec<SocketAddrV4> = Vec::with_capacity( config.subscribers.len() );
     for address in &config.subscribers {
@@ -104,13 +154,46 @@ fn main() {
         subscribers.push(socket_addr);
     }
 
+    // build metering subscriber addresses
+    let mut level_metering = config.metering.as_ref().map(metering::from_config);
+    let mut meter_subscribers: Vec<SocketAddrV4> = Vec::new();
+    if let Some (ref params) = config.metering {
+        for address in &params.subscribers {
+            match SocketAddrV4::from_str( format!("{}:{}", address.host, address.port).as_str() ) {
+                Ok(addr)    => meter_subscribers.push(addr),
+                Err(_)      => {
+                    println!("Unable to use metering subscriber's host and port fields ('{}:{}') as an address!", address.host, address.port);
+                    ::std::process::exit(1)
+                }
+            }
+        }
+    }
+
     // test scene files
-    for scene_file in &config.scenes {
+    for scene_file in &sleep::scene_files(&config) {
         print!("Checking scene file: '{}'...", scene_file);
         config::check_scene_file(&scene_file).expect("Found error with scene content");
         println!("Scene OK", );
     }
 
+    let mut zone = match localtime::from_config(&config) {
+        Ok (zone) => zone,
+        Err (e) => {
+            println!("Error in timezone: {}", e);
+            ::std::process::exit(1)
+        },
+    };
+    println!("Scheduling in {}", localtime::name(&zone));
+
+    let mut playlist = match playlist::from_config(&config) {
+        Ok (playlist) => playlist,
+        Err (e) => {
+            println!("Error in playlist: {}", e);
+            ::std::process::exit(1)
+        },
+    };
+    println!("Playing scenes in {:?} order", playlist.mode);
+
     let background_scene = match config.background_scene {
         Some (ref scene_file) => Some(config::check_scene_file(scene_file).expect("Error in background scene!")),
         None => {
@@ -119,19 +202,16 @@ fn main() {
         },
     };
 
-    // setup metronome
+    // setup message channel
     let (tx_app_msg, rx_app_msg) = mpsc::channel();
     let tx_metro = mpsc::Sender::clone(&tx_app_msg);
     let tx_osc   = mpsc::Sender::clone(&tx_app_msg);
-    let metro = timer::MessageTimer::new(tx_metro);
 
     let step_size_ms = config.metro_step_ms as i64;
-    let metro_rate = step_size_ms as i64;
-    let _guard_metro = metro.schedule_repeating(chrono::Duration::milliseconds(metro_rate), AppMsg::MetroTick);
 
     // setup command BinaryHeap and queue first play command
     let mut future_commands = BinaryHeap::with_capacity(128);
-    future_commands.push(soundscape::load_at(0, soundscape::Origin::Internal, 0));
+    future_commands.push(soundscape::load_at(playlist::first(&mut playlist, localtime::seconds_of_day(&zone)), soundscape::Origin::Internal, 0));
     future_commands.push(soundscape::load_background(0));
 
     // Setup socket
@@ -144,11 +224,10 @@ fn main() {
 
         loop {
             match socket.recv_from(&mut packet_buffer) {
-                Ok((bytes, _remote_address)) => {
-                    let action = route_osc(
-                        rosc::decoder::decode(&packet_buffer[..bytes]).unwrap()
-                    );
-                    tx_osc.send(AppMsg::Osc(action)).unwrap();
+                Ok((bytes, remote_address)) => {
+                    if let Some (packet) = access::receive(&mut osc_access, &packet_buffer[..bytes], remote_address.ip(), Instant::now(), Utc::now().timestamp_millis()) {
+                        tx_osc.send(AppMsg::Osc(route_osc(packet))).unwrap();
+                    }
                 }
                 Err(e) => {
                     // Log to console and quit the recv loop
@@ -161,20 +240,38 @@ fn main() {
 
     // Setup audio
 
-    let output_device = rodio::default_output_device().expect("Error selecting audio output device");
+    let output_settings = config::to_output_settings(&config);
+    let output_device = rodiox::output::select_device(&output_settings.device).expect("Error selecting audio output device");
 
     println!("Outputing audio to {}", output_device.name());
-    let output_count = match output_device.default_output_format() {
+    let output_format = match rodiox::output::select_format(&output_device, &output_settings) {
         Ok (format) => {
-            println!("Using default output format of {:?}", format);
-            format.channels as usize
+            println!("Using output format of {:?}", format);
+            format
+        },
+        Err (e) => {
+            println!("Error selecting output format for audio device: {}", e);
+            ::std::process::exit(1);
         },
+    };
+    let output_count = output_format.channels as usize;
+    let sample_rate = output_format.sample_rate.0;
+
+    let limiter_settings = config::to_limiter_settings(&config);
+    println!("Limiting master bus with {:?}", limiter_settings);
+    let (master_bus, master_bus_output) = rodiox::master_bus::MasterBus::new(output_count as u16, sample_rate, &limiter_settings);
+    let mut output = match rodiox::output::open(&output_device, &output_format, master_bus_output) {
+        Ok (output) => output,
         Err (e) => {
-            println!("Error retriving channel count from audio device: {:?}", e);
+            println!("Error opening audio output: {}", e);
             ::std::process::exit(1);
         },
     };
 
+    // Reopen the output if the device stops consuming audio
+    let tx_watchdog = mpsc::Sender::clone(&tx_app_msg);
+    let _watchdog = rodiox::output::start_watchdog(master_bus.clock(), 2000, tx_watchdog, AppMsg::AudioStalled);
+
     let mut speaker_positions :Vec<[f32; 3]> = Vec::with_capacity(output_count);
 
     let positions_limit = match config::ignore_extra_speakers(&config) {
@@ -198,30 +295,58 @@ fn main() {
     let mut background_sources: Vec<soundscape::SoundSource> = Vec::new();
     let mut active_sources: Vec<soundscape::SoundSource> = Vec::with_capacity(config.voice_limit);
     let mut retired_sources: Vec<soundscape::SoundSource> = Vec::with_capacity(config.voice_limit);
+    let mut scene_events: Vec<events::EventSource> = Vec::new();
+    let event_voices = Arc::new(AtomicUsize::new(0));
+    let mut is_events_playing = false;
+    let mut tempo_grid: Option<tempo::Grid> = None;
+    // Counts scene loads, so a retire command only applies to the scene it was queued with
+    let mut scene_load = 0usize;
+
+    // setup metronome, driven by the audio consumed by the output device
+    let metro_pending = Arc::new(AtomicBool::new(false));
+    let _metronome = transport::start_metronome(master_bus.clock(), step_size_ms, metro_pending.clone(), tx_metro, AppMsg::MetroTick);
+    let mut transport = transport::new();
+    let audio_clock = master_bus.clock();
 
     let mut elapsed_ms = 0i64;
-    let mut dynamic_curve = soundscape::structure_from_scene(&open_scene(&config.scenes[0]));
-    let step_increment = metro_rate as f32;
+    let mut last_status_ms = 0i64;
+    // Most recent limiter gain reduction, sent with the metering and shown in the status line
+    let mut limiter_reduction_db = 0f32;
+    let mut master_level = config.default_level;
+    // Where the current playlist scene began on the timeline, for units joining part-way through
+    let mut scene_start_ms = 0i64;
+    // The scene queued to load after the current one and when, sent to joining units
+    let mut next_scene_load: Option<(usize, i64)> = None;
+    // Scene indices past the playlist's scenes are the closing and opening scenes
+    let scene_files = sleep::scene_files(&config);
+    let mut structures = soundscape::structures_from_scene(&open_scene(&scene_files[0]));
+    let mut control_inputs = inputs::from_config(&config);
 
     // Schedule state
-    let mut is_schedule_live = true;
-    future_commands.push(soundscape::check_shedule(0));
-    // Master/slave redudancy timer
-    let mut is_master = false;
-    let mut master_activity_timer = match config::is_fallback_slave(&config) {
-        true => 1000,
-        false => {
-            is_master = true;
-            -1
+    let mut play_schedule = match schedule::from_config(&config) {
+        Ok (schedule) => schedule,
+        Err (e) => {
+            println!("Error in schedule: {}", e);
+            ::std::process::exit(1)
         },
     };
-
-    if is_master {
-        println!("Running as master.");
-    }
-    else {
-        println!("Running as slave.");
-    }
+    let mut schedule_logged_date = None;
+    let mut is_schedule_live = true;
+    let mut is_schedule_checked = false;
+    let mut sleep = sleep::from_config(&config);
+    // The schedule is applied once now and again each time the plan comes due
+    let mut schedule_plan = schedule::plan(&play_schedule, &Utc::now(), Instant::now());
+    println!("{}", schedule::describe_plan(&schedule_plan, &zone));
+    future_commands.push(soundscape::check_shedule(0));
+    let mut config_modified = config::modified_time(&config_file_name);
+    let mut config_checked_at = Instant::now();
+    // One unit leads the others, chosen by priority and unit id
+    let mut election = election::from_config(&config);
+    println!("Running as unit {} with priority {}.", election.unit_id, election.priority);
+    // Followers measure the leader's timeline and slew toward it
+    let mut clock_sync = clocksync::from_config(&config);
+    // A follower plays along once it has the leader's state, until then it stands by
+    let mut state_requester = snapshot::new_requester();
 
     // Run loop
     loop {
@@ -234,65 +359,210 @@ fn main() {
         };
         match message {
             AppMsg::Error => (),
+            AppMsg::AudioStalled => {
+                println!("Audio output to {} has stalled, reopening output.", rodiox::output::device_name(&output));
+                match rodiox::output::reopen(&mut output, &output_settings) {
+                    Ok (_) => println!("Reopened audio output on {}", rodiox::output::device_name(&output)),
+                    Err (e) => println!("Unable to reopen audio output, will retry: {}", e),
+                }
+            },
             AppMsg::Osc (action) => {
                 match action {
-                    // add master alive message handling here
-                    OscEvent::MasterAlive(new_time) => {
-                        if master_activity_timer < 0 && !is_master {
-                            println!("Master aquired, switching to slave mode");
+                    OscEvent::MasterAlive(new_time, term, priority, unit_id) => {
+                        // Take on the leader's timeline until round trips have measured it
+                        if election::receive_heartbeat(&mut election, term, priority, unit_id) {
+                            if clocksync::receive_heartbeat(&mut clock_sync, unit_id) {
+                                elapsed_ms = new_time;
+                                transport::set_time(&mut transport, new_time);
+                            }
+                        }
+                    },
+                    OscEvent::LegacyMasterAlive(new_time) => {
+                        // It can't answer round trips, so its time is taken from every heartbeat
+                        if election::receive_legacy_heartbeat(&mut election) {
+                            if clocksync::receive_heartbeat(&mut clock_sync, election::LEGACY_RANK.1) {
+                                elapsed_ms = new_time;
+                                transport::set_time(&mut transport, new_time);
+                            }
+                        }
+                    },
+                    OscEvent::TimeRequest(unit_id, t0) => {
+                        if election::is_leader(&election) {
+                            let leader_ms = transport::from_audio_ms(&transport, audio_clock.elapsed_ms());
+                            broadcast(&osc_socket_out, &sign_now(&osc_secret, clocksync::reply_packet(unit_id, t0, leader_ms, leader_ms)), &subscribers);
                         }
-                        // If we are not a master, update counters
-                        if !is_master {
-                            master_activity_timer = 1000;
-                            elapsed_ms = new_time;
+                    },
+                    OscEvent::TimeReply(unit_id, t0, t1, t2) => {
+                        if unit_id == election.unit_id {
+                            clocksync::receive_reply(&mut clock_sync, t0, t1, t2, audio_clock.elapsed_ms());
+                        }
+                    },
+                    OscEvent::Election(term, priority, unit_id) => {
+                        if let Some (reply) = election::receive_candidate(&mut election, term, priority, unit_id) {
+                            broadcast(&osc_socket_out, &sign_now(&osc_secret, election::to_packet(&reply, elapsed_ms)), &subscribers);
+                        }
+                    },
+                    OscEvent::StateRequest(unit_id) => {
+                        if election::is_leader(&election) {
+                            let state = snapshot::Snapshot {
+                                scene:              sleep.current_scene,
+                                scene_start_ms:     scene_start_ms,
+                                time_ms:            transport::from_audio_ms(&transport, audio_clock.elapsed_ms()),
+                                level:              master_level,
+                                is_schedule_live:   is_schedule_live,
+                                is_audible:         sleep::is_audible(&sleep),
+                                next_scene:         next_scene_load,
+                                overrides:          inputs::overrides(&control_inputs),
+                            };
+                            broadcast(&osc_socket_out, &sign_now(&osc_secret, snapshot::to_packet(unit_id, &state)), &subscribers);
+                        }
+                    },
+                    OscEvent::StateSnapshot(unit_id, state) => {
+                        if unit_id == election.unit_id && election::is_leader_alive(&election) {
+                            snapshot::answered(&mut state_requester);
+                            master_level = state.level;
+                            for &(ref name, value) in &state.overrides {
+                                inputs::receive(&mut control_inputs, name, value);
+                            }
+                            if state.is_schedule_live != is_schedule_live {
+                                println!("Leader's schedule is {}, this unit follows its own schedule.", if state.is_schedule_live { "live" } else { "not live" });
+                            }
+
+                            let playing = if scene_load > 0 { Some ((sleep.current_scene, scene_start_ms)) } else { None };
+                            for command in snapshot::join(&state, config.scenes.len(), playing, elapsed_ms) {
+                                // The next scene may already have come with the leader's /ChangeScene
+                                if !soundscape::is_queued(&future_commands, &command) {
+                                    future_commands.push(command);
+                                }
+                            }
+                        }
+                    },
+                    OscEvent::SceneChange(index, delta, offset_ms) => {
+                        let command = soundscape::load_offset_at(index, soundscape::Origin::Remote, delta, offset_ms);
+                        // Also queued when joining, if the snapshot came first
+                        if !soundscape::is_queued(&future_commands, &command) {
+                            future_commands.push(command);
                         }
                     },
-                    OscEvent::SceneChange(index, delta) => future_commands.push(soundscape::load_at(index, soundscape::Origin::Remote, delta)),
                     OscEvent::RefreshBackground => future_commands.push(soundscape::load_background(0)),
-                    OscEvent::Volume (_) => println!("Ignored volume message."),
+                    OscEvent::Input (name, value) => {
+                        if !inputs::receive(&mut control_inputs, &name, value) {
+                            println!("Ignored value for undeclared input '{}'.", name);
+                        }
+                    },
+                    OscEvent::Volume (volume) => {
+                        // Snapshots pass the level on to followers as they join
+                        master_level = volume.max(0.0);
+                        println!("Master level set to {}.", master_level);
+                        if sleep::is_audible(&sleep) {
+                            fade_to_level(&mut active_sources, master_level);
+                            fade_to_level(&mut background_sources, master_level);
+                        }
+                    },
                     OscEvent::NoAction => () //println!("No action defined for {:?}", action),
                 }
             }
-            AppMsg::MetroTick => {
-                // Keep time rolling forward if we are the master or we lose our master
-                if is_master || master_activity_timer < 0 {
-                    elapsed_ms += step_size_ms;
+            AppMsg::MetroTick (audio_ms) => {
+                metro_pending.store(false, Ordering::SeqCst);
+                let tick_ms = transport::advance(&mut transport, audio_ms);
+
+                // Followers slew toward the leader's timeline, measured by round trips to it
+                let leader = election.leader.filter(|_| election::is_leader_alive(&election)).map(|(_, unit_id)| unit_id);
+                clocksync::follow(&mut clock_sync, leader);
+                if let Some (t0) = clocksync::tick(&mut clock_sync, tick_ms, audio_ms) {
+                    broadcast(&osc_socket_out, &sign_now(&osc_secret, clocksync::request_packet(election.unit_id, t0)), &subscribers);
+                }
+                if snapshot::tick(&mut state_requester, leader, tick_ms) {
+                    broadcast(&osc_socket_out, &sign_now(&osc_secret, snapshot::request_packet(election.unit_id)), &subscribers);
+                }
+                let slew_ms = clocksync::slew(&mut clock_sync, transport.offset_ms, tick_ms);
+                transport::shift(&mut transport, slew_ms);
+                if let Some (report) = clocksync::report(&mut clock_sync, transport.offset_ms, tick_ms) {
+                    println!("{}", report);
+                }
+                elapsed_ms = transport::now(&transport);
+
+                // Run the soundscape if we lead or have no leader to follow, or alongside the leader once
+                // its snapshot has arrived. A change of leader is joined again from the new leader's snapshot.
+                if election::is_autonomous(&election) || snapshot::is_joined(&state_requester) {
                     // trigger an update cycle
-                    tx_app_msg.send(AppMsg::Update).unwrap();
+                    tx_app_msg.send(AppMsg::Update(tick_ms)).unwrap();
                 }
 
-                if is_master {
-                    // Tell any slaved device we are alive
-                    let alive_message = rosc::encoder::encode(&OscPacket::Message(OscMessage {
-                        addr: "/MasterAlive".to_string(),
-                        args: Some( vec![ rosc::OscType::Long(elapsed_ms) ] ),
-                    })).expect("Error creating Master alive message");
-
-                    for addr in &subscribers {
-                        match osc_socket_out.send_to(&alive_message, addr) {
-                            Ok (_) => (),
-                            Err (e) => {
-                                println!("Error sending to client: {}, reason: {}", addr, e);
-                                ()
+                // Heartbeats while leading, a candidacy when standing for election
+                if let Some (message) = election::tick(&mut election, tick_ms) {
+                    broadcast(&osc_socket_out, &sign_now(&osc_secret, election::to_packet(&message, elapsed_ms)), &subscribers);
+                }
+
+                // Broadcast levels to metering subscribers
+                if let Some (ref mut level_metering) = level_metering {
+                    if metering::tick(level_metering, tick_ms) {
+                        let levels = master_bus.take_levels();
+                        for (speaker, level) in levels.iter().enumerate() {
+                            broadcast(&osc_socket_out, &metering::speaker_message(speaker, level), &meter_subscribers);
+                        }
+                        limiter_reduction_db = master_bus.take_gain_reduction_db();
+                        broadcast(&osc_socket_out, &metering::limiter_message(limiter_reduction_db), &meter_subscribers);
+
+                        for alarm in metering::check_alarms(level_metering, &levels) {
+                            println!("Metering alarm: {:?}", alarm);
+                            broadcast(&osc_socket_out, &metering::alarm_message(&alarm), &meter_subscribers);
+                        }
+
+                        if level_metering.include_sources {
+                            for source in active_sources.iter().chain(background_sources.iter()) {
+                                let source_levels = source.channel.take_levels();
+                                broadcast(&osc_socket_out, &metering::source_message(&source.name, &source_levels), &meter_subscribers);
                             }
                         }
                     }
                 }
-                else {
-                    // Update slave fallover duration
-                    if master_activity_timer >= 0 {
-                        if master_activity_timer - metro_rate < 0 {
-                            println!("Master keep alive has timed out, slave going autonomous!");
+            },
+            AppMsg::Update (tick_ms) => {
+                // Update automation cycle, held while asleep
+                if sleep::is_audible(&sleep) {
+                    soundscape::advance_structures(&mut structures, tick_ms as f32);
+                }
+                inputs::advance(&mut control_inputs, tick_ms);
+
+                // Follow the schedule on the wall clock, which the master can't move
+                let wall_now = Utc::now();
+                let monotonic_now = Instant::now();
+                let mut is_replan_needed = schedule::is_due(&schedule_plan, &wall_now);
+                if schedule::has_clock_jumped(&schedule_plan, &wall_now, monotonic_now) {
+                    println!("Wall clock has jumped, planning the schedule again.");
+                    is_replan_needed = true;
+                }
+
+                // Pick up schedule changes from the config file
+                if monotonic_now.duration_since(config_checked_at) >= Duration::from_secs(5) {
+                    config_checked_at = monotonic_now;
+                    let modified = config::modified_time(&config_file_name);
+                    if modified != config_modified {
+                        config_modified = modified;
+                        // The zone is reloaded with the schedule, as the schedule's times are on its wall clock
+                        let reload = config::load_from_file(&config_file_name)
+                            .and_then(|c| Ok((localtime::from_config(&c)?, schedule::from_config(&c)?)));
+                        match reload {
+                            Ok ((reloaded_zone, reloaded)) => {
+                                println!("Reloaded the timezone and schedule from {}, other changes apply after a restart.", config_file_name);
+                                if reloaded_zone != zone {
+                                    println!("Scheduling in {}", localtime::name(&reloaded_zone));
+                                }
+                                zone = reloaded_zone;
+                                play_schedule = reloaded;
+                                schedule_logged_date = None;
+                                is_replan_needed = true;
+                            },
+                            Err (e) => println!("Keeping the current schedule, unable to reload {}: {}", config_file_name, e),
                         }
-                        master_activity_timer -= metro_rate;
                     }
                 }
-            },
-            AppMsg::Update => {
-                // Update automation cycle
-                dynamic_curve.step += step_increment;
-                if dynamic_curve.step > dynamic_curve.duration {
-                    dynamic_curve.step = 0f32;
+
+                if is_replan_needed {
+                    schedule_plan = schedule::plan(&play_schedule, &wall_now, monotonic_now);
+                    println!("{}", schedule::describe_plan(&schedule_plan, &zone));
+                    future_commands.push(soundscape::check_shedule(elapsed_ms));
                 }
 
                 // execute any commands that should be executed now or earlier
@@ -302,105 +572,230 @@ fn main() {
                             match future_cmd.command {
                                 soundscape::Cmd::Play => {
                                     println!("Executing play command at step: {}", elapsed_ms);
-                                    play(&mut active_sources)
+                                    // Start every loop on the same frame, where the scene's tempo grid begins
+                                    let start_ms = transport::to_audio_ms(&transport, future_cmd.at_tick + step_size_ms);
+                                    start(&mut active_sources, start_ms.max(audio_clock.elapsed_ms()));
+                                    is_events_playing = true;
                                 },
-                                soundscape::Cmd::Load (n, origin) => {
-                                    if origin == soundscape::Origin::Internal && !is_master && master_activity_timer > 0 {
-                                        println!("Ignored local load due live remote master.");
+                                soundscape::Cmd::Load (n, origin, offset_ms) => {
+                                    // Closing and opening scenes follow this unit's own schedule
+                                    let is_sleep_scene = sleep::is_closing_scene(&sleep, n) || sleep::is_opening_scene(&sleep, n);
+                                    // so other units can only load the playlist's scenes
+                                    let scene_count = match origin {
+                                        soundscape::Origin::Internal => scene_files.len(),
+                                        soundscape::Origin::Remote => config.scenes.len(),
+                                    };
+                                    if n >= scene_count {
+                                        println!("Ignored load of scene {}, there are {} scenes to load.", n, scene_count);
+                                    }
+                                    else if origin == soundscape::Origin::Internal && !is_sleep_scene && election::is_leader_alive(&election) {
+                                        println!("Ignored local load while following a live leader.");
                                     }
                                     else {
                                         println!("Executing load command at step: {}", elapsed_ms);
-                                        let scene = open_scene(&config.scenes[n]);
-                                        add_resources(&mut active_sources, &output_device, &scene, &speaker_positions);
-                                        dynamic_curve = soundscape::structure_from_scene(&scene);
-
-                                        future_commands.push(soundscape::play_at(elapsed_ms + step_size_ms));
-                                        future_commands.push(soundscape::retire_at(elapsed_ms + scene.duration_ms));
-                                        // Avoid double queueing of load actions
-                                        if is_master || master_activity_timer < 0 {
-                                            future_commands.push(soundscape::load_at(
-                                                (n + 1) % config.scenes.len(),
-                                                soundscape::Origin::Internal,
-                                                elapsed_ms + scene.duration_ms + step_size_ms,
-                                                ));
+                                        let scene = open_scene(&scene_files[n]);
+                                        scene_load += 1;
+                                        // The scene runs from when it would have been loaded to be offset_ms in when due,
+                                        // so a load joining a scene part-way lines up with it
+                                        let load_ms = future_cmd.at_tick - offset_ms;
+                                        // Loops start a step after the play command, which is a step after the load
+                                        let loop_start_ms = load_ms + 2 * step_size_ms;
+                                        if !is_sleep_scene {
+                                            sleep.current_scene = n;
+                                            scene_start_ms = load_ms;
                                         }
 
-                                        if is_master {
-                                            // Add remote load commmand to all slaved devices
-                                            let load_message = rosc::encoder::encode(&OscPacket::Message(OscMessage {
-                                                addr: "/ChangeScene".to_string(),
-                                                args: Some( vec!
-                                                            [ rosc::OscType::Int( ((n + 1) % config.scenes.len()) as i32)
-                                                            , rosc::OscType::Long(elapsed_ms + scene.duration_ms + step_size_ms)
-                                                            ] ),
-                                            })).expect("Error creating Master alive message");
-
-                                            for addr in &subscribers {
-                                                match osc_socket_out.send_to(&load_message, addr) {
-                                                    Ok (_) => (),
-                                                    Err (e) => {
-                                                        println!("Error sending to client: {}, reason: {}", addr, e);
-                                                        ()
-                                                    }
-                                                }
+                                        // Crossfade from a scene which is still playing
+                                        let transition_fade = scene.transition.as_ref().map(|t| (
+                                            t.overlap_ms as f32,
+                                            t.shape.as_ref().map(config::to_fade_shape).unwrap_or(rodiox::source::FadeShape::Linear),
+                                        ));
+                                        retire_resources(&mut active_sources, &mut retired_sources, transition_fade);
+
+                                        // Loops of a scene joined part-way are moved on by the offset here, as reading
+                                        // through them to find their length is too slow for the audio output
+                                        add_resources(&mut active_sources, &master_bus, &scene, &speaker_positions, config.metro_step_ms, offset_ms);
+                                        // Sources going live during the crossfade come in over it
+                                        if let Some ((fade_ms, shape)) = transition_fade {
+                                            for s in active_sources.iter_mut() {
+                                                s.transition_fade = Some ((shape, elapsed_ms + fade_ms as i64));
+                                            }
+                                        }
+                                        let previous_values = soundscape::structure_values(&structures);
+                                        structures = soundscape::structures_from_scene(&scene);
+                                        if offset_ms > 0 {
+                                            println!("Starting scene {} {}ms in.", n, elapsed_ms - load_ms);
+                                            soundscape::seek_structures(&mut structures, (elapsed_ms - load_ms) as f32);
+                                        }
+                                        else if let Some (config::StructureStart::MatchValue) = scene.transition.as_ref().and_then(|t| t.structure_start) {
+                                            soundscape::match_structures(&mut structures, &previous_values);
+                                        }
+                                        scene_events = events::from_scene(&scene);
+                                        is_events_playing = false;
+
+                                        // Bars are counted from where the loops start. The loops play a step after this
+                                        // load, when they'd be as far in as the offset
+                                        let play_ms = future_cmd.at_tick + step_size_ms;
+                                        tempo_grid = tempo::from_scene(&scene, loop_start_ms);
+                                        let scene_end_ms = tempo::snap(&tempo_grid, load_ms + scene.duration_ms);
+
+                                        future_commands.push(soundscape::play_at(play_ms));
+                                        next_scene_load = None;
+                                        if sleep::is_closing_scene(&sleep, n) {
+                                            // Fall asleep once the closing scene has played through
+                                            future_commands.push(soundscape::sleep_at(scene_end_ms));
+                                        }
+                                        else if sleep::is_opening_scene(&sleep, n) {
+                                            future_commands.push(soundscape::resume_at(scene_end_ms));
+                                        }
+                                        else {
+                                            // The next scene loads early by its overlap, retiring this scene as it does
+                                            let next_load = |scene: usize| {
+                                                let overlap_ms = config::transition_overlap_ms(&open_scene(&config.scenes[scene]));
+                                                (scene_end_ms - overlap_ms).max(play_ms) + step_size_ms
+                                            };
+                                            // Time of day slots are those of the wall clock when the next scene loads
+                                            let next_scene = playlist::next_loading(&mut playlist, n, scene_end_ms, &next_load, |at_ms| {
+                                                localtime::seconds_of_day_in(&zone, at_ms - elapsed_ms)
+                                            });
+                                            let next_load_ms = next_load(next_scene);
+                                            next_scene_load = Some ((next_scene, next_load_ms));
+
+                                            future_commands.push(soundscape::retire_at(scene_load, scene_end_ms));
+                                            // Avoid double queueing of load actions
+                                            if election::is_autonomous(&election) {
+                                                future_commands.push(soundscape::load_at(
+                                                    next_scene,
+                                                    soundscape::Origin::Internal,
+                                                    next_load_ms,
+                                                    ));
                                             }
 
+                                            if election::is_leader(&election) {
+                                                // Add remote load commmand to all slaved devices
+                                                let load_message = OscPacket::Message(OscMessage {
+                                                    addr: "/ChangeScene".to_string(),
+                                                    args: Some( vec!
+                                                                [ rosc::OscType::Int(next_scene as i32)
+                                                                , rosc::OscType::Long(next_load_ms)
+                                                                ] ),
+                                                });
+                                                broadcast(&osc_socket_out, &sign_now(&osc_secret, load_message), &subscribers);
+                                            }
                                         }
                                     }
                                 },
                                 soundscape::Cmd::LoadBackground => {
                                     println!("Executing LoadBackground at step: {}", elapsed_ms);
-                                    if is_master {
+                                    if election::is_leader(&election) {
                                         // send LoadBackground commmand to all slaved devices
-                                        let load_message = rosc::encoder::encode(&OscPacket::Message(OscMessage {
+                                        let load_message = OscPacket::Message(OscMessage {
                                             addr: "/RefreshBackground".to_string(),
                                             args: None
-                                        })).expect("Error creating refresh background message");
-
-                                        for addr in &subscribers {
-                                            match osc_socket_out.send_to(&load_message, addr) {
-                                                Ok (_) => (),
-                                                Err (e) => {
-                                                    println!("Error sending to client: {}, reason: {}", addr, e);
-                                                    ()
-                                                }
-                                            }
-                                        }
+                                        });
+                                        broadcast(&osc_socket_out, &sign_now(&osc_secret, load_message), &subscribers);
                                     }
 
-                                    retire_resources(&mut background_sources, &mut retired_sources);
+                                    retire_resources(&mut background_sources, &mut retired_sources, None);
 
                                     match background_scene {
                                         Some (ref scene) => {
-                                            add_resources(&mut background_sources, &output_device, &scene, &speaker_positions);
+                                            add_resources(&mut background_sources, &master_bus, &scene, &speaker_positions, config.metro_step_ms, 0);
                                             play(&mut background_sources);
-                                            set_volume(&mut background_sources, config.default_level);
+                                            // Stays silent until waking when loaded during sleep
+                                            match sleep::is_audible(&sleep) {
+                                                true    => set_volume(&mut background_sources, master_level),
+                                                false   => set_volume(&mut background_sources, 0.0),
+                                            }
                                         },
                                         None => (),
                                     }
                                 },
-                                soundscape::Cmd::Retire => {
-                                    println!("Executing retire command at step: {}", elapsed_ms);
-                                    retire_resources(&mut active_sources, &mut retired_sources);
+                                soundscape::Cmd::Retire (load) => {
+                                    if load != scene_load {
+                                        println!("Ignored retire command for a scene which has been replaced.");
+                                    }
+                                    else {
+                                        println!("Executing retire command at step: {}", elapsed_ms);
+                                        retire_resources(&mut active_sources, &mut retired_sources, None);
+                                        // Voices already playing finish on their own
+                                        scene_events.clear();
+                                    }
                                 }
                                 soundscape::Cmd::CheckSchedule => {
                                     println!("Executing schedule check at step: {}", elapsed_ms);
-                                    match config::is_in_schedule_now(&config, &config::localtime()) {
+                                    let now = localtime::now(&zone);
+                                    if let Some (ref active_schedule) = play_schedule {
+                                        if schedule_logged_date != Some(now.date()) {
+                                            schedule_logged_date = Some(now.date());
+                                            println!("Schedule for {}", schedule::describe_day(active_schedule, now.date()));
+                                        }
+                                    }
+                                    match schedule_plan.is_live {
                                         true => {
                                             if !is_schedule_live {
-                                                println!("Soundscape going live according to schedule. At {:?}", config::localtime());
+                                                println!("Soundscape going live according to schedule. At {}", now);
+                                                if let Some (command) = sleep::wake(&mut sleep, elapsed_ms) {
+                                                    wake_background(&sleep, &mut background_sources, &mut future_commands, master_level, elapsed_ms);
+                                                    future_commands.push(command);
+                                                }
                                             }
                                             is_schedule_live = true;
                                         },
                                         false => {
                                             if is_schedule_live {
-                                            println!("Soundscape is going to sleep according to schedule. At {:?}", config::localtime());
+                                                println!("Soundscape is going to sleep according to schedule. At {}", now);
+                                                // Starting up outside the schedule goes straight to sleep
+                                                let command = sleep::begin(&mut sleep, &mut future_commands, elapsed_ms, is_schedule_checked);
+                                                future_commands.push(command);
                                             }
                                             is_schedule_live = false;
                                         }
                                     }
-                                    // repeat the check in about 10 second
-                                    future_commands.push(soundscape::check_shedule(elapsed_ms + 10_000));
+                                    is_schedule_checked = true;
+                                }
+                                soundscape::Cmd::Sleep => {
+                                    println!("Executing sleep command at step: {}", elapsed_ms);
+                                    let fade_ms = sleep::fade_out(&mut active_sources, sleep.fade_ms)
+                                        .max(sleep::fade_out(&mut background_sources, sleep.fade_ms));
+                                    sleep.phase = sleep::Phase::FadingOut;
+                                    future_commands.push(soundscape::asleep_at(elapsed_ms + fade_ms as i64 + step_size_ms));
+                                }
+                                soundscape::Cmd::Asleep => {
+                                    println!("Executing asleep command at step: {}", elapsed_ms);
+                                    if sleep::settle(&mut sleep) {
+                                        pause(&mut active_sources);
+                                    }
+                                    else {
+                                        retire_resources(&mut active_sources, &mut retired_sources, Some ((0.0, rodiox::source::FadeShape::Linear)));
+                                        scene_events.clear();
+                                    }
+                                    match sleep.mode {
+                                        config::SleepMode::Pause => pause(&mut background_sources),
+                                        config::SleepMode::Release => retire_resources(&mut background_sources, &mut retired_sources, Some ((0.0, rodiox::source::FadeShape::Linear))),
+                                    }
+                                    if sleep.is_wake_pending {
+                                        if let Some (command) = sleep::wake(&mut sleep, elapsed_ms) {
+                                            wake_background(&sleep, &mut background_sources, &mut future_commands, master_level, elapsed_ms);
+                                            future_commands.push(command);
+                                        }
+                                    }
+                                }
+                                soundscape::Cmd::Resume => {
+                                    println!("Executing resume command at step: {}", elapsed_ms);
+                                    match sleep::resume(&mut sleep, elapsed_ms) {
+                                        Some (held) => {
+                                            sleep::fade_in(&mut active_sources, sleep.fade_ms, master_level);
+                                            future_commands.extend(held);
+                                        },
+                                        None => {
+                                            let n = match sleep.wake {
+                                                config::WakeMode::Restart => playlist::first(&mut playlist, localtime::seconds_of_day(&zone)),
+                                                config::WakeMode::Resume => sleep.current_scene,
+                                            };
+                                            future_commands.push(soundscape::load_at(n, soundscape::Origin::Internal, elapsed_ms));
+                                        },
+                                    }
                                 }
                             }
                         },
@@ -409,19 +804,34 @@ fn main() {
                 }
 
 
-                let t = dynamic_curve.step_t * dynamic_curve.step;
-                let volume = dynamic_curve.spline.point( t );
-                manage_source_activity(&mut active_sources, volume, config.default_level, master_activity_timer, is_schedule_live);
-                manage_source_activity(&mut background_sources, volume, config.default_level, master_activity_timer, is_schedule_live);
+                let mut curve_values = soundscape::structure_values(&structures);
+                inputs::apply(&control_inputs, &mut curve_values);
+                // Sleep fades and pauses sources itself
+                if sleep::is_audible(&sleep) {
+                    manage_source_activity(&mut active_sources, &curve_values, master_level, true, tick_ms, &tempo_grid, &transport);
+                    manage_source_activity(&mut background_sources, &curve_values, master_level, true, tick_ms, &None, &transport);
+                }
 
-                // run fades and remove any retired sources which have finished their fade out.
-                for s in &mut retired_sources {
-                    soundscape::update(s);
+                if sleep::is_audible(&sleep) && is_events_playing {
+                    // Events take the voices the scenes' sources leave free
+                    let event_voice_limit = config.voice_limit.saturating_sub(active_sources.len() + background_sources.len() + retired_sources.len());
+                    for event in &mut scene_events {
+                        for _ in 0..events::advance(event, &curve_values, tick_ms) {
+                            events::trigger(event, &master_bus, &speaker_positions, master_level, &event_voices, event_voice_limit);
+                        }
+                    }
                 }
-                retired_sources.retain(|s| s.volume_updates > 0);
 
-                if elapsed_ms % 3000 == 0 {
-                    println!("v: {}, t: {}, step: {}, pending commands: {}", volume, t, elapsed_ms, future_commands.len());
+                // remove any retired sources which have finished their fade out.
+                retired_sources.retain(|s| soundscape::is_fading(s));
+
+                if elapsed_ms / 3000 != last_status_ms / 3000 {
+                    last_status_ms = elapsed_ms;
+                    // Without metering the status line takes its own reading
+                    if level_metering.is_none() {
+                        limiter_reduction_db = master_bus.take_gain_reduction_db();
+                    }
+                    println!("curves: {:?}, step: {}, pending commands: {}, limiter: -{:.1}dB", curve_values, elapsed_ms, future_commands.len(), limiter_reduction_db);
                 }
             }
         }
@@ -429,6 +839,29 @@ fn main() {
 
 }
 
+// Send an OSC packet to each address
+// Sign control and sync messages to other units with the time they are sent
+fn sign_now(secret: &Option<Vec<u8>>, packet: OscPacket) -> OscPacket {
+    access::sign(secret, packet, Utc::now().timestamp_millis())
+}
+
+fn broadcast(socket: &UdpSocket, packet: &OscPacket, addresses: &Vec<SocketAddrV4>) {
+    let message = match rosc::encoder::encode(packet) {
+        Ok (message) => message,
+        Err (e) => {
+            println!("Error encoding OSC packet {:?}: {:?}", packet, e);
+            return
+        }
+    };
+
+    for addr in addresses {
+        match socket.send_to(&message, addr) {
+            Ok (_) => (),
+            Err (e) => println!("Error sending to client: {}, reason: {}", addr, e),
+        }
+    }
+}
+
 fn route_osc(packet: OscPacket) -> OscEvent {
     match packet {
         OscPacket::Message(message) => {
@@ -465,18 +898,73 @@ fn route_osc(packet: OscPacket) -> OscEvent {
             else if message.addr == "/MasterAlive" {
                 match message.args {
                     Some (arguments) => {
-                        match arguments.first() {
-                            Some(&rosc::OscType::Long (time)) => {
-                                OscEvent::MasterAlive(time)
+                        match arguments.as_slice() {
+                            [rosc::OscType::Long (time), rosc::OscType::Long (term), rosc::OscType::Int (priority), rosc::OscType::Int (unit_id)] => {
+                                OscEvent::MasterAlive(*time, *term as u64, *priority as u32, *unit_id as u32)
+                            },
+                            // From a unit without elections, which ranks lowest in the current term
+                            [rosc::OscType::Long (time)] => {
+                                OscEvent::LegacyMasterAlive(*time)
                             },
                             _ => {
-                                println!("MasterAlive message requires Long");
+                                println!("MasterAlive message requires Long, Long, Int, Int");
                                 OscEvent::NoAction
                             }
                         }
                     },
                     None => {
-                        println!("No arguments in MasterAlive message, expected 1");
+                        println!("No arguments in MasterAlive message, expected 4");
+                        OscEvent::NoAction
+                    },
+                }
+            }
+            else if message.addr == "/Election" {
+                match message.args.as_ref().map(|args| args.as_slice()) {
+                    Some ([rosc::OscType::Long (term), rosc::OscType::Int (priority), rosc::OscType::Int (unit_id)]) => {
+                        OscEvent::Election(*term as u64, *priority as u32, *unit_id as u32)
+                    },
+                    _ => {
+                        println!("Election message requires Long, Int, Int, received: {:?}", message.args);
+                        OscEvent::NoAction
+                    },
+                }
+            }
+            else if message.addr == "/TimeRequest" {
+                match message.args.as_ref().map(|args| args.as_slice()) {
+                    Some ([rosc::OscType::Int (unit_id), rosc::OscType::Long (t0)]) => {
+                        OscEvent::TimeRequest(*unit_id as u32, *t0)
+                    },
+                    _ => {
+                        println!("TimeRequest message requires Int, Long, received: {:?}", message.args);
+                        OscEvent::NoAction
+                    },
+                }
+            }
+            else if message.addr == "/TimeReply" {
+                match message.args.as_ref().map(|args| args.as_slice()) {
+                    Some ([rosc::OscType::Int (unit_id), rosc::OscType::Long (t0), rosc::OscType::Long (t1), rosc::OscType::Long (t2)]) => {
+                        OscEvent::TimeReply(*unit_id as u32, *t0, *t1, *t2)
+                    },
+                    _ => {
+                        println!("TimeReply message requires Int, Long, Long, Long, received: {:?}", message.args);
+                        OscEvent::NoAction
+                    },
+                }
+            }
+            else if message.addr == "/StateRequest" {
+                match message.args.as_ref().map(|args| args.as_slice()) {
+                    Some ([rosc::OscType::Int (unit_id)]) => OscEvent::StateRequest(*unit_id as u32),
+                    _ => {
+                        println!("StateRequest message requires Int, received: {:?}", message.args);
+                        OscEvent::NoAction
+                    },
+                }
+            }
+            else if message.addr == "/StateSnapshot" {
+                match snapshot::from_args(message.args.as_ref().map(|args| args.as_slice()).unwrap_or(&[])) {
+                    Ok ((unit_id, state)) => OscEvent::StateSnapshot(unit_id, state),
+                    Err (e) => {
+                        println!("{}", e);
                         OscEvent::NoAction
                     },
                 }
@@ -487,9 +975,15 @@ fn route_osc(packet: OscPacket) -> OscEvent {
                         let mut arg_list = arg.iter();
                         let first = arg_list.next();
                         let second = arg_list.next();
+                        // How far into the scene to start, from the beginning if not given
+                        let offset = match arg_list.next() {
+                            Some (&rosc::OscType::Long(offset)) => Some(offset),
+                            Some (_) => None,
+                            None => Some(0),
+                        };
                         if first != None && second != None {
                             let scene_index = match first.unwrap() {
-                                &rosc::OscType::Int(index) => Some(index),
+                                &rosc::OscType::Int(index) if index >= 0 => Some(index),
                                 _ => None
                             };
 
@@ -498,11 +992,11 @@ fn route_osc(packet: OscPacket) -> OscEvent {
                                 _ => None
                             };
 
-                            if scene_index != None && delta != None {
-                                OscEvent::SceneChange(scene_index.unwrap() as usize, delta.unwrap() as i64)
+                            if scene_index != None && delta != None && offset != None {
+                                OscEvent::SceneChange(scene_index.unwrap() as usize, delta.unwrap() as i64, offset.unwrap())
                             }
                             else {
-                                println!("/ChangeScene requires int, int.");
+                                println!("/ChangeScene requires a scene index, long and an optional long.");
                                 OscEvent::NoAction
                             }
                         }
@@ -513,6 +1007,18 @@ fn route_osc(packet: OscPacket) -> OscEvent {
                     None => OscEvent::NoAction,
                 }
             }
+            else if message.addr.starts_with("/input/") {
+                let name = message.addr["/input/".len()..].to_string();
+                match message.args.as_ref().and_then(|args| args.first()) {
+                    Some (&rosc::OscType::Float(value))   => OscEvent::Input(name, value),
+                    Some (&rosc::OscType::Double(value))  => OscEvent::Input(name, value as f32),
+                    Some (&rosc::OscType::Int(value))     => OscEvent::Input(name, value as f32),
+                    _ => {
+                        println!("{} expected a float, but received: {:?}", message.addr, message.args);
+                        OscEvent::NoAction
+                    },
+                }
+            }
             else if message.addr == "/RefreshBackground" {
                 OscEvent::RefreshBackground
             }
@@ -529,66 +1035,95 @@ fn route_osc(packet: OscPacket) -> OscEvent {
 }
 
 // active_sources actions
-fn manage_source_activity(sources: &mut Vec<soundscape::SoundSource>, volume :f32, default_level :f32, master_activity_timer :i64, is_schedule_live: bool) {
+// Changes of activity wait for the next beat or bar when the scene has a tempo
+fn manage_source_activity(sources: &mut Vec<soundscape::SoundSource>, curve_values: &Vec<(String, f32)>, default_level :f32, is_schedule_live: bool, tick_ms: i64, grid: &Option<tempo::Grid>, transport: &transport::Transport) {
+    let now_ms = transport::now(transport);
     for c in sources {
-        soundscape::update(c); // execute volume fade steps
+        let value = soundscape::curve_value(curve_values, &c.curve);
+        c.band_gain = soundscape::band_gain(c, value);
+        soundscape::apply_modulations(c, curve_values);
+        soundscape::update_variants(c);
 
-        if is_schedule_live && c.max_threshold > volume && c.min_threshold < volume {
-            if c.is_live == false {
-                c.is_live = true;
-                let volume = default_level + c.gain;
-                let fade_steps = c.fade_in_steps;
-                soundscape::volume_fade(c, volume, fade_steps)
+        if let Some (is_live) = soundscape::update_activity(c, value, is_schedule_live, tick_ms) {
+            let at_ms = tempo::snap(grid, now_ms);
+            soundscape::schedule_change(c, is_live, at_ms);
+            // On a grid the new variant is lined up now to start on the beat or bar it goes live on
+            if is_live && grid.is_some() && c.pending_change.is_some() {
+                soundscape::change_variant_on_activation(c, Some (transport::to_audio_ms(transport, at_ms)));
             }
         }
-        else {
-            if c.is_live == true || is_schedule_live == false {
-                c.is_live = false;
-                let fade_steps = c.fade_out_steps;
-                soundscape::volume_fade(c, 0.0, fade_steps)
-            }
+
+        match soundscape::take_due_change(c, now_ms) {
+            Some (true) => {
+                if grid.is_none() {
+                    soundscape::change_variant_on_activation(c, None);
+                }
+                let volume = default_level + c.gain;
+                soundscape::fade_in(c, volume, now_ms)
+            },
+            Some (false) => {
+                let fade_ms = c.fade_out_ms;
+                soundscape::volume_fade(c, 0.0, fade_ms)
+            },
+            None => (),
         }
     }
 }
 
 // Load sound sources from config objects
-fn add_resources(active_sources: &mut Vec<soundscape::SoundSource>, output_device: &rodio::Device, scene: &config::Scene, speakers: &Vec<[f32; 3]>) {
+fn add_resources(active_sources: &mut Vec<soundscape::SoundSource>, master_bus: &rodiox::master_bus::MasterBus, scene: &config::Scene, speakers: &Vec<[f32; 3]>, metro_step_ms: u32, seek_ms: i64) {
     println!("Loading {}", scene.name);
     for res in &scene.resources {
         println!("Adding: {:?}", res);
-        let mut sound_source = soundscape::resource_to_sound_source(res, &output_device, &speakers);
-        let source =
-            config::res_to_file(&res.path).and_then(|file| {
-                match rodio::Decoder::new( BufReader::new(file) ) {
-                    Ok (decoder) => Ok(decoder),
-                    Err (e) => Err( format!("Error creating audio source for '{}': {}", res.path, e) ),
-                }
-            })
-            .expect("Error reading audio resource")
-            .fade_in(Duration::from_millis(50))
-            .buffered()
-            .repeat_infinite();
+        let mut sound_source = soundscape::resource_to_sound_source(res, config::resource_curve(scene, res), &master_bus, &speakers, metro_step_ms);
+        // Variants are converted to the format of the first so they can follow each other
+        let mut variants = Vec::new();
+        let mut variant_format = None;
+        for path in config::variant_paths(res) {
+            let decoder =
+                config::res_to_file(&path).and_then(|file| {
+                    match rodio::Decoder::new( BufReader::new(file) ) {
+                        Ok (decoder) => Ok(decoder),
+                        Err (e) => Err( format!("Error creating audio source for '{}': {}", path, e) ),
+                    }
+                })
+                .expect("Error reading audio resource");
+            let (channels, sample_rate) = *variant_format.get_or_insert((decoder.channels(), decoder.sample_rate()));
+            variants.push(rodio::source::UniformSourceIterator::<_, i16>::new(decoder, channels, sample_rate).buffered());
+        }
+        let mut switch = rodiox::source::VariantSwitch::new(variants, sound_source.variant_control.clone());
+        if seek_ms > 0 {
+            switch.seek(Duration::from_millis(seek_ms as u64));
+        }
+        let source = switch.fade_in(Duration::from_millis(50));
 
         // pause until a play command is executed
         sound_source.channel.set_volume(0.0);
         sound_source.channel.pause();
         sound_source.is_live = false;
 
-        match res.reverb {
-            Some (ref params) => sound_source.channel.append(source.reverb(Duration::from_millis(params.delay_ms), params.mix_t)),
-            None => sound_source.channel.append(source),
+        if let Some (ref params) = res.reverb {
+            sound_source.channel.set_reverb(Duration::from_millis(params.delay_ms), params.mix_t);
         }
+        sound_source.channel.append(source);
         active_sources.push(sound_source)
     }
 }
 
-// Move from active Vec to retired Vec and apply fade out to each channel
-fn retire_resources(active_sources: &mut Vec<soundscape::SoundSource>, retired_sources: &mut Vec<soundscape::SoundSource>) {
-    retired_sources.append(active_sources);
-    for s in retired_sources {
-        let fade_steps = s.fade_out_steps;
-        soundscape::volume_fade(s, -0.0, fade_steps);
+// Move from active Vec to retired Vec and apply fade out to each channel.
+// A transition fade replaces the sources' own fade out duration and shape.
+fn retire_resources(active_sources: &mut Vec<soundscape::SoundSource>, retired_sources: &mut Vec<soundscape::SoundSource>, transition_fade: Option<(f32, rodiox::source::FadeShape)>) {
+    for s in active_sources.iter_mut() {
+        let fade_ms = match transition_fade {
+            Some ((fade_ms, shape)) => {
+                s.fade_shape = shape;
+                fade_ms
+            },
+            None => s.fade_out_ms,
+        };
+        soundscape::volume_fade(s, -0.0, fade_ms);
     }
+    retired_sources.append(active_sources);
 }
 
 fn set_volume(channels: &mut Vec<soundscape::SoundSource>, volume: f32) {
@@ -597,8 +1132,40 @@ fn set_volume(channels: &mut Vec<soundscape::SoundSource>, volume: f32) {
     }
 }
 
+// Fade live sources to a new master level
+fn fade_to_level(sources: &mut Vec<soundscape::SoundSource>, level: f32) {
+    for s in sources {
+        if s.is_live {
+            let fade_ms = s.fade_in_ms;
+            soundscape::volume_fade(s, level + s.gain, fade_ms);
+        }
+    }
+}
+
+// Move loops part-way into their variants before they start
+fn start(channels: &mut Vec<soundscape::SoundSource>, audio_ms: i64) {
+    for c in channels {
+        c.channel.start_at(audio_ms);
+        c.channel.play()
+    }
+}
+
 fn play(channels: &mut Vec<soundscape::SoundSource>) {
     for c in channels {
         c.channel.play()
     }
 }
+
+fn pause(channels: &mut Vec<soundscape::SoundSource>) {
+    for c in channels {
+        c.channel.pause()
+    }
+}
+
+// Bring the background back when waking, reloading it if it was released
+fn wake_background(sleep: &sleep::Sleep, background_sources: &mut Vec<soundscape::SoundSource>, future_commands: &mut BinaryHeap<soundscape::FutureCmd>, default_level: f32, now_ms: i64) {
+    match sleep.mode {
+        config::SleepMode::Pause => sleep::fade_in(background_sources, sleep.fade_ms, default_level),
+        config::SleepMode::Release => future_commands.push(soundscape::load_background(now_ms)),
+    }
+}
@@ -0,0 +1,43 @@
+#[cfg(test)]
+mod main_test {
+    use {route_osc, OscEvent};
+    use rosc::{OscMessage, OscPacket, OscType};
+
+    fn change_scene(args: Vec<OscType>) -> OscEvent {
+        route_osc(OscPacket::Message(OscMessage { addr: "/ChangeScene".to_string(), args: Some (args) }))
+    }
+
+    #[test]
+    fn change_scene_offsets() {
+        match change_scene(vec![ OscType::Int(2), OscType::Long(60_000), OscType::Long(15_000) ]) {
+            OscEvent::SceneChange(2, 60_000, 15_000) => (),
+            other => panic!("Expected a scene change 15s in, received {:?}", other),
+        }
+        // Without an offset the scene starts from the beginning
+        match change_scene(vec![ OscType::Int(2), OscType::Long(60_000) ]) {
+            OscEvent::SceneChange(2, 60_000, 0) => (),
+            other => panic!("Expected a scene change from the start, received {:?}", other),
+        }
+        match change_scene(vec![ OscType::Int(2), OscType::Long(60_000), OscType::Float(1.5) ]) {
+            OscEvent::NoAction => (),
+            other => panic!("Expected no action, received {:?}", other),
+        }
+        match change_scene(vec![ OscType::Int(-1), OscType::Long(60_000) ]) {
+            OscEvent::NoAction => (),
+            other => panic!("Expected no action for a negative scene, received {:?}", other),
+        }
+    }
+
+    #[test]
+    fn legacy_heartbeats() {
+        let heartbeat = |args: Vec<OscType>| route_osc(OscPacket::Message(OscMessage { addr: "/MasterAlive".to_string(), args: Some (args) }));
+        match heartbeat(vec![ OscType::Long(5_000) ]) {
+            OscEvent::LegacyMasterAlive(5_000) => (),
+            other => panic!("Expected a legacy heartbeat, received {:?}", other),
+        }
+        match heartbeat(vec![ OscType::Long(5_000), OscType::Long(3), OscType::Int(1), OscType::Int(7) ]) {
+            OscEvent::MasterAlive(5_000, 3, 1, 7) => (),
+            other => panic!("Expected a heartbeat, received {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,58 @@
+#[cfg(test)]
+mod metering_test {
+    use metering::*;
+    use config::MeteringParams;
+    use rodiox::source::meter::ChannelLevel;
+    use rosc::{OscPacket, OscType};
+
+    fn metering() -> Metering {
+        from_config(&MeteringParams {
+            rate_ms:        100,
+            subscribers:    vec![],
+            sources:        None,
+            silence_db:     Some (-60.0),
+            silence_ms:     Some (300),
+            clip_db:        Some (-1.0),
+        })
+    }
+
+    fn level(peak: f32) -> ChannelLevel {
+        ChannelLevel { peak: peak, sum_squares: peak * peak, samples: 1 }
+    }
+
+    #[test]
+    fn silence_after_hold_time() {
+        let mut metering = metering();
+        let quiet = vec![level(0.5), level(0.0)];
+        assert_eq!(check_alarms(&mut metering, &quiet), vec![]);
+        assert_eq!(check_alarms(&mut metering, &quiet), vec![]);
+        assert_eq!(check_alarms(&mut metering, &quiet), vec![Alarm::Silence(1)]);
+        // Raised once
+        assert_eq!(check_alarms(&mut metering, &quiet), vec![]);
+        assert_eq!(check_alarms(&mut metering, &vec![level(0.5), level(0.1)]), vec![Alarm::SignalRestored(1)]);
+        // A short gap isn't silence
+        assert_eq!(check_alarms(&mut metering, &quiet), vec![]);
+        assert_eq!(check_alarms(&mut metering, &vec![level(0.5), level(0.1)]), vec![]);
+    }
+
+    #[test]
+    fn clip_and_clear() {
+        let mut metering = metering();
+        assert_eq!(check_alarms(&mut metering, &vec![level(0.95)]), vec![Alarm::Clip(0)]);
+        assert_eq!(check_alarms(&mut metering, &vec![level(1.0)]), vec![]);
+        assert_eq!(check_alarms(&mut metering, &vec![level(0.5)]), vec![Alarm::ClipCleared(0)]);
+        // Speakers are added as readings grow
+        assert_eq!(check_alarms(&mut metering, &vec![level(0.5), level(1.0)]), vec![Alarm::Clip(1)]);
+    }
+
+    #[test]
+    fn limiter_reading() {
+        match limiter_message(3.5) {
+            OscPacket::Message(message) => {
+                assert_eq!(message.addr, "/meter/limiter");
+                assert_eq!(message.args, Some (vec![ OscType::Float(3.5) ]));
+            },
+            _ => panic!("Expected a message"),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use rosc::{OscMessage, OscPacket, OscType};
+
+use config::MeteringParams;
+use rodiox::source::meter::ChannelLevel;
+
+mod metering_tests;
+
+// Metering state
+pub struct Metering {
+    pub rate_ms:            i64,
+    pub include_sources:    bool,
+    pub silence_db:         f32,
+    pub silence_ms:         i64,
+    pub clip_db:            f32,
+    timer_ms:               i64,
+    speakers:               Vec<SpeakerState>,
+}
+
+struct SpeakerState {
+    silent_for_ms:  i64,
+    is_silent:      bool,
+    is_clipping:    bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Alarm {
+    Silence(usize),
+    SignalRestored(usize),
+    Clip(usize),
+    ClipCleared(usize),
+}
+
+pub fn from_config(params: &MeteringParams) -> Metering {
+    Metering {
+        rate_ms:            params.rate_ms.max(1) as i64,
+        include_sources:    params.sources.unwrap_or(false),
+        silence_db:         params.silence_db.unwrap_or(-70.0),
+        silence_ms:         params.silence_ms.unwrap_or(5000) as i64,
+        clip_db:            params.clip_db.unwrap_or(-0.5),
+        timer_ms:           0,
+        speakers:           Vec::new(),
+    }
+}
+
+// Advance the metering timer, returns true when a reading is due
+pub fn tick(metering: &mut Metering, step_ms: i64) -> bool {
+    metering.timer_ms += step_ms;
+    if metering.timer_ms >= metering.rate_ms {
+        metering.timer_ms = 0;
+        true
+    }
+    else {
+        false
+    }
+}
+
+// Compare a reading against the alarm thresholds, returning any alarms which changed state
+pub fn check_alarms(metering: &mut Metering, levels: &Vec<ChannelLevel>) -> Vec<Alarm> {
+    while metering.speakers.len() < levels.len() {
+        metering.speakers.push(SpeakerState { silent_for_ms: 0, is_silent: false, is_clipping: false });
+    }
+
+    let mut alarms = Vec::new();
+    for (speaker, level) in levels.iter().enumerate() {
+        let state = &mut metering.speakers[speaker];
+        let peak_db = level.peak_db();
+
+        if peak_db < metering.silence_db {
+            state.silent_for_ms += metering.rate_ms;
+            if !state.is_silent && state.silent_for_ms >= metering.silence_ms {
+                state.is_silent = true;
+                alarms.push(Alarm::Silence(speaker));
+            }
+        }
+        else {
+            state.silent_for_ms = 0;
+            if state.is_silent {
+                state.is_silent = false;
+                alarms.push(Alarm::SignalRestored(speaker));
+            }
+        }
+
+        if peak_db >= metering.clip_db {
+            if !state.is_clipping {
+                state.is_clipping = true;
+                alarms.push(Alarm::Clip(speaker));
+            }
+        }
+        else if state.is_clipping {
+            state.is_clipping = false;
+            alarms.push(Alarm::ClipCleared(speaker));
+        }
+    }
+    alarms
+}
+
+// /meter/<speaker> peak_db rms_db
+pub fn speaker_message(speaker: usize, level: &ChannelLevel) -> OscPacket {
+    OscPacket::Message(OscMessage {
+        addr: format!("/meter/{}", speaker),
+        args: Some( vec![ OscType::Float(level.peak_db()), OscType::Float(level.rms_db()) ] ),
+    })
+}
+
+// /meter/source/<name> peak_db rms_db, taken from the loudest speaker the source feeds
+pub fn source_message(name: &str, levels: &Vec<ChannelLevel>) -> OscPacket {
+    let mut loudest = ChannelLevel::new();
+    for level in levels {
+        if level.peak > loudest.peak {
+            loudest = *level;
+        }
+    }
+    OscPacket::Message(OscMessage {
+        addr: format!("/meter/source/{}", name),
+        args: Some( vec![ OscType::Float(loudest.peak_db()), OscType::Float(loudest.rms_db()) ] ),
+    })
+}
+
+// /meter/limiter reduction_db, the most gain the limiter took off since the last reading
+pub fn limiter_message(reduction_db: f32) -> OscPacket {
+    OscPacket::Message(OscMessage {
+        addr: "/meter/limiter".to_string(),
+        args: Some( vec![ OscType::Float(reduction_db) ] ),
+    })
+}
+
+// /meter/alarm/<kind> speaker is_active
+pub fn alarm_message(alarm: &Alarm) -> OscPacket {
+    let (kind, speaker, is_active) = match *alarm {
+        Alarm::Silence(speaker)         => ("silence", speaker, true),
+        Alarm::SignalRestored(speaker)  => ("silence", speaker, false),
+        Alarm::Clip(speaker)            => ("clip", speaker, true),
+        Alarm::ClipCleared(speaker)     => ("clip", speaker, false),
+    };
+    OscPacket::Message(OscMessage {
+        addr: format!("/meter/alarm/{}", kind),
+        args: Some( vec![ OscType::Int(speaker as i32), OscType::Int(is_active as i32) ] ),
+    })
+}
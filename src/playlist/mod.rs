@@ -0,0 +1,136 @@
+use config;
+use config::{PlaylistMode, Soundscape, VariantChange, VariantMode};
+use rng;
+use variants;
+use variants::VariantPicker;
+
+mod playlist_tests;
+
+// Playlist
+// Chooses the scene which follows the current one. Shuffle and weighted modes share the
+// variant picker used for resource files.
+pub struct Playlist {
+    pub mode:       PlaylistMode,
+    pub scene_count: usize,
+    picker:         Option<VariantPicker>,
+    slots:          Vec<(u32, Vec<usize>)>, // seconds from midnight, scene indices
+}
+
+pub fn sequential(scene_count: usize) -> Playlist {
+    Playlist {
+        mode:           PlaylistMode::Sequential,
+        scene_count:    scene_count,
+        picker:         None,
+        slots:          Vec::new(),
+    }
+}
+
+pub fn from_config(config: &Soundscape) -> Result<Playlist, String> {
+    let params = match config.playlist {
+        Some (ref params) => params,
+        None => return Ok(sequential(config.scenes.len())),
+    };
+    let rng = match params.seed {
+        Some (seed) => rng::seeded(seed),
+        None => rng::from_time(),
+    };
+
+    let picker = match params.mode {
+        PlaylistMode::Shuffle => Some(variants::new(vec![1.0; config.scenes.len()], VariantMode::ShuffleBag, VariantChange::Activation, rng)),
+        PlaylistMode::Weighted => {
+            let weights = params.weights.clone().unwrap_or(vec![1.0; config.scenes.len()]);
+            if weights.len() != config.scenes.len() {
+                return Err(format!("Playlist has {} weights for {} scenes", weights.len(), config.scenes.len()))
+            }
+            Some(variants::new(weights, VariantMode::Weighted, VariantChange::Activation, rng))
+        },
+        _ => None,
+    };
+
+    let mut slots = Vec::new();
+    if let Some (ref params_slots) = params.slots {
+        for slot in params_slots {
+            let start = config::parse_time_of_day(&slot.start)?;
+            let mut scenes = Vec::new();
+            for scene in &slot.scenes {
+                match config.scenes.iter().position(|s| s == scene) {
+                    Some (index) => scenes.push(index),
+                    None => return Err(format!("Playlist slot at {} uses '{}' which is not in the scenes list", slot.start, scene)),
+                }
+            }
+            slots.push((start, scenes));
+        }
+    }
+    slots.sort_by_key(|&(start, _)| start);
+    if params.mode == PlaylistMode::TimeOfDay && slots.is_empty() {
+        return Err("Playlist mode time_of_day requires slots".to_string())
+    }
+
+    Ok(Playlist {
+        mode:           params.mode,
+        scene_count:    config.scenes.len(),
+        picker:         picker,
+        slots:          slots,
+    })
+}
+
+// Scenes for the slot covering the time of day, the last slot carries on past midnight
+fn slot_scenes(playlist: &Playlist, seconds_of_day: u32) -> Option<&Vec<usize>> {
+    playlist.slots.iter()
+        .filter(|&&(start, _)| start <= seconds_of_day)
+        .last()
+        .or_else(|| playlist.slots.last())
+        .map(|&(_, ref scenes)| scenes)
+}
+
+// Choose the scene to start with
+pub fn first(playlist: &mut Playlist, seconds_of_day: u32) -> usize {
+    match playlist.mode {
+        PlaylistMode::Sequential => 0,
+        // Any index outside the playlist gives the first scene of the slot
+        PlaylistMode::TimeOfDay => {
+            let outside = playlist.scene_count;
+            next(playlist, outside, seconds_of_day)
+        },
+        _ => next(playlist, 0, seconds_of_day),
+    }
+}
+
+// Choose the scene to follow the current scene
+pub fn next(playlist: &mut Playlist, current: usize, seconds_of_day: u32) -> usize {
+    if playlist.scene_count == 0 {
+        return 0
+    }
+    let next = match playlist.mode {
+        PlaylistMode::Sequential => (current + 1) % playlist.scene_count,
+        PlaylistMode::Shuffle | PlaylistMode::Weighted => match playlist.picker {
+            Some (ref mut picker) => variants::pick(picker),
+            None => (current + 1) % playlist.scene_count,
+        },
+        PlaylistMode::TimeOfDay => match slot_scenes(playlist, seconds_of_day) {
+            // Step through the slot's scenes, starting at the first when arriving from another slot
+            Some (scenes) if !scenes.is_empty() => match scenes.iter().position(|&s| s == current) {
+                Some (i) => scenes[(i + 1) % scenes.len()],
+                None => scenes[0],
+            },
+            _ => (current + 1) % playlist.scene_count,
+        },
+    };
+    next.min(playlist.scene_count - 1)
+}
+
+// Choose the scene to follow the current scene ending at end_ms, where load_ms gives when a
+// scene would load to follow it. Time of day slots are looked up at that load time, which
+// depends on the overlap of the scene chosen, so a slot starting during the overlap is found
+// from a first choice made at the end.
+pub fn next_loading<L, S>(playlist: &mut Playlist, current: usize, end_ms: i64, load_ms: L, seconds_of_day_at: S) -> usize
+    where L: Fn(usize) -> i64, S: Fn(i64) -> u32
+{
+    match playlist.mode {
+        PlaylistMode::TimeOfDay => {
+            let first_choice = next(playlist, current, seconds_of_day_at(end_ms));
+            next(playlist, current, seconds_of_day_at(load_ms(first_choice)))
+        },
+        _ => next(playlist, current, seconds_of_day_at(end_ms)),
+    }
+}
@@ -0,0 +1,80 @@
+#[cfg(test)]
+mod playlist_test {
+    use playlist::*;
+    use config::*;
+
+    fn test_config(params: PlaylistParams) -> Soundscape {
+        Soundscape {
+            listen_addr:            Address { host: "127.0.0.1".to_string(), port: 4000 },
+            subscribers:            vec![],
+            osc_access:             None,
+            scenes:                 vec!["a.yml".to_string(), "b.yml".to_string(), "calm.yml".to_string()],
+            metro_step_ms:          10,
+            voice_limit:            16,
+            default_level:          1.0,
+            background_scene:       None,
+            speaker_positions:      Speakers { positions: vec![] },
+            ignore_extra_speakers:  None,
+            is_fallback_slave:      None,
+            election:               None,
+            clock_sync:             None,
+            timezone:               None,
+            daily_schedule:         None,
+            schedule:               None,
+            limiter:                None,
+            metering:               None,
+            output:                 None,
+            inputs:                 None,
+            playlist:               Some (params),
+            sleep:                  None,
+        }
+    }
+
+    #[test]
+    fn shuffle_plays_each_scene() {
+        let config = test_config(PlaylistParams { mode: PlaylistMode::Shuffle, weights: None, slots: None, seed: Some (5) });
+        let mut playlist = from_config(&config).unwrap();
+        let mut played: Vec<usize> = (0..3).map(|_| next(&mut playlist, 0, 0)).collect();
+        played.sort();
+        assert_eq!(played, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn time_of_day() {
+        let slots = vec![
+            PlaylistSlot { start: "08:00:00".to_string(), scenes: vec!["a.yml".to_string(), "b.yml".to_string()] },
+            PlaylistSlot { start: "22:00:00".to_string(), scenes: vec!["calm.yml".to_string()] },
+        ];
+        let config = test_config(PlaylistParams { mode: PlaylistMode::TimeOfDay, weights: None, slots: Some (slots), seed: None });
+        let mut playlist = from_config(&config).unwrap();
+        let (morning, night) = (9 * 3600, 23 * 3600);
+        assert_eq!(next(&mut playlist, 0, morning), 1);
+        assert_eq!(next(&mut playlist, 1, morning), 0);
+        assert_eq!(next(&mut playlist, 2, morning), 0);
+        assert_eq!(next(&mut playlist, 0, night), 2);
+        // The night slot carries on past midnight
+        assert_eq!(next(&mut playlist, 0, 3600), 2);
+    }
+
+    #[test]
+    fn slot_at_next_load() {
+        let slots = vec![
+            PlaylistSlot { start: "08:00:00".to_string(), scenes: vec!["a.yml".to_string()] },
+            PlaylistSlot { start: "22:00:00".to_string(), scenes: vec!["calm.yml".to_string()] },
+        ];
+        let config = test_config(PlaylistParams { mode: PlaylistMode::TimeOfDay, weights: None, slots: Some (slots), seed: None });
+        let mut playlist = from_config(&config).unwrap();
+        let seconds_at = |ms: i64| (ms / 1000) as u32;
+        // The scene ends just after the night slot starts, but the next loads before it
+        let end_ms = 22 * 3600 * 1000 + 5_000;
+        assert_eq!(next_loading(&mut playlist, 0, end_ms, |_| end_ms - 10_000, &seconds_at), 0);
+        assert_eq!(next_loading(&mut playlist, 0, end_ms, |_| end_ms - 1_000, &seconds_at), 2);
+    }
+
+    #[test]
+    fn unknown_slot_scenes() {
+        let slots = vec![ PlaylistSlot { start: "08:00:00".to_string(), scenes: vec!["missing.yml".to_string()] } ];
+        let config = test_config(PlaylistParams { mode: PlaylistMode::TimeOfDay, weights: None, slots: Some (slots), seed: None });
+        assert!(from_config(&config).is_err());
+    }
+}
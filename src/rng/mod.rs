@@ -0,0 +1,49 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Small seedable random number generator (xorshift64*).
+// Sequences are reproducible from the seed, which matters more here than statistical quality.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+pub fn seeded(seed: u64) -> Rng {
+    // A zero state would only ever produce zeros
+    let state = match seed {
+        0 => 0x9E37_79B9_7F4A_7C15,
+        s => s,
+    };
+    let mut rng = Rng { state: state };
+    // Mix the seed so nearby seeds give unrelated sequences
+    for _ in 0..4 {
+        next_u64(&mut rng);
+    }
+    rng
+}
+
+// Seeded from the system clock, for when a reproducible sequence isn't asked for
+pub fn from_time() -> Rng {
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|t| t.as_secs() ^ ((t.subsec_nanos() as u64) << 32))
+        .unwrap_or(0);
+    seeded(seed)
+}
+
+pub fn next_u64(rng: &mut Rng) -> u64 {
+    let mut x = rng.state;
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    rng.state = x;
+    x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+}
+
+// Uniform in [0, 1)
+pub fn next_f32(rng: &mut Rng) -> f32 {
+    (next_u64(rng) >> 40) as f32 / (1u64 << 24) as f32
+}
+
+// Uniform in [min, max)
+pub fn range(rng: &mut Rng, min: f32, max: f32) -> f32 {
+    min + (max - min) * next_f32(rng)
+}
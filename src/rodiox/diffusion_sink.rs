@@ -1,16 +1,48 @@
+use rodiox::dynamic_mixer::DynamicMixerController;
+use rodiox::master_bus::MasterBus;
+use rodiox::source::{Fade, FadeShape, Meter, Param, Reverb, SmoothGain, StartGate, VariableSpeed};
+use rodiox::source::clock::AudioClock;
 use rodiox::source::diffusion::Diffusion;
+use rodiox::source::meter::{ChannelLevel, LevelMeter};
 use std::f32;
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use rodio::Device;
 use rodio::Sample;
-use rodio::Sink;
 use rodio::Source;
 
+/// Plays sounds positioned in 3 dimensional space onto the master bus.
+///
+/// Behaves like a `rodio::Sink` but mixes into a `MasterBus` instead of opening its own stream on
+/// the output device.
 pub struct DiffusionSink {
-    sink: Sink,
+    bus: Arc<DynamicMixerController<f32>>,
+    clock: Arc<AudioClock>,
+    start_ms: Arc<AtomicUsize>,
+    controls: Arc<Controls>,
     positions: Arc<Mutex<SoundPositions>>,
+    meter: Arc<LevelMeter>,
+    speed: Arc<Param>,
+    modulation_gain: Arc<Param>,
+    reverb_delay: Duration,
+    reverb_mix: Arc<Param>,
+    detached: bool,
+}
+
+struct Controls {
+    pause: AtomicBool,
+    fade: Mutex<Option<FadeCommand>>,
+    // f32 bits of the gain last applied in the audio thread
+    gain: AtomicUsize,
+    fading: AtomicBool,
+    stopped: AtomicBool,
+}
+
+struct FadeCommand {
+    target: f32,
+    duration: Duration,
+    shape: FadeShape,
 }
 
 struct SoundPositions {
@@ -22,14 +54,29 @@ impl DiffusionSink {
     /// Builds a new `DiffusionSink`.
     #[inline]
     pub fn new(
-        device: &Device, emitter_position: [f32; 3], speakers: Vec<[f32; 3]>
+        bus: &MasterBus, emitter_position: [f32; 3], speakers: Vec<[f32; 3]>
     ) -> DiffusionSink {
         DiffusionSink {
-            sink: Sink::new(device),
+            bus: bus.controller(),
+            clock: bus.clock(),
+            start_ms: Arc::new(AtomicUsize::new(0)),
+            controls: Arc::new(Controls {
+                pause: AtomicBool::new(false),
+                fade: Mutex::new(None),
+                gain: AtomicUsize::new(1f32.to_bits() as usize),
+                fading: AtomicBool::new(false),
+                stopped: AtomicBool::new(false),
+            }),
             positions: Arc::new(Mutex::new(SoundPositions {
                 emitter_position,
                 speakers,
             })),
+            meter: Arc::new(LevelMeter::new()),
+            speed: Param::new(1.0),
+            modulation_gain: Param::new(1.0),
+            reverb_delay: Duration::from_millis(0),
+            reverb_mix: Param::new(0.0),
+            detached: false,
         }
     }
 
@@ -38,12 +85,58 @@ impl DiffusionSink {
         self.positions.lock().unwrap().emitter_position = pos;
     }
 
+    /// Returns the position of the sound emitter.
+    pub fn emitter_position(&self) -> [f32; 3] {
+        self.positions.lock().unwrap().emitter_position
+    }
+
     /// Sets the position of the left ear in 3 dimensional space.
     pub fn set_speaker_positions(&mut self, pos: Vec<[f32; 3]>) {
         self.positions.lock().unwrap().speakers = pos;
     }
 
-    /// Appends a sound to the queue of sounds to play.
+    /// Sets the delay and mix of the reverb applied to sounds appended after this call.
+    pub fn set_reverb(&mut self, delay: Duration, mix: f32) {
+        self.reverb_delay = delay;
+        self.reverb_mix.set(mix);
+    }
+
+    /// Changes the reverb mix of the sounds already playing.
+    pub fn set_reverb_mix(&self, mix: f32) {
+        self.reverb_mix.set(mix);
+    }
+
+    /// Changes the playback speed, `1.0` plays at the original speed and pitch.
+    pub fn set_speed(&self, factor: f32) {
+        self.speed.set(factor);
+    }
+
+    /// Sets a gain applied on top of the volume, for continuous modulation.
+    ///
+    /// Changes glide over a few milliseconds and don't interrupt fades.
+    pub fn set_modulation_gain(&self, gain: f32) {
+        self.modulation_gain.set(gain);
+    }
+
+    /// Returns the modulation gain last set.
+    pub fn modulation_gain(&self) -> f32 {
+        self.modulation_gain.get()
+    }
+
+    /// Returns the playback speed last set.
+    pub fn speed(&self) -> f32 {
+        self.speed.get()
+    }
+
+    /// Holds sounds silent until the master bus clock reaches `audio_ms`.
+    ///
+    /// Sounds given the same start time begin on the same frame, so loops stay in phase. Sounds
+    /// already playing are held again until the new time.
+    pub fn start_at(&self, audio_ms: i64) {
+        self.start_ms.store(audio_ms.max(0) as usize, Ordering::Relaxed);
+    }
+
+    /// Adds a sound to the master bus, positioned and controlled by this sink.
     #[inline]
     pub fn append<S>(&self, source: S)
     where
@@ -51,7 +144,14 @@ impl DiffusionSink {
         S::Item: Sample + Send + Debug,
     {
         let positions = self.positions.clone();
+        let controls = self.controls.clone();
         let pos_lock = self.positions.lock().unwrap();
+        let source = StartGate::new(source, self.clock.clone(), self.start_ms.clone());
+        let source = Reverb::new(
+            VariableSpeed::new(source, self.speed.clone()),
+            self.reverb_delay,
+            self.reverb_mix.clone(),
+        );
         let source = Diffusion::new(
             source,
             pos_lock.emitter_position,
@@ -59,17 +159,42 @@ impl DiffusionSink {
         ).periodic_access(Duration::from_millis(10), move |i| {
             let pos = positions.lock().unwrap();
             i.set_positions(pos.emitter_position, &pos.speakers);
-        });
-        self.sink.append(source);
+        })
+        .pausable(self.is_paused());
+
+        let source = Fade::new(source, self.volume())
+            .stoppable()
+            .periodic_access(Duration::from_millis(5), move |src| {
+                if controls.stopped.load(Ordering::SeqCst) {
+                    src.stop();
+                }
+                else {
+                    let fade = src.inner_mut();
+                    if let Some(command) = controls.fade.lock().unwrap().take() {
+                        fade.fade_to(command.target, command.duration, command.shape);
+                    }
+                    controls.gain.store(fade.gain().to_bits() as usize, Ordering::Relaxed);
+                    controls.fading.store(fade.is_fading(), Ordering::SeqCst);
+                    fade.inner_mut().set_paused(controls.pause.load(Ordering::SeqCst));
+                }
+            });
+        let source = SmoothGain::new(source, self.modulation_gain.clone(), Duration::from_millis(20));
+        self.bus.add(Meter::new(source, self.meter.clone()));
+    }
+
+    /// Returns the level this sink has contributed to each speaker since the last call.
+    pub fn take_levels(&self) -> Vec<ChannelLevel> {
+        self.meter.take_levels()
     }
 
     // Gets the volume of the sound.
     ///
     /// The value `1.0` is the "normal" volume (unfiltered input). Any value other than 1.0 will
-    /// multiply each sample by this value.
+    /// multiply each sample by this value. During a fade this is the gain most recently applied
+    /// by the audio thread.
     #[inline]
     pub fn volume(&self) -> f32 {
-        self.sink.volume()
+        f32::from_bits(self.controls.gain.load(Ordering::Relaxed) as u32)
     }
 
     /// Changes the volume of the sound.
@@ -78,7 +203,22 @@ impl DiffusionSink {
     /// multiply each sample by this value.
     #[inline]
     pub fn set_volume(&mut self, value: f32) {
-        self.sink.set_volume(value);
+        self.fade_to(value, Duration::from_millis(0), FadeShape::Linear);
+        self.controls.gain.store(value.to_bits() as usize, Ordering::Relaxed);
+    }
+
+    /// Fades from the current volume to `target` over `duration`.
+    ///
+    /// The fade is applied per sample in the audio thread, replacing any fade already in progress.
+    pub fn fade_to(&mut self, target: f32, duration: Duration, shape: FadeShape) {
+        let mut pending = self.controls.fade.lock().unwrap();
+        *pending = Some(FadeCommand { target, duration, shape });
+        self.controls.fading.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true while a fade is pending or in progress.
+    pub fn is_fading(&self) -> bool {
+        self.controls.fading.load(Ordering::SeqCst) || self.controls.fade.lock().unwrap().is_some()
     }
 
     /// Resumes playback of a paused sound.
@@ -86,7 +226,7 @@ impl DiffusionSink {
     /// No effect if not paused.
     #[inline]
     pub fn play(&self) {
-        self.sink.play();
+        self.controls.pause.store(false, Ordering::SeqCst);
     }
 
     /// Pauses playback of this sink.
@@ -95,31 +235,34 @@ impl DiffusionSink {
     ///
     /// A paused sound can be resumed with `play()`.
     pub fn pause(&self) {
-        self.sink.pause();
+        self.controls.pause.store(true, Ordering::SeqCst);
     }
 
     /// Gets if a sound is paused
     ///
     /// Sounds can be paused and resumed using pause() and play(). This gets if a sound is paused.
     pub fn is_paused(&self) -> bool {
-        self.sink.is_paused()
+        self.controls.pause.load(Ordering::SeqCst)
     }
 
     /// Destroys the sink without stopping the sounds that are still playing.
     #[inline]
-    pub fn detach(self) {
-        self.sink.detach();
+    pub fn detach(mut self) {
+        self.detached = true;
     }
 
-    /// Sleeps the current thread until the sound ends.
+    /// Stops the sounds playing in this sink, removing them from the master bus.
     #[inline]
-    pub fn sleep_until_end(&self) {
-        self.sink.sleep_until_end();
+    pub fn stop(&self) {
+        self.controls.stopped.store(true, Ordering::SeqCst);
     }
+}
 
-    /// Returns true if this sink has no more sounds to play.
+impl Drop for DiffusionSink {
     #[inline]
-    pub fn empty(&self) -> bool {
-        self.sink.empty()
+    fn drop(&mut self) {
+        if !self.detached {
+            self.stop();
+        }
     }
 }
@@ -0,0 +1,92 @@
+use rodiox::dynamic_mixer::{self, DynamicMixer, DynamicMixerController};
+use rodiox::source::{Clock, Limiter, Meter};
+use rodiox::source::clock::AudioClock;
+use rodiox::source::limiter::{LimiterMeter, LimiterSettings};
+use rodiox::source::meter::{ChannelLevel, LevelMeter};
+use std::sync::Arc;
+use rodio::Source;
+use rodio::source::Zero;
+
+/// The signal chain at the output of a `MasterBus`.
+pub type MasterBusOutput = Clock<Meter<Limiter<DynamicMixer<f32>>>>;
+
+/// Mixes every sound played by the soundscape into a single stream on the output device.
+///
+/// The mixed signal passes through a look-ahead limiter so overlapping sources can't clip the output.
+/// The level of each output channel is metered after the limiter and the frames consumed by the
+/// device drive an `AudioClock`.
+pub struct MasterBus {
+    mixer: Arc<DynamicMixerController<f32>>,
+    clock: Arc<AudioClock>,
+    limiter_meter: Arc<LimiterMeter>,
+    level_meter: Arc<LevelMeter>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl MasterBus {
+    /// Builds a new `MasterBus`, the returned output should be played on the output device.
+    pub fn new(channels: u16, sample_rate: u32, limiter: &LimiterSettings) -> (MasterBus, MasterBusOutput) {
+        let (mixer, output) = dynamic_mixer::mixer::<f32>(channels, sample_rate);
+
+        // The mixer ends when it runs out of sources, keep a silent source on the bus so it never does.
+        mixer.add(Zero::<f32>::new(channels, sample_rate));
+
+        let limited = Limiter::new(output, limiter);
+        let limiter_meter = limited.meter();
+        let level_meter = Arc::new(LevelMeter::new());
+        let clock = Arc::new(AudioClock::new());
+        let output = Clock::new(Meter::new(limited, level_meter.clone()), clock.clone());
+
+        let bus = MasterBus {
+            mixer,
+            clock,
+            limiter_meter,
+            level_meter,
+            channels,
+            sample_rate,
+        };
+        (bus, output)
+    }
+
+    /// Adds a new source to the bus.
+    #[inline]
+    pub fn add<S>(&self, source: S)
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        self.mixer.add(source);
+    }
+
+    /// Returns a handle for adding sources to the bus.
+    #[inline]
+    pub fn controller(&self) -> Arc<DynamicMixerController<f32>> {
+        self.mixer.clone()
+    }
+
+    /// Returns the largest gain reduction, in dB, applied by the limiter since the last call.
+    pub fn take_gain_reduction_db(&self) -> f32 {
+        self.limiter_meter.take_max_reduction_db()
+    }
+
+    /// Returns a handle to the clock advanced by the output device consuming the bus.
+    #[inline]
+    pub fn clock(&self) -> Arc<AudioClock> {
+        self.clock.clone()
+    }
+
+    /// Returns the level of each output channel measured since the last call.
+    pub fn take_levels(&self) -> Vec<ChannelLevel> {
+        self.level_meter.take_levels()
+    }
+
+    #[inline]
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    #[inline]
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+}
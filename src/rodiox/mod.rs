@@ -1,3 +1,7 @@
 pub mod diffusion_sink;
 pub mod dynamic_mixer;
+pub mod master_bus;
+pub mod output;
 pub mod source;
+
+mod rodiox_tests;
@@ -0,0 +1,267 @@
+//! Plays the master bus on a chosen output device with a chosen format.
+//!
+//! `rodio::play_raw` always opens the device with its default format, so the stream is built with
+//! cpal directly. One event loop runs for the life of the output and the source is shared between
+//! the streams built on it, so a lost device can be reopened without rebuilding the bus.
+//!
+//! cpal 0.8 has no way to request a buffer size, streams use the device's default buffer.
+
+use cpal;
+use cpal::{EventLoop, Format, SampleFormat, StreamData, StreamId, SupportedFormat, UnknownTypeOutputBuffer};
+use rodio::Device;
+use rodio::Source;
+use rodiox::source::clock::AudioClock;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Requested output device and format, any `None` falls back to the device's default.
+#[derive(Clone, Debug)]
+pub struct OutputSettings {
+    pub device:         Option<String>,
+    pub sample_rate:    Option<u32>,
+    pub channels:       Option<u16>,
+}
+
+type SharedSource = Arc<Mutex<Box<Source<Item = f32> + Send>>>;
+
+/// A stream playing a source on an output device.
+pub struct Output {
+    source: SharedSource,
+    event_loop: Arc<EventLoop>,
+    // The stream playing the source, replaced when the device is reopened
+    stream: Arc<Mutex<Option<StreamId>>>,
+    device_name: String,
+    format: Format,
+}
+
+/// Prints each output device and the formats it supports.
+pub fn list_devices() {
+    let default_name = ::rodio::default_output_device().map(|d| d.name());
+    for device in ::rodio::output_devices() {
+        let name = device.name();
+        let marker = match Some (&name) == default_name.as_ref() {
+            true    => " (default)",
+            false   => "",
+        };
+        println!("{}{}", name, marker);
+        match device.supported_output_formats() {
+            Ok (formats) => {
+                for format in formats {
+                    println!("    {} channels, {}-{} Hz, {:?}", format.channels, format.min_sample_rate.0, format.max_sample_rate.0, format.data_type);
+                }
+            },
+            Err (e) => println!("    Unable to list formats: {:?}", e),
+        }
+    }
+}
+
+/// Finds the named output device, falling back to the default device if it is missing.
+pub fn select_device(name: &Option<String>) -> Option<Device> {
+    let mut devices: Vec<Device> = ::rodio::output_devices().collect();
+    let names: Vec<String> = devices.iter().map(|d| d.name()).collect();
+    match choose_device(name, &names) {
+        Some (index) => Some(devices.swap_remove(index)),
+        None => ::rodio::default_output_device(),
+    }
+}
+
+/// Returns the index of the named device, or None when the default device should be used.
+pub fn choose_device(name: &Option<String>, names: &[String]) -> Option<usize> {
+    match *name {
+        Some (ref name) => {
+            let found = names.iter().position(|n| n == name);
+            if found.is_none() {
+                println!("Output device '{}' not found, falling back to the default device.", name);
+            }
+            found
+        },
+        None => None,
+    }
+}
+
+/// Chooses a format supported by the device which best matches the settings.
+pub fn select_format(device: &Device, settings: &OutputSettings) -> Result<Format, String> {
+    let default = device.default_output_format()
+        .map_err(|e| format!("Error retriving default format from '{}': {:?}", device.name(), e))?;
+
+    if settings.sample_rate.is_none() && settings.channels.is_none() {
+        return Ok(default);
+    }
+
+    let formats: Vec<SupportedFormat> = device.supported_output_formats()
+        .map_err(|e| format!("Error retriving formats from '{}': {:?}", device.name(), e))?
+        .collect();
+
+    match choose_format(&formats, settings, &default) {
+        Some (format) => Ok(format),
+        None => {
+            let channels = settings.channels.unwrap_or(default.channels);
+            let sample_rate = settings.sample_rate.unwrap_or(default.sample_rate.0);
+            println!("'{}' does not support {} channels at {} Hz, using default format {:?}", device.name(), channels, sample_rate, default);
+            Ok(default)
+        },
+    }
+}
+
+/// Picks from the supported formats one with the requested channels and sample rate, preferring
+/// float samples. Anything not requested is taken from the default format.
+pub fn choose_format(formats: &[SupportedFormat], settings: &OutputSettings, default: &Format) -> Option<Format> {
+    let channels = settings.channels.unwrap_or(default.channels);
+    let sample_rate = settings.sample_rate.unwrap_or(default.sample_rate.0);
+
+    let mut fallback = None;
+    for supported in formats {
+        if supported.channels != channels {
+            continue
+        }
+        if supported.min_sample_rate.0 <= sample_rate && sample_rate <= supported.max_sample_rate.0 {
+            let format = Format {
+                channels:       channels,
+                sample_rate:    cpal::SampleRate(sample_rate),
+                data_type:      supported.data_type,
+            };
+            // Prefer float output, there is no conversion needed for the bus
+            if supported.data_type == SampleFormat::F32 {
+                return Some(format);
+            }
+            fallback = Some(format);
+        }
+    }
+
+    fallback
+}
+
+/// Opens a stream on the device which plays the source.
+pub fn open<S>(device: &Device, format: &Format, source: S) -> Result<Output, String>
+where
+    S: Source<Item = f32> + Send + 'static,
+{
+    let mut output = Output {
+        source: Arc::new(Mutex::new(Box::new(source))),
+        event_loop: Arc::new(EventLoop::new()),
+        stream: Arc::new(Mutex::new(None)),
+        device_name: device.name(),
+        format: format.clone(),
+    };
+    start_stream(&mut output, device)?;
+    run(&output);
+    Ok(output)
+}
+
+/// Opens a new stream on the configured device with the current format, replacing the old stream.
+pub fn reopen(output: &mut Output, settings: &OutputSettings) -> Result<(), String> {
+    let device = select_device(&settings.device).ok_or("No output device available".to_string())?;
+    let format = output.format.clone();
+    let supported = device.supported_output_formats()
+        .map_err(|e| format!("Error retriving formats from '{}': {:?}", device.name(), e))?
+        .any(|f| f.channels == format.channels && f.min_sample_rate.0 <= format.sample_rate.0 && format.sample_rate.0 <= f.max_sample_rate.0);
+    if !supported {
+        return Err(format!("'{}' does not support the current format {:?}", device.name(), format));
+    }
+
+    output.device_name = device.name();
+    start_stream(output, &device)
+}
+
+pub fn device_name(output: &Output) -> &str {
+    &output.device_name
+}
+
+pub fn format(output: &Output) -> &Format {
+    &output.format
+}
+
+// Destroys the current stream, if any, before building a stream on the device to replace it
+fn start_stream(output: &mut Output, device: &Device) -> Result<(), String> {
+    let old_stream = output.stream.lock().unwrap().take();
+    if let Some (old_stream) = old_stream {
+        output.event_loop.destroy_stream(old_stream);
+    }
+
+    let stream_id = output.event_loop.build_output_stream(device, &output.format)
+        .map_err(|e| format!("Error building output stream on '{}': {:?}", device.name(), e))?;
+    *output.stream.lock().unwrap() = Some (stream_id.clone());
+    output.event_loop.play_stream(stream_id);
+    Ok(())
+}
+
+// The event loop never returns, so runs on its own thread for the life of the output
+fn run(output: &Output) {
+    let event_loop = output.event_loop.clone();
+    let source = output.source.clone();
+    let current_stream = output.stream.clone();
+    thread::spawn(move || {
+        event_loop.run(move |stream_id, stream_data| {
+            let buffer = match stream_data {
+                StreamData::Output { buffer } => buffer,
+                _ => return,
+            };
+
+            // A buffer asked for by a stream on its way out
+            if current_stream.lock().unwrap().as_ref() != Some (&stream_id) {
+                write_silence(buffer);
+                return
+            }
+
+            let mut source = source.lock().unwrap();
+            match buffer {
+                UnknownTypeOutputBuffer::F32(mut buffer) => {
+                    for sample in buffer.iter_mut() {
+                        *sample = source.next().unwrap_or(0.0);
+                    }
+                },
+                UnknownTypeOutputBuffer::I16(mut buffer) => {
+                    for sample in buffer.iter_mut() {
+                        *sample = cpal::Sample::to_i16(&source.next().unwrap_or(0.0));
+                    }
+                },
+                UnknownTypeOutputBuffer::U16(mut buffer) => {
+                    for sample in buffer.iter_mut() {
+                        *sample = cpal::Sample::to_u16(&source.next().unwrap_or(0.0));
+                    }
+                },
+            }
+        });
+    });
+}
+
+fn write_silence(buffer: UnknownTypeOutputBuffer) {
+    match buffer {
+        UnknownTypeOutputBuffer::F32(mut buffer) => {
+            for sample in buffer.iter_mut() {
+                *sample = 0.0;
+            }
+        },
+        UnknownTypeOutputBuffer::I16(mut buffer) => {
+            for sample in buffer.iter_mut() {
+                *sample = 0;
+            }
+        },
+        UnknownTypeOutputBuffer::U16(mut buffer) => {
+            for sample in buffer.iter_mut() {
+                *sample = cpal::Sample::to_u16(&0f32);
+            }
+        },
+    }
+}
+
+/// Sends `message` whenever the audio clock has not advanced for `stall_ms`, once per stall period.
+pub fn start_watchdog<T>(clock: Arc<AudioClock>, stall_ms: u64, tx: mpsc::Sender<T>, message: T) -> thread::JoinHandle<()>
+where
+    T: Clone + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut last_ms = clock.elapsed_ms();
+        loop {
+            thread::sleep(Duration::from_millis(stall_ms));
+            let now_ms = clock.elapsed_ms();
+            if now_ms == last_ms {
+                if tx.send(message.clone()).is_err() {
+                    break;
+                }
+            }
+            last_ms = now_ms;
+        }
+    })
+}
@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod rodiox_test {
+    use rodiox::output::*;
+    use cpal::{Format, SampleFormat, SampleRate, SupportedFormat};
+
+    fn settings(sample_rate: Option<u32>, channels: Option<u16>) -> OutputSettings {
+        OutputSettings { device: None, sample_rate: sample_rate, channels: channels }
+    }
+
+    fn supported(channels: u16, min: u32, max: u32, data_type: SampleFormat) -> SupportedFormat {
+        SupportedFormat {
+            channels:           channels,
+            min_sample_rate:    SampleRate(min),
+            max_sample_rate:    SampleRate(max),
+            data_type:          data_type,
+        }
+    }
+
+    fn default_format() -> Format {
+        Format { channels: 2, sample_rate: SampleRate(44_100), data_type: SampleFormat::I16 }
+    }
+
+    #[test]
+    fn formats() {
+        let formats = vec![
+            supported(2, 8_000, 96_000, SampleFormat::I16),
+            supported(4, 44_100, 48_000, SampleFormat::I16),
+            supported(4, 8_000, 192_000, SampleFormat::F32),
+        ];
+        let default = default_format();
+
+        // Float is preferred when both match
+        assert_eq!(
+            choose_format(&formats, &settings(Some (48_000), Some (4)), &default),
+            Some (Format { channels: 4, sample_rate: SampleRate(48_000), data_type: SampleFormat::F32 })
+        );
+        // Unset fields come from the default format
+        assert_eq!(
+            choose_format(&formats, &settings(Some (96_000), None), &default),
+            Some (Format { channels: 2, sample_rate: SampleRate(96_000), data_type: SampleFormat::I16 })
+        );
+        assert_eq!(choose_format(&formats, &settings(Some (192_000), None), &default), None);
+        assert_eq!(choose_format(&formats, &settings(None, Some (6)), &default), None);
+    }
+
+    #[test]
+    fn device_fallback() {
+        let names = vec!["default".to_string(), "hw:CARD=Device,DEV=0".to_string()];
+        assert_eq!(choose_device(&Some ("hw:CARD=Device,DEV=0".to_string()), &names), Some (1));
+        assert_eq!(choose_device(&Some ("hw:CARD=Missing,DEV=0".to_string()), &names), None);
+        assert_eq!(choose_device(&None, &names), None);
+    }
+}
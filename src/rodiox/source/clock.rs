@@ -0,0 +1,110 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use rodio::Sample;
+use rodio::Source;
+
+/// Shared view of the time played by a `Clock`.
+pub struct AudioClock {
+    // 64 bits so the count can't wrap on 32 bit targets, where a usize would after 49.7 days
+    elapsed_ms: AtomicU64,
+}
+
+impl AudioClock {
+    pub fn new() -> AudioClock {
+        AudioClock { elapsed_ms: AtomicU64::new(0) }
+    }
+
+    /// Milliseconds of audio consumed by the output so far.
+    #[inline]
+    pub fn elapsed_ms(&self) -> i64 {
+        self.elapsed_ms.load(Ordering::SeqCst) as i64
+    }
+}
+
+/// Counts the frames pulled through it, advancing an `AudioClock` every millisecond of audio.
+///
+/// Frames are converted to milliseconds with integer arithmetic so the clock never drifts from
+/// the sample rate of the output.
+pub struct Clock<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    input: I,
+    current_channel: usize,
+    // Accumulates 1000 per frame, a millisecond has passed each time it reaches the sample rate
+    remainder: u32,
+    shared: Arc<AudioClock>,
+}
+
+impl<I> Clock<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    pub fn new(input: I, shared: Arc<AudioClock>) -> Clock<I> {
+        Clock {
+            input,
+            current_channel: 0,
+            remainder: 0,
+            shared,
+        }
+    }
+}
+
+impl<I> Iterator for Clock<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<I::Item> {
+        let sample = self.input.next();
+
+        self.current_channel += 1;
+        if self.current_channel >= self.input.channels() as usize {
+            self.current_channel = 0;
+            self.remainder += 1000;
+            let sample_rate = self.input.sample_rate().max(1);
+            while self.remainder >= sample_rate {
+                self.remainder -= sample_rate;
+                self.shared.elapsed_ms.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        sample
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for Clock<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
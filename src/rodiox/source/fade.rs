@@ -0,0 +1,176 @@
+use std::f32;
+use std::time::Duration;
+use rodio::Sample;
+use rodio::Source;
+
+/// The curve followed by a `Fade` between its start and target gain.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FadeShape {
+    /// Gain changes at a constant rate.
+    Linear,
+    /// Gain changes at a constant rate in decibels, bottoming out at -60 dB.
+    Exponential,
+    /// Quarter sine and cosine curves, keeps the summed power of a crossfade constant.
+    EqualPower,
+    /// Smooth start and finish with the fastest change in the middle.
+    SCurve,
+}
+
+pub const EXPONENTIAL_FLOOR: f32 = 0.001; // -60 dB
+
+impl FadeShape {
+    /// Returns the gain at `t`, from 0.0 to 1.0, of the way from `from` to `to`.
+    pub fn gain_at(&self, from: f32, to: f32, t: f32) -> f32 {
+        if t <= 0.0 {
+            return from;
+        }
+        if t >= 1.0 {
+            return to;
+        }
+        match *self {
+            FadeShape::Linear => from + (to - from) * t,
+            FadeShape::Exponential => {
+                let from_db = from.max(EXPONENTIAL_FLOOR).log10();
+                let to_db = to.max(EXPONENTIAL_FLOOR).log10();
+                10f32.powf(from_db + (to_db - from_db) * t)
+            },
+            FadeShape::EqualPower => {
+                let quarter = t * f32::consts::FRAC_PI_2;
+                match to > from {
+                    true    => from + (to - from) * quarter.sin(),
+                    false   => from + (to - from) * (1.0 - quarter.cos()),
+                }
+            },
+            FadeShape::SCurve => from + (to - from) * (t * t * (3.0 - 2.0 * t)),
+        }
+    }
+}
+
+/// Applies a gain to its input which can be faded to a new value, sample by sample.
+#[derive(Clone, Debug)]
+pub struct Fade<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    input: I,
+    current_channel: usize,
+    gain: f32,
+    from: f32,
+    to: f32,
+    shape: FadeShape,
+    length_frames: u64,
+    position_frames: u64,
+}
+
+impl<I> Fade<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    pub fn new(input: I, gain: f32) -> Fade<I> {
+        Fade {
+            input,
+            current_channel: 0,
+            gain,
+            from: gain,
+            to: gain,
+            shape: FadeShape::Linear,
+            length_frames: 0,
+            position_frames: 0,
+        }
+    }
+
+    /// Starts fading from the current gain to `target` over `duration`.
+    pub fn fade_to(&mut self, target: f32, duration: Duration, shape: FadeShape) {
+        let seconds = duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0;
+        self.from = self.gain;
+        self.to = target;
+        self.shape = shape;
+        self.length_frames = (seconds * self.input.sample_rate() as f64) as u64;
+        self.position_frames = 0;
+        if self.length_frames == 0 {
+            self.gain = target;
+        }
+    }
+
+    /// Returns the gain currently applied.
+    #[inline]
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Returns true while a fade is in progress.
+    #[inline]
+    pub fn is_fading(&self) -> bool {
+        self.position_frames < self.length_frames
+    }
+
+    /// Returns a mutable reference to the inner source.
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+}
+
+impl<I> Iterator for Fade<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<I::Item> {
+        if self.current_channel == 0 && self.position_frames < self.length_frames {
+            self.position_frames += 1;
+            let t = self.position_frames as f32 / self.length_frames as f32;
+            self.gain = self.shape.gain_at(self.from, self.to, t);
+        }
+
+        self.current_channel += 1;
+        if self.current_channel >= self.input.channels() as usize {
+            self.current_channel = 0;
+        }
+
+        self.input.next().map(|sample| sample.amplify(self.gain))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> ExactSizeIterator for Fade<I>
+where
+    I: Source + ExactSizeIterator,
+    I::Item: Sample,
+{
+}
+
+impl<I> Source for Fade<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
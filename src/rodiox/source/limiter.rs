@@ -0,0 +1,325 @@
+use std::collections::VecDeque;
+use std::f32;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use rodio::Source;
+
+/// Settings for a `Limiter`.
+#[derive(Clone, Debug)]
+pub struct LimiterSettings {
+    /// Peak level the limiter holds the output under, in dBFS.
+    pub threshold_db: f32,
+    /// Time taken for the gain to recover once a peak has passed.
+    pub release_ms: f32,
+    /// How far ahead of the output peaks are detected.
+    pub lookahead_ms: f32,
+    /// Apply the same gain reduction to all channels instead of limiting each channel independently.
+    pub linked: bool,
+    /// Soft clip anything the limiter let through instead of letting the output hard clip.
+    /// Samples under the threshold are untouched, louder samples bend smoothly towards 1.0.
+    pub soft_clip: bool,
+}
+
+impl Default for LimiterSettings {
+    fn default() -> LimiterSettings {
+        LimiterSettings {
+            threshold_db:   -1.0,
+            release_ms:     250.0,
+            lookahead_ms:   5.0,
+            linked:         true,
+            soft_clip:      true,
+        }
+    }
+}
+
+/// Shared view of the gain reduction applied by a `Limiter`.
+pub struct LimiterMeter {
+    // f32 bits of the largest gain reduction, in dB, since the last read
+    max_reduction_db: AtomicUsize,
+}
+
+impl LimiterMeter {
+    fn new() -> LimiterMeter {
+        LimiterMeter {
+            max_reduction_db: AtomicUsize::new(0f32.to_bits() as usize),
+        }
+    }
+
+    fn report(&self, reduction_db: f32) {
+        let current = f32::from_bits(self.max_reduction_db.load(Ordering::Relaxed) as u32);
+        if reduction_db > current {
+            self.max_reduction_db.store(reduction_db.to_bits() as usize, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the largest gain reduction, in dB, applied since the last call and resets it.
+    pub fn take_max_reduction_db(&self) -> f32 {
+        f32::from_bits(self.max_reduction_db.swap(0f32.to_bits() as usize, Ordering::Relaxed) as u32)
+    }
+}
+
+// The gain each frame needs is held at the lowest of the look-ahead window, then averaged over
+// the window. Every held value covering a frame is at or under the gain the frame needs, so the
+// average is too by the time the frame reaches the output, and it ramps down smoothly to meet it.
+struct Envelope {
+    gain:           f32,
+    // Frame number and required gain, increasing, the front is the window's lowest
+    minimum:        VecDeque<(u64, f32)>,
+    held:           VecDeque<f32>,
+    held_sum:       f64,
+}
+
+impl Envelope {
+    fn new(window: usize) -> Envelope {
+        Envelope {
+            gain:       1.0,
+            minimum:    VecDeque::with_capacity(window),
+            held:       (0..window).map(|_| 1.0).collect(),
+            held_sum:   window as f64,
+        }
+    }
+}
+
+/// Look-ahead peak limiter with an optional soft clipper on its output.
+///
+/// Input is delayed by the look-ahead time so the gain can ramp down before a peak reaches the
+/// output, then recovers exponentially over the release time.
+pub struct Limiter<I>
+where
+    I: Source<Item = f32>,
+{
+    input: I,
+    channels: usize,
+    threshold: f32,
+    knee: f32,
+    release_coeff: f32,
+    lookahead_frames: usize,
+    soft_clip: bool,
+    envelopes: Vec<Envelope>,
+    peaks: Vec<f32>,
+    delay: VecDeque<f32>,
+    frame: Vec<f32>,
+    frame_position: usize,
+    frame_count: u64,
+    input_done: bool,
+    meter: Arc<LimiterMeter>,
+}
+
+impl<I> Limiter<I>
+where
+    I: Source<Item = f32>,
+{
+    pub fn new(input: I, settings: &LimiterSettings) -> Limiter<I> {
+        let channels = input.channels().max(1) as usize;
+        let sample_rate = input.sample_rate() as f32;
+        let lookahead_frames = ((settings.lookahead_ms / 1000.0) * sample_rate).max(1.0) as usize;
+        let release_frames = ((settings.release_ms / 1000.0) * sample_rate).max(1.0);
+        let envelope_count = match settings.linked {
+            true    => 1,
+            false   => channels,
+        };
+
+        let envelopes = (0..envelope_count).map(|_| Envelope::new(lookahead_frames + 1)).collect();
+        let threshold = db_to_amplitude(settings.threshold_db);
+
+        Limiter {
+            input,
+            channels,
+            threshold,
+            // Soft clipping starts at the threshold, leaving some headroom to bend into
+            knee: threshold.min(0.99),
+            release_coeff: (-1.0 / release_frames).exp(),
+            lookahead_frames,
+            soft_clip: settings.soft_clip,
+            peaks: vec![0.0; envelope_count],
+            envelopes,
+            delay: VecDeque::with_capacity((lookahead_frames + 1) * channels),
+            frame: vec![0.0; channels],
+            frame_position: channels,
+            frame_count: 0,
+            input_done: false,
+            meter: Arc::new(LimiterMeter::new()),
+        }
+    }
+
+    /// Returns a handle for reading the gain reduction from another thread.
+    pub fn meter(&self) -> Arc<LimiterMeter> {
+        self.meter.clone()
+    }
+
+    fn update_envelope(&mut self, index: usize, peak: f32) -> f32 {
+        let required = match peak > self.threshold {
+            true    => self.threshold / peak,
+            false   => 1.0,
+        };
+
+        let window = self.lookahead_frames + 1;
+        let frame = self.frame_count;
+        let release_coeff = self.release_coeff;
+        let env = &mut self.envelopes[index];
+
+        // Lowest required gain of the frames between the input and the output
+        while env.minimum.back().map(|&(_, gain)| gain >= required).unwrap_or(false) {
+            env.minimum.pop_back();
+        }
+        env.minimum.push_back((frame, required));
+        while env.minimum.front().map(|&(start, _)| start + (window as u64) <= frame).unwrap_or(false) {
+            env.minimum.pop_front();
+        }
+        let held = env.minimum.front().map(|&(_, gain)| gain).unwrap_or(1.0);
+
+        env.held.push_back(held);
+        env.held_sum += held as f64;
+        env.held_sum -= env.held.pop_front().unwrap_or(1.0) as f64;
+        if frame % window as u64 == 0 {
+            // Start the sum afresh now and then so rounding can't build up
+            env.held_sum = env.held.iter().map(|&gain| gain as f64).sum();
+        }
+        let smoothed = ((env.held_sum / window as f64) as f32).min(1.0);
+
+        if smoothed < env.gain {
+            env.gain = smoothed;
+        }
+        else {
+            env.gain = smoothed + (env.gain - smoothed) * release_coeff;
+        }
+        env.gain
+    }
+
+    // Pulls the next frame from the input and writes the next limited frame to self.frame.
+    fn next_frame(&mut self) -> bool {
+        if !self.input_done {
+            for peak in self.peaks.iter_mut() {
+                *peak = 0.0;
+            }
+            for channel in 0..self.channels {
+                match self.input.next() {
+                    Some (sample) => {
+                        let index = channel % self.peaks.len();
+                        self.peaks[index] = self.peaks[index].max(sample.abs());
+                        self.delay.push_back(sample);
+                    },
+                    None => {
+                        self.input_done = true;
+                        break;
+                    },
+                }
+            }
+
+            if !self.input_done {
+                let mut min_gain = 1f32;
+                for index in 0..self.peaks.len() {
+                    let peak = self.peaks[index];
+                    min_gain = min_gain.min(self.update_envelope(index, peak));
+                }
+                self.meter.report(-amplitude_to_db(min_gain));
+                self.frame_count += 1;
+            }
+        }
+
+        if self.input_done && self.delay.len() >= self.channels {
+            // Silence follows the input so the last frames still ramp down in time
+            for index in 0..self.peaks.len() {
+                self.update_envelope(index, 0.0);
+            }
+            self.frame_count += 1;
+        }
+
+        // Wait for the look-ahead window to fill before producing output
+        if !self.input_done && self.delay.len() < (self.lookahead_frames + 1) * self.channels {
+            for sample in self.frame.iter_mut() {
+                *sample = 0.0;
+            }
+            return true;
+        }
+
+        if self.delay.len() < self.channels {
+            return false;
+        }
+
+        for channel in 0..self.channels {
+            let gain = self.envelopes[channel % self.envelopes.len()].gain;
+            let sample = self.delay.pop_front().unwrap_or(0.0) * gain;
+            self.frame[channel] = match self.soft_clip {
+                true    => soft_clip(sample, self.knee),
+                false   => sample,
+            };
+        }
+        true
+    }
+}
+
+/// Passes samples below the knee untouched and bends anything above it smoothly towards 1.0.
+/// The knee is an amplitude under 1.0.
+pub fn soft_clip(sample: f32, knee: f32) -> f32 {
+    let magnitude = sample.abs();
+    if magnitude <= knee {
+        sample
+    }
+    else {
+        let headroom = 1.0 - knee;
+        let clipped = knee + headroom * ((magnitude - knee) / headroom).tanh();
+        clipped * sample.signum()
+    }
+}
+
+/// Converts a level in dBFS to an amplitude.
+pub fn db_to_amplitude(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Converts an amplitude to a level in dBFS, silence reads as -120 dB.
+pub fn amplitude_to_db(amplitude: f32) -> f32 {
+    20.0 * amplitude.max(0.000_001).log10()
+}
+
+impl<I> Iterator for Limiter<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        if self.frame_position >= self.channels {
+            if !self.next_frame() {
+                return None;
+            }
+            self.frame_position = 0;
+        }
+        let sample = self.frame[self.frame_position];
+        self.frame_position += 1;
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for Limiter<I>
+where
+    I: Source<Item = f32>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
@@ -0,0 +1,178 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use rodio::Sample;
+use rodio::Source;
+
+/// Level of a single channel accumulated over a metering period.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelLevel {
+    pub peak:           f32,
+    pub sum_squares:    f32,
+    pub samples:        u32,
+}
+
+impl ChannelLevel {
+    pub fn new() -> ChannelLevel {
+        ChannelLevel { peak: 0.0, sum_squares: 0.0, samples: 0 }
+    }
+
+    pub fn peak_db(&self) -> f32 {
+        to_db(self.peak)
+    }
+
+    pub fn rms_db(&self) -> f32 {
+        match self.samples {
+            0 => to_db(0.0),
+            n => to_db((self.sum_squares / n as f32).sqrt()),
+        }
+    }
+
+    fn add(&mut self, sample: f32) {
+        let magnitude = sample.abs();
+        if magnitude > self.peak {
+            self.peak = magnitude;
+        }
+        self.sum_squares += sample * sample;
+        self.samples += 1;
+    }
+
+    fn merge(&mut self, other: &ChannelLevel) {
+        if other.peak > self.peak {
+            self.peak = other.peak;
+        }
+        self.sum_squares += other.sum_squares;
+        self.samples += other.samples;
+    }
+}
+
+fn to_db(amplitude: f32) -> f32 {
+    20.0 * amplitude.max(0.000_001).log10()
+}
+
+/// Shared view of the levels measured by a `Meter`.
+pub struct LevelMeter {
+    levels: Mutex<Vec<ChannelLevel>>,
+}
+
+impl LevelMeter {
+    pub fn new() -> LevelMeter {
+        LevelMeter { levels: Mutex::new(Vec::new()) }
+    }
+
+    /// Returns the level of each channel measured since the last call and resets them.
+    pub fn take_levels(&self) -> Vec<ChannelLevel> {
+        let mut levels = self.levels.lock().unwrap();
+        let taken = levels.clone();
+        for level in levels.iter_mut() {
+            *level = ChannelLevel::new();
+        }
+        taken
+    }
+}
+
+/// Measures the peak and RMS level of each channel passing through it.
+///
+/// Levels are accumulated locally and published to the shared `LevelMeter` every few
+/// milliseconds to keep locking out of the per sample path.
+pub struct Meter<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    input: I,
+    current_channel: usize,
+    local: Vec<ChannelLevel>,
+    frames_since_publish: usize,
+    publish_frames: usize,
+    shared: Arc<LevelMeter>,
+}
+
+impl<I> Meter<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    pub fn new(input: I, shared: Arc<LevelMeter>) -> Meter<I> {
+        let channels = input.channels().max(1) as usize;
+        // publish about every 10 ms
+        let publish_frames = (input.sample_rate() / 100).max(1) as usize;
+        Meter {
+            input,
+            current_channel: 0,
+            local: vec![ChannelLevel::new(); channels],
+            frames_since_publish: 0,
+            publish_frames,
+            shared,
+        }
+    }
+
+    fn publish(&mut self) {
+        if let Ok(mut levels) = self.shared.levels.try_lock() {
+            if levels.len() != self.local.len() {
+                *levels = vec![ChannelLevel::new(); self.local.len()];
+            }
+            for (shared, local) in levels.iter_mut().zip(self.local.iter_mut()) {
+                shared.merge(local);
+                *local = ChannelLevel::new();
+            }
+            self.frames_since_publish = 0;
+        }
+    }
+}
+
+impl<I> Iterator for Meter<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<I::Item> {
+        let sample = self.input.next()?;
+        if self.current_channel < self.local.len() {
+            self.local[self.current_channel].add(sample.to_f32());
+        }
+
+        self.current_channel += 1;
+        if self.current_channel >= self.local.len() {
+            self.current_channel = 0;
+            self.frames_since_publish += 1;
+            if self.frames_since_publish >= self.publish_frames {
+                self.publish();
+            }
+        }
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for Meter<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
@@ -1,4 +1,26 @@
 pub use self::channel_volume::ChannelVolume;
+pub use self::clock::Clock;
+pub use self::fade::{Fade, FadeShape};
+pub use self::limiter::Limiter;
+pub use self::meter::Meter;
+pub use self::param::Param;
+pub use self::reverb::Reverb;
+pub use self::smooth_gain::SmoothGain;
+pub use self::start_gate::StartGate;
+pub use self::variable_speed::VariableSpeed;
+pub use self::variant_switch::{VariantControl, VariantSwitch};
 
 pub mod channel_volume;
+pub mod clock;
 pub mod diffusion;
+pub mod fade;
+pub mod limiter;
+pub mod meter;
+pub mod param;
+pub mod reverb;
+pub mod smooth_gain;
+pub mod start_gate;
+pub mod variable_speed;
+pub mod variant_switch;
+
+mod source_tests;
@@ -0,0 +1,23 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// An f32 which can be set from one thread and read from the audio thread without locking.
+pub struct Param {
+    bits: AtomicUsize,
+}
+
+impl Param {
+    pub fn new(value: f32) -> Arc<Param> {
+        Arc::new(Param { bits: AtomicUsize::new(value.to_bits() as usize) })
+    }
+
+    #[inline]
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.bits.load(Ordering::Relaxed) as u32)
+    }
+
+    #[inline]
+    pub fn set(&self, value: f32) {
+        self.bits.store(value.to_bits() as usize, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,86 @@
+use rodiox::source::param::Param;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use rodio::Source;
+
+/// Mixes a delayed copy of its input back in, like `rodio::Source::reverb` but the amount of the
+/// delayed copy can be changed while playing.
+pub struct Reverb<I>
+where
+    I: Source<Item = f32>,
+{
+    input: I,
+    mix: Arc<Param>,
+    delay_line: VecDeque<f32>,
+}
+
+impl<I> Reverb<I>
+where
+    I: Source<Item = f32>,
+{
+    pub fn new(input: I, delay: Duration, mix: Arc<Param>) -> Reverb<I> {
+        let seconds = delay.as_secs() as f32 + delay.subsec_nanos() as f32 / 1_000_000_000.0;
+        let delay_samples = (seconds * input.sample_rate() as f32) as usize * input.channels() as usize;
+        let mut delay_line = VecDeque::with_capacity(delay_samples + 1);
+        for _ in 0..delay_samples {
+            delay_line.push_back(0.0);
+        }
+
+        Reverb {
+            input,
+            mix,
+            delay_line,
+        }
+    }
+}
+
+impl<I> Iterator for Reverb<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next()?;
+        // Without a delay there is nothing to mix in
+        if self.delay_line.is_empty() {
+            return Some(sample);
+        }
+
+        self.delay_line.push_back(sample);
+        let delayed = self.delay_line.pop_front().unwrap_or(0.0);
+        Some(sample + delayed * self.mix.get())
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for Reverb<I>
+where
+    I: Source<Item = f32>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
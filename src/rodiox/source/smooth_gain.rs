@@ -0,0 +1,86 @@
+use rodiox::source::param::Param;
+use std::sync::Arc;
+use std::time::Duration;
+use rodio::Source;
+
+/// Applies a gain which glides towards its target, so a gain updated every few milliseconds
+/// doesn't step audibly.
+pub struct SmoothGain<I>
+where
+    I: Source<Item = f32>,
+{
+    input: I,
+    target: Arc<Param>,
+    gain: f32,
+    coeff: f32,
+    current_channel: usize,
+}
+
+impl<I> SmoothGain<I>
+where
+    I: Source<Item = f32>,
+{
+    /// `smoothing` is the time taken to move most of the way to a new target.
+    pub fn new(input: I, target: Arc<Param>, smoothing: Duration) -> SmoothGain<I> {
+        let seconds = smoothing.as_secs() as f32 + smoothing.subsec_nanos() as f32 / 1_000_000_000.0;
+        let frames = (seconds * input.sample_rate() as f32).max(1.0);
+        let gain = target.get();
+        SmoothGain {
+            input,
+            target,
+            gain,
+            coeff: (-1.0 / frames).exp(),
+            current_channel: 0,
+        }
+    }
+}
+
+impl<I> Iterator for SmoothGain<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        if self.current_channel == 0 {
+            let target = self.target.get();
+            self.gain = target + (self.gain - target) * self.coeff;
+        }
+        self.current_channel += 1;
+        if self.current_channel >= self.input.channels() as usize {
+            self.current_channel = 0;
+        }
+        self.input.next().map(|sample| sample * self.gain)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I> Source for SmoothGain<I>
+where
+    I: Source<Item = f32>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
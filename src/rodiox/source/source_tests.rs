@@ -0,0 +1,192 @@
+#[cfg(test)]
+mod source_test {
+    use rodiox::source::clock::AudioClock;
+    use rodiox::source::fade::EXPONENTIAL_FLOOR;
+    use rodiox::source::limiter::*;
+    use rodiox::source::meter::{ChannelLevel, LevelMeter};
+    use rodiox::source::{Clock, Fade, FadeShape, Limiter, Meter, VariantControl, VariantSwitch};
+    use rodio::buffer::SamplesBuffer;
+    use rodio::Source;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    // Output starts with the look-ahead's worth of silence
+    const LOOKAHEAD_FRAMES: usize = 240;
+
+    fn limit(samples: Vec<f32>, channels: u16, settings: &LimiterSettings) -> Vec<f32> {
+        Limiter::new(SamplesBuffer::new(channels, 48_000, samples), settings).collect()
+    }
+
+    #[test]
+    fn limiter_holds_peaks_under_threshold() {
+        let settings = LimiterSettings { soft_clip: false, ..LimiterSettings::default() };
+        let threshold = db_to_amplitude(settings.threshold_db);
+
+        // A loud peak followed by a smaller one while the gain is still down for the first,
+        // then back to back peaks closer together than the look-ahead, ending on a peak
+        let mut samples = vec![0.1f32; 4_000];
+        samples[1_000] = 2.0;
+        samples[1_100] = 1.2;
+        for i in 0..20 {
+            samples[2_000 + i * 37] = 1.0 + i as f32 * 0.25;
+        }
+        samples[3_999] = 3.0;
+
+        let output = limit(samples.clone(), 1, &settings);
+        assert_eq!(output.len(), samples.len() + LOOKAHEAD_FRAMES);
+        for sample in output {
+            assert!(sample.abs() <= threshold + 1e-6, "{} over {}", sample, threshold);
+        }
+    }
+
+    #[test]
+    fn limiter_unlinked_channels() {
+        let settings = LimiterSettings { soft_clip: false, linked: false, ..LimiterSettings::default() };
+        let threshold = db_to_amplitude(settings.threshold_db);
+        let mut samples = vec![0.5f32; 2_000];
+        samples[500] = 4.0;
+        samples[501] = -0.5;
+        samples[1_200] = -1.5;
+
+        let output = limit(samples, 2, &settings);
+        for sample in &output {
+            assert!(sample.abs() <= threshold + 1e-6);
+        }
+        // The quiet channel is left alone
+        assert!((output[501 + 2 * LOOKAHEAD_FRAMES] + 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn soft_clip_bends_above_knee() {
+        assert_eq!(soft_clip(0.5, 0.8), 0.5);
+        assert_eq!(soft_clip(-0.8, 0.8), -0.8);
+        let bent = soft_clip(1.5, 0.8);
+        assert!(bent > 0.8 && bent < 1.0);
+        assert_eq!(soft_clip(-1.5, 0.8), -bent);
+        assert!(soft_clip(100.0, 0.8) <= 1.0);
+        // Louder stays louder
+        assert!(soft_clip(0.9, 0.8) < soft_clip(0.95, 0.8));
+    }
+
+    #[test]
+    fn decibels() {
+        assert!((db_to_amplitude(0.0) - 1.0).abs() < 1e-6);
+        assert!((db_to_amplitude(-6.0) - 0.501).abs() < 1e-3);
+        assert!((db_to_amplitude(-20.0) - 0.1).abs() < 1e-6);
+        for &db in &[-60.0, -12.5, -1.0, 0.0, 6.0] {
+            assert!((amplitude_to_db(db_to_amplitude(db)) - db).abs() < 1e-4);
+        }
+        assert_eq!(amplitude_to_db(0.0), -120.0);
+    }
+
+    #[test]
+    fn meter_levels_accumulate_until_taken() {
+        let shared = Arc::new(LevelMeter::new());
+        // Published every 10 frames at 1kHz
+        let samples: Vec<f32> = (0..100).flat_map(|_| vec![0.5, -0.25]).collect();
+        let passed: Vec<f32> = Meter::new(SamplesBuffer::new(2, 1_000, samples.clone()), shared.clone()).collect();
+        assert_eq!(passed, samples);
+
+        let levels = shared.take_levels();
+        assert_eq!(levels.len(), 2);
+        assert_eq!(levels[0].samples, 100);
+        assert_eq!(levels[0].peak, 0.5);
+        assert_eq!(levels[1].peak, 0.25);
+        assert!((levels[0].rms_db() + 6.02).abs() < 0.01);
+
+        // Taking resets the levels
+        let levels = shared.take_levels();
+        assert_eq!(levels, vec![ChannelLevel::new(); 2]);
+        assert_eq!(levels[0].rms_db(), -120.0);
+    }
+
+    #[test]
+    fn fade_shapes_meet_endpoints() {
+        let shapes = [FadeShape::Linear, FadeShape::Exponential, FadeShape::EqualPower, FadeShape::SCurve];
+        for shape in shapes.iter() {
+            assert_eq!(shape.gain_at(0.2, 0.8, 0.0), 0.2);
+            assert_eq!(shape.gain_at(0.2, 0.8, -1.0), 0.2);
+            assert_eq!(shape.gain_at(0.2, 0.8, 1.0), 0.8);
+            assert_eq!(shape.gain_at(0.2, 0.8, 2.0), 0.8);
+            // Part way is between the two, both ways
+            let rising = shape.gain_at(0.2, 0.8, 0.5);
+            assert!(rising > 0.2 && rising < 0.8, "{:?} {}", shape, rising);
+            let falling = shape.gain_at(0.8, 0.2, 0.5);
+            assert!(falling > 0.2 && falling < 0.8, "{:?} {}", shape, falling);
+        }
+        assert!((FadeShape::Linear.gain_at(0.0, 1.0, 0.25) - 0.25).abs() < 1e-6);
+        assert!((FadeShape::SCurve.gain_at(0.0, 1.0, 0.5) - 0.5).abs() < 1e-6);
+        assert!(FadeShape::SCurve.gain_at(0.0, 1.0, 0.1) < 0.1);
+    }
+
+    #[test]
+    fn exponential_fade_floor() {
+        // Halfway in decibels between -60 dB and 0 dB
+        assert!((FadeShape::Exponential.gain_at(0.0, 1.0, 0.5) - EXPONENTIAL_FLOOR.sqrt()).abs() < 1e-4);
+        assert!((FadeShape::Exponential.gain_at(1.0, 0.0, 0.999) - EXPONENTIAL_FLOOR).abs() < 1e-4);
+        // Equal steps in decibels
+        let quarter = FadeShape::Exponential.gain_at(0.01, 1.0, 0.25);
+        assert!((amplitude_to_db(quarter) + 30.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn equal_power_crossfade() {
+        for i in 0..=20 {
+            let t = i as f32 / 20.0;
+            let incoming = FadeShape::EqualPower.gain_at(0.0, 1.0, t);
+            let outgoing = FadeShape::EqualPower.gain_at(1.0, 0.0, t);
+            assert!((incoming * incoming + outgoing * outgoing - 1.0).abs() < 1e-5, "{}", t);
+        }
+    }
+
+    #[test]
+    fn fade_applies_gain_each_frame() {
+        // A linear fade in over 10 frames at 1kHz
+        let mut fade = Fade::new(SamplesBuffer::new(2, 1_000, vec![1.0f32; 30]), 0.0);
+        fade.fade_to(1.0, Duration::from_millis(10), FadeShape::Linear);
+        assert!(fade.is_fading());
+        let output: Vec<f32> = fade.by_ref().take(20).collect();
+        for frame in 0..10 {
+            let expected = (frame + 1) as f32 / 10.0;
+            assert!((output[frame * 2] - expected).abs() < 1e-6);
+            assert_eq!(output[frame * 2], output[frame * 2 + 1]);
+        }
+        assert!(!fade.is_fading());
+        assert_eq!(fade.gain(), 1.0);
+
+        // Without a length the gain is set straight away
+        fade.fade_to(0.5, Duration::from_millis(0), FadeShape::Linear);
+        assert!(!fade.is_fading());
+        assert_eq!(fade.collect::<Vec<f32>>(), vec![0.5; 10]);
+    }
+
+    #[test]
+    fn clock_counts_whole_milliseconds() {
+        let shared = Arc::new(AudioClock::new());
+        // 1.5 seconds of stereo at 44.1kHz, which isn't a whole number of frames per millisecond
+        let mut clock = Clock::new(SamplesBuffer::new(2, 44_100, vec![0.0f32; 2 * 66_150]), shared.clone());
+        clock.by_ref().take(2 * 441).count();
+        assert_eq!(shared.elapsed_ms(), 10);
+        clock.by_ref().take(2 * 440).count();
+        assert_eq!(shared.elapsed_ms(), 19);
+        assert_eq!(clock.count(), 2 * (66_150 - 881));
+        assert_eq!(shared.elapsed_ms(), 1_500);
+    }
+
+    #[test]
+    fn variant_switch_seeks_within_the_loop() {
+        // Ten stereo frames at 1kHz, each frame holding its index
+        let frames: Vec<f32> = (0..10).flat_map(|i| vec![i as f32, i as f32]).collect();
+        let variant = SamplesBuffer::new(2, 1_000, frames.clone()).buffered();
+        let control = VariantControl::new(0);
+        let mut switch = VariantSwitch::new(vec![variant], control.clone());
+        switch.seek(Duration::from_millis(3));
+        assert_eq!(switch.by_ref().take(2).collect::<Vec<f32>>(), vec![3.0, 3.0]);
+
+        // Seeking past the end wraps
+        let mut switch = VariantSwitch::new(vec![SamplesBuffer::new(2, 1_000, frames).buffered()], control);
+        switch.seek(Duration::from_millis(18));
+        assert_eq!(switch.by_ref().take(2).collect::<Vec<f32>>(), vec![8.0, 8.0]);
+        assert_eq!(switch.by_ref().take(4).collect::<Vec<f32>>(), vec![9.0, 9.0, 0.0, 0.0]);
+    }
+}
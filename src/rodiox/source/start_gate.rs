@@ -0,0 +1,106 @@
+use rodiox::source::clock::AudioClock;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use rodio::Sample;
+use rodio::Source;
+
+/// Outputs silence, without consuming its input, until the audio clock reaches a start time.
+///
+/// Every source reads the same clock for a given frame, so sources given the same start time
+/// begin on the same frame. A new start time holds the input again until the clock reaches it.
+pub struct StartGate<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    input: I,
+    clock: Arc<AudioClock>,
+    start_ms: Arc<AtomicUsize>,
+    // The start time the input was last held for
+    held_for_ms: i64,
+    started: bool,
+    current_channel: u16,
+}
+
+impl<I> StartGate<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    pub fn new(input: I, clock: Arc<AudioClock>, start_ms: Arc<AtomicUsize>) -> StartGate<I> {
+        StartGate {
+            input,
+            clock,
+            start_ms,
+            held_for_ms: 0,
+            started: false,
+            current_channel: 0,
+        }
+    }
+}
+
+impl<I> Iterator for StartGate<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<I::Item> {
+        if self.current_channel == 0 {
+            let start_ms = self.start_ms.load(Ordering::Relaxed) as i64;
+            if start_ms != self.held_for_ms {
+                self.held_for_ms = start_ms;
+                self.started = false;
+            }
+            if !self.started {
+                self.started = self.clock.elapsed_ms() >= start_ms;
+            }
+        }
+
+        self.current_channel += 1;
+        if self.current_channel >= self.input.channels() {
+            self.current_channel = 0;
+        }
+        match self.started {
+            true    => self.input.next(),
+            false   => Some(I::Item::zero_value()),
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.input.size_hint().0, None)
+    }
+}
+
+impl<I> Source for StartGate<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        match self.started {
+            true    => self.input.current_frame_len(),
+            false   => None,
+        }
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,126 @@
+use rodiox::source::param::Param;
+use std::sync::Arc;
+use std::time::Duration;
+use rodio::Sample;
+use rodio::Source;
+
+/// Plays its input faster or slower by a factor which can be changed while playing.
+///
+/// Frames are linearly interpolated, so the pitch follows the speed like a tape machine.
+pub struct VariableSpeed<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    input: I,
+    factor: Arc<Param>,
+    channels: usize,
+    current: Vec<f32>,
+    next: Vec<f32>,
+    position: f32,
+    current_channel: usize,
+    input_done: bool,
+    finished: bool,
+}
+
+impl<I> VariableSpeed<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    pub fn new(input: I, factor: Arc<Param>) -> VariableSpeed<I> {
+        let channels = input.channels().max(1) as usize;
+        let mut speed = VariableSpeed {
+            input,
+            factor,
+            channels,
+            current: vec![0.0; channels],
+            next: vec![0.0; channels],
+            position: 0.0,
+            current_channel: 0,
+            input_done: false,
+            finished: false,
+        };
+        speed.finished = !speed.read_frame();
+        speed.current.copy_from_slice(&speed.next);
+        speed.read_frame();
+        speed
+    }
+
+    // Reads the next input frame into self.next, returns false once the input has run out.
+    fn read_frame(&mut self) -> bool {
+        if self.input_done {
+            return false;
+        }
+        for channel in 0..self.channels {
+            match self.input.next() {
+                Some (sample) => self.next[channel] = sample.to_f32(),
+                None => {
+                    self.input_done = true;
+                    return false;
+                },
+            }
+        }
+        true
+    }
+}
+
+impl<I> Iterator for VariableSpeed<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        if self.finished {
+            return None;
+        }
+
+        let channel = self.current_channel;
+        let sample = self.current[channel] + (self.next[channel] - self.current[channel]) * self.position;
+
+        self.current_channel += 1;
+        if self.current_channel >= self.channels {
+            self.current_channel = 0;
+            self.position += self.factor.get().max(0.0);
+            while self.position >= 1.0 {
+                self.position -= 1.0;
+                if self.input_done {
+                    self.finished = true;
+                    break;
+                }
+                self.current.copy_from_slice(&self.next);
+                self.read_frame();
+            }
+        }
+        Some(sample)
+    }
+}
+
+impl<I> Source for VariableSpeed<I>
+where
+    I: Source,
+    I::Item: Sample,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
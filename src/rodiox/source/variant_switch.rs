@@ -0,0 +1,176 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use rodio::Sample;
+use rodio::Source;
+
+/// Shared control of a `VariantSwitch`.
+pub struct VariantControl {
+    selected: AtomicUsize,
+    restart: AtomicBool,
+    started: AtomicUsize,
+}
+
+impl VariantControl {
+    pub fn new(selected: usize) -> Arc<VariantControl> {
+        Arc::new(VariantControl {
+            selected: AtomicUsize::new(selected),
+            restart: AtomicBool::new(false),
+            started: AtomicUsize::new(0),
+        })
+    }
+
+    /// Chooses the variant played once the current variant finishes.
+    pub fn select(&self, index: usize) {
+        self.selected.store(index, Ordering::SeqCst);
+    }
+
+    /// Starts the variant from the beginning straight away.
+    pub fn restart(&self, index: usize) {
+        self.selected.store(index, Ordering::SeqCst);
+        self.restart.store(true, Ordering::SeqCst);
+    }
+
+    /// Number of times a variant has been started.
+    pub fn started(&self) -> usize {
+        self.started.load(Ordering::SeqCst)
+    }
+}
+
+/// Loops one of several sources, moving to the selected source each time the current one ends.
+///
+/// Every variant must have the same channel count and sample rate.
+pub struct VariantSwitch<S>
+where
+    S: Source + Clone,
+    S::Item: Sample,
+{
+    variants: Vec<S>,
+    current: S,
+    current_channel: u16,
+    control: Arc<VariantControl>,
+}
+
+impl<S> VariantSwitch<S>
+where
+    S: Source + Clone,
+    S::Item: Sample,
+{
+    /// Panics if `variants` is empty.
+    pub fn new(variants: Vec<S>, control: Arc<VariantControl>) -> VariantSwitch<S> {
+        let index = control.selected.load(Ordering::SeqCst).min(variants.len() - 1);
+        let current = variants[index].clone();
+        control.started.fetch_add(1, Ordering::SeqCst);
+        VariantSwitch {
+            variants,
+            current,
+            current_channel: 0,
+            control,
+        }
+    }
+
+    /// Moves the current variant on by `position`, wrapping at its end, so a loop can start
+    /// part-way through.
+    ///
+    /// A variant without a known duration is read through to find its length, so seek before
+    /// the switch is handed to the audio output.
+    pub fn seek(&mut self, position: Duration) {
+        let frames_per_loop = self.current_frames();
+        if frames_per_loop == 0 {
+            return
+        }
+        let channels = self.current.channels().max(1) as usize;
+        let ms = position.as_secs() * 1000 + (position.subsec_nanos() / 1_000_000) as u64;
+        let frames = (ms * self.current.sample_rate() as u64 / 1000) as usize % frames_per_loop;
+        if frames > 0 {
+            self.current.nth(frames * channels - 1);
+        }
+    }
+
+    /// Frames in the current variant, from its duration when known. Otherwise the variant is
+    /// read through once to count them.
+    fn current_frames(&self) -> usize {
+        let variant = &self.current;
+        match variant.total_duration() {
+            Some (duration) => {
+                let rate = variant.sample_rate() as u64;
+                (duration.as_secs() * rate + duration.subsec_nanos() as u64 * rate / 1_000_000_000) as usize
+            },
+            None => variant.clone().count() / variant.channels().max(1) as usize,
+        }
+    }
+
+    fn start_selected(&mut self) {
+        let index = self.control.selected.load(Ordering::SeqCst).min(self.variants.len() - 1);
+        self.current = self.variants[index].clone();
+        self.current_channel = 0;
+        self.control.started.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl<S> Iterator for VariantSwitch<S>
+where
+    S: Source + Clone,
+    S::Item: Sample,
+{
+    type Item = S::Item;
+
+    #[inline]
+    fn next(&mut self) -> Option<S::Item> {
+        // Only switch between frames so channels stay aligned
+        if self.current_channel == 0 {
+            if self.control.restart.swap(false, Ordering::SeqCst) {
+                self.start_selected();
+            }
+        }
+
+        let sample = match self.current.next() {
+            Some (sample) => sample,
+            None => {
+                self.start_selected();
+                // An empty variant would otherwise loop forever
+                match self.current.next() {
+                    Some (sample) => sample,
+                    None => return None,
+                }
+            },
+        };
+
+        self.current_channel += 1;
+        if self.current_channel >= self.current.channels() {
+            self.current_channel = 0;
+        }
+        Some(sample)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, None)
+    }
+}
+
+impl<S> Source for VariantSwitch<S>
+where
+    S: Source + Clone,
+    S::Item: Sample,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.current.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.current.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
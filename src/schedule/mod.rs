@@ -0,0 +1,380 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday};
+
+use config::{DailySchedule, Day, Location, Soundscape, WeeklyWindows};
+use localtime;
+use localtime::Zone;
+use solar;
+use solar::SolarEvent;
+
+use std::time::Instant;
+
+mod schedule_tests;
+
+// Schedule
+// Windows of time the soundscape plays in. Windows are laid out on the local wall clock and
+// compared as instants, so they keep their times either side of DST changes. Times are
+// evaluated against a clock passed in, so any moment can be checked without waiting for it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimeSpec {
+    Fixed(NaiveTime),
+    Solar(SolarEvent, i64), // offset in seconds
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub start:  TimeSpec,
+    pub end:    TimeSpec,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DayRule {
+    pub days:       Vec<Weekday>,
+    pub windows:    Vec<Window>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeasonRule {
+    pub name:       String,
+    pub from:       NaiveDate,
+    pub to:         NaiveDate,
+    pub weekly:     Vec<DayRule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    pub weekly:     Vec<DayRule>,
+    pub seasons:    Vec<SeasonRule>,
+    pub exceptions: Vec<(NaiveDate, Vec<Window>)>,
+    pub location:   Option<Location>,
+    pub zone:       Zone,
+}
+
+// HH:MM or HH:MM:SS offset from a solar event
+fn parse_offset(offset: &str) -> Option<i64> {
+    let parts: Vec<&str> = offset.split(':').collect();
+    if parts.len() < 2 || parts.len() > 3 {
+        return None
+    }
+    let mut seconds = 0;
+    for (part, scale) in parts.iter().zip([3600, 60, 1].iter()) {
+        match part.parse::<i64>() {
+            Ok (n) if n >= 0 => seconds += n * scale,
+            _ => return None,
+        }
+    }
+    Some(seconds)
+}
+
+// A fixed HH:MM:SS time or a solar event with an optional offset, such as sunset+00:15
+pub fn parse_time(time: &String) -> Result<TimeSpec, String> {
+    if let Ok (fixed) = NaiveTime::parse_from_str(time.as_str(), "%H:%M:%S") {
+        return Ok(TimeSpec::Fixed(fixed))
+    }
+
+    let trimmed = time.trim();
+    let (name, offset) = match trimmed.find(|c: char| c == '+' || c == '-') {
+        Some (i) => {
+            let sign = if &trimmed[i..i + 1] == "-" { -1 } else { 1 };
+            match parse_offset(&trimmed[i + 1..]) {
+                Some (seconds) => (&trimmed[..i], sign * seconds),
+                None => return Err(format!("Unable to use time '{}': offset should be HH:MM or HH:MM:SS", time)),
+            }
+        },
+        None => (trimmed, 0),
+    };
+    match solar::parse_event(name.trim()) {
+        Some (event) => Ok(TimeSpec::Solar(event, offset)),
+        None => Err(format!("Unable to use time '{}': expected HH:MM:SS or a solar event such as sunset+00:15", time)),
+    }
+}
+
+pub fn parse_date(date: &String) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(date.as_str(), "%Y-%m-%d")
+        .map_err(|e| format!("Unable to use date '{}': {}", date, e))
+}
+
+fn to_window(params: &DailySchedule) -> Result<Window, String> {
+    Ok(Window {
+        start:  parse_time(&params.start)?,
+        end:    parse_time(&params.end)?,
+    })
+}
+
+fn to_windows(params: &Vec<DailySchedule>) -> Result<Vec<Window>, String> {
+    params.iter().map(to_window).collect()
+}
+
+fn to_weekday(day: &Day) -> Weekday {
+    match *day {
+        Day::Mon => Weekday::Mon,
+        Day::Tue => Weekday::Tue,
+        Day::Wed => Weekday::Wed,
+        Day::Thu => Weekday::Thu,
+        Day::Fri => Weekday::Fri,
+        Day::Sat => Weekday::Sat,
+        Day::Sun => Weekday::Sun,
+    }
+}
+
+fn to_day_rules(params: &Vec<WeeklyWindows>) -> Result<Vec<DayRule>, String> {
+    let mut rules = Vec::new();
+    for weekly in params {
+        rules.push(DayRule {
+            days:       weekly.days.iter().map(to_weekday).collect(),
+            windows:    to_windows(&weekly.windows)?,
+        });
+    }
+    Ok(rules)
+}
+
+// Build the schedule from config, None when there is no schedule and the soundscape always plays.
+// The daily_schedule window applies to every day of the regular week.
+pub fn from_config(config: &Soundscape) -> Result<Option<Schedule>, String> {
+    let mut schedule = Schedule {
+        weekly:     Vec::new(),
+        seasons:    Vec::new(),
+        exceptions: Vec::new(),
+        location:   config.schedule.as_ref().and_then(|params| params.location),
+        zone:       localtime::from_config(config)?,
+    };
+
+    if let Some (ref daily) = config.daily_schedule {
+        schedule.weekly.push(DayRule {
+            days:       vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun],
+            windows:    vec![to_window(daily)?],
+        });
+    }
+
+    let params = match config.schedule {
+        Some (ref params) => params,
+        None => return match config.daily_schedule {
+            Some (_) => check_location(schedule).map(Some),
+            None => Ok(None),
+        },
+    };
+
+    if let Some (ref weekly) = params.weekly {
+        schedule.weekly.extend(to_day_rules(weekly)?);
+    }
+
+    if let Some (ref seasons) = params.seasons {
+        for season in seasons {
+            let rule = SeasonRule {
+                name:   season.name.clone().unwrap_or(format!("{} to {}", season.from, season.to)),
+                from:   parse_date(&season.from)?,
+                to:     parse_date(&season.to)?,
+                weekly: to_day_rules(&season.weekly)?,
+            };
+            if rule.to < rule.from {
+                return Err(format!("Season '{}' ends before it starts", rule.name))
+            }
+            schedule.seasons.push(rule);
+        }
+    }
+
+    if let Some (ref exceptions) = params.exceptions {
+        for exception in exceptions {
+            let windows = match (exception.closed.unwrap_or(false), &exception.windows) {
+                (true, _)                   => Vec::new(),
+                (false, &Some (ref windows)) => to_windows(windows)?,
+                (false, &None)              => return Err(format!("Exception on {} is neither closed nor has windows", exception.date)),
+            };
+            schedule.exceptions.push((parse_date(&exception.date)?, windows));
+        }
+    }
+
+    check_location(schedule).map(Some)
+}
+
+fn uses_sun(windows: &Vec<Window>) -> bool {
+    windows.iter().any(|w| match (&w.start, &w.end) {
+        (&TimeSpec::Solar(..), _) | (_, &TimeSpec::Solar(..)) => true,
+        _ => false,
+    })
+}
+
+// Times relative to the sun need to know where the installation is
+fn check_location(schedule: Schedule) -> Result<Schedule, String> {
+    let needs_location = schedule.weekly.iter().any(|r| uses_sun(&r.windows))
+        || schedule.seasons.iter().any(|s| s.weekly.iter().any(|r| uses_sun(&r.windows)))
+        || schedule.exceptions.iter().any(|&(_, ref windows)| uses_sun(windows));
+    match (needs_location, schedule.location) {
+        (true, None) => Err("Schedule times relative to the sun require schedule.location".to_string()),
+        _ => Ok(schedule),
+    }
+}
+
+// Local date and time for the date, None if the sun doesn't reach the event that day. Times
+// relative to the sun keep their own date, as an offset can carry them past midnight.
+pub fn resolve_time(schedule: &Schedule, time: &TimeSpec, date: NaiveDate) -> Option<NaiveDateTime> {
+    match *time {
+        TimeSpec::Fixed(time) => Some(date.and_time(time)),
+        TimeSpec::Solar(event, offset) => {
+            let location = schedule.location?;
+            solar::event_utc(event, date, location.latitude, location.longitude)
+                .map(|utc| localtime::to_local(&schedule.zone, &DateTime::from_utc(utc, Utc)) + Duration::seconds(offset))
+        },
+    }
+}
+
+// Windows which start on the date, an exception replaces the season or regular week
+pub fn windows_on(schedule: &Schedule, date: NaiveDate) -> Vec<Window> {
+    if let Some (&(_, ref windows)) = schedule.exceptions.iter().find(|&&(d, _)| d == date) {
+        return windows.clone()
+    }
+
+    let weekly = match schedule.seasons.iter().find(|s| s.from <= date && date <= s.to) {
+        Some (season) => &season.weekly,
+        None => &schedule.weekly,
+    };
+    weekly.iter()
+        .filter(|rule| rule.days.contains(&date.weekday()))
+        .flat_map(|rule| rule.windows.iter().cloned())
+        .collect()
+}
+
+// Start and end of each window starting on the date, ends at or before the start are the next day.
+// Windows with a solar event which doesn't happen on the date are left out.
+pub fn spans_on(schedule: &Schedule, date: NaiveDate) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    windows_on(schedule, date).iter().filter_map(|window| {
+        let start = resolve_time(schedule, &window.start, date)?;
+        let end = resolve_time(schedule, &window.end, date)?;
+        let end = match end <= start {
+            true    => end + Duration::days(1),
+            false   => end,
+        };
+        Some((start, end))
+    }).collect()
+}
+
+// Is the clock within a window, windows from the day before are checked for overnight spans
+pub fn is_live_at(schedule: &Schedule, now: NaiveDateTime) -> bool {
+    let today = now.date();
+    let yesterday = today - Duration::days(1);
+    spans_on(schedule, yesterday).iter()
+        .chain(spans_on(schedule, today).iter())
+        .any(|&(start, end)| start <= now && now < end)
+}
+
+// Spans starting on the local date as instants
+pub fn instant_spans_on(schedule: &Schedule, date: NaiveDate) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+    spans_on(schedule, date).iter()
+        .map(|&(start, end)| (localtime::to_instant(&schedule.zone, &start), localtime::to_instant(&schedule.zone, &end)))
+        .collect()
+}
+
+// Is the instant within a window, compared as instants so a repeated hour is only counted once
+pub fn is_live_at_instant(schedule: &Schedule, instant: &DateTime<Utc>) -> bool {
+    let today = localtime::to_local(&schedule.zone, instant).date();
+    let yesterday = today - Duration::days(1);
+    instant_spans_on(schedule, yesterday).iter()
+        .chain(instant_spans_on(schedule, today).iter())
+        .any(|&(start, end)| start <= *instant && *instant < end)
+}
+
+// The earliest start or end of a span after the instant, looking up to a year ahead. Spans are
+// checked from the day before, for overnight windows, and a day past the first match in case
+// windows overlap.
+fn first_after(schedule: &Schedule, from: &DateTime<Utc>, is_start: bool) -> Option<DateTime<Utc>> {
+    let first_day = localtime::to_local(&schedule.zone, from).date() - Duration::days(1);
+    let mut found: Option<(i64, DateTime<Utc>)> = None;
+    for day in 0..367 {
+        if let Some ((found_day, instant)) = found {
+            if day > found_day + 1 {
+                return Some(instant)
+            }
+        }
+        for &(start, end) in &instant_spans_on(schedule, first_day + Duration::days(day)) {
+            let instant = if is_start { start } else { end };
+            if instant > *from && found.map(|(_, f)| instant < f).unwrap_or(true) {
+                found = Some((found.map(|(d, _)| d).unwrap_or(day), instant));
+            }
+        }
+    }
+    found.map(|(_, instant)| instant)
+}
+
+// The first window start after the instant, None if there isn't one within the next year
+pub fn next_start(schedule: &Schedule, from: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+    first_after(schedule, from, true)
+}
+
+// The first window end after the instant, None if there isn't one within the next year
+pub fn next_end(schedule: &Schedule, from: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+    first_after(schedule, from, false)
+}
+
+// The schedule's state and when it next needs checking, planned against the wall clock.
+// The monotonic clock reading taken alongside reveals the wall clock jumping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan {
+    pub is_live:        bool,
+    pub next_change:    Option<DateTime<Utc>>,
+    pub replan_at:      Option<DateTime<Utc>>, // the next change or local midnight, for sun times and the daily log
+    pub planned_at:     DateTime<Utc>,
+    pub planned_at_monotonic: Instant,
+}
+
+// Wall clock and monotonic time may drift apart this far before the plan is redone
+pub const CLOCK_JUMP_MS: i64 = 2000;
+
+pub fn plan(schedule: &Option<Schedule>, now: &DateTime<Utc>, monotonic: Instant) -> Plan {
+    let (is_live, next_change, replan_at) = match *schedule {
+        Some (ref schedule) => {
+            let is_live = is_live_at_instant(schedule, now);
+            let next_change = match is_live {
+                true    => next_end(schedule, now),
+                false   => next_start(schedule, now),
+            };
+            let tomorrow = localtime::to_local(&schedule.zone, now).date() + Duration::days(1);
+            let midnight = localtime::to_instant(&schedule.zone, &tomorrow.and_hms(0, 0, 0));
+            (is_live, next_change, Some(next_change.map(|change| change.min(midnight)).unwrap_or(midnight)))
+        },
+        None => (true, None, None),
+    };
+    Plan {
+        is_live:        is_live,
+        next_change:    next_change,
+        replan_at:      replan_at,
+        planned_at:     *now,
+        planned_at_monotonic: monotonic,
+    }
+}
+
+pub fn is_due(plan: &Plan, now: &DateTime<Utc>) -> bool {
+    plan.replan_at.map(|at| *now >= at).unwrap_or(false)
+}
+
+// Has the wall clock been stepped, by NTP or a suspend and resume, since planning
+pub fn has_clock_jumped(plan: &Plan, now: &DateTime<Utc>, monotonic: Instant) -> bool {
+    let wall_ms = now.signed_duration_since(plan.planned_at).num_milliseconds();
+    let elapsed = monotonic.duration_since(plan.planned_at_monotonic);
+    let monotonic_ms = (elapsed.as_secs() * 1000) as i64 + (elapsed.subsec_nanos() / 1_000_000) as i64;
+    (wall_ms - monotonic_ms).abs() > CLOCK_JUMP_MS
+}
+
+pub fn describe_plan(plan: &Plan, zone: &Zone) -> String {
+    let state = match plan.is_live {
+        true    => "live",
+        false   => "asleep",
+    };
+    match plan.next_change {
+        Some (change) => format!("Schedule is {} until {}", state, localtime::to_local(zone, &change)),
+        None => format!("Schedule is {} with no change planned", state),
+    }
+}
+
+// Log the windows for a date, with solar times resolved for the day
+pub fn describe_day(schedule: &Schedule, date: NaiveDate) -> String {
+    let mut spans: Vec<String> = spans_on(schedule, date).iter()
+        .map(|&(start, end)| format!("{} to {}", start, end))
+        .collect();
+    let skipped = windows_on(schedule, date).len() - spans.len();
+    if skipped > 0 {
+        spans.push(format!("{} window(s) skipped, the sun doesn't reach their event", skipped));
+    }
+    match spans.is_empty() {
+        true    => format!("{}: closed", date),
+        false   => format!("{}: {}", date, spans.join(", ")),
+    }
+}
@@ -0,0 +1,203 @@
+#[cfg(test)]
+mod schedule_test {
+    use schedule::*;
+    use config::*;
+    use solar::SolarEvent;
+    use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc};
+    use localtime;
+    use std::time::{Duration as StdDuration, Instant};
+
+    fn window(start: &str, end: &str) -> DailySchedule {
+        DailySchedule { start: start.to_string(), end: end.to_string() }
+    }
+
+    fn test_config(daily: Option<DailySchedule>, params: Option<ScheduleParams>) -> Soundscape {
+        Soundscape {
+            daily_schedule:         daily,
+            schedule:               params,
+            .. test_soundscape()
+        }
+    }
+
+    // 2018-05-25 is a Friday
+    fn at(date: &str, time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn without_schedule() {
+        assert_eq!(from_config(&test_config(None, None)).unwrap(), None);
+        let always = plan(&None, &Utc::now(), Instant::now());
+        assert!(always.is_live);
+        assert!(!is_due(&always, &Utc::now()));
+    }
+
+    #[test]
+    fn overnight_windows() {
+        let schedule = from_config(&test_config(Some (window("18:30:00", "01:00:00")), None)).unwrap().unwrap();
+        assert!(!is_live_at(&schedule, at("2018-05-25", "18:29:59")));
+        assert!(is_live_at(&schedule, at("2018-05-25", "18:30:00")));
+        assert!(is_live_at(&schedule, at("2018-05-25", "23:59:59")));
+        assert!(is_live_at(&schedule, at("2018-05-26", "00:30:00")));
+        assert!(!is_live_at(&schedule, at("2018-05-26", "01:00:00")));
+        assert!(!is_live_at(&schedule, at("2018-05-26", "12:00:00")));
+    }
+
+    #[test]
+    fn weeks_seasons_and_exceptions() {
+        let params = ScheduleParams {
+            location: None,
+            weekly: Some (vec![
+                WeeklyWindows { days: vec![Day::Fri, Day::Sat], windows: vec![window("10:00:00", "12:00:00"), window("19:00:00", "23:00:00")] },
+            ]),
+            seasons: Some (vec![ Season {
+                name:   Some ("festival".to_string()),
+                from:   "2018-06-01".to_string(),
+                to:     "2018-06-10".to_string(),
+                weekly: vec![ WeeklyWindows { days: vec![Day::Mon, Day::Tue, Day::Wed, Day::Thu, Day::Fri, Day::Sat, Day::Sun], windows: vec![window("18:00:00", "02:00:00")] } ],
+            } ]),
+            exceptions: Some (vec![
+                ScheduleException { date: "2018-05-26".to_string(), closed: Some (true), windows: None },
+                ScheduleException { date: "2018-06-05".to_string(), closed: None, windows: Some (vec![window("18:00:00", "04:00:00")]) },
+            ]),
+        };
+        let schedule = from_config(&test_config(None, Some (params))).unwrap().unwrap();
+
+        // Several windows on a day, none on other days of the week
+        assert!(is_live_at(&schedule, at("2018-05-25", "11:00:00")));
+        assert!(!is_live_at(&schedule, at("2018-05-25", "15:00:00")));
+        assert!(is_live_at(&schedule, at("2018-05-25", "20:00:00")));
+        assert!(!is_live_at(&schedule, at("2018-05-24", "20:00:00")));
+        // Closed on the Saturday
+        assert!(!is_live_at(&schedule, at("2018-05-26", "20:00:00")));
+        // The season replaces the week, running past midnight
+        assert!(is_live_at(&schedule, at("2018-06-04", "19:00:00")));
+        assert!(is_live_at(&schedule, at("2018-06-05", "01:00:00")));
+        assert!(!is_live_at(&schedule, at("2018-06-05", "03:00:00")));
+        // An extended night
+        assert!(is_live_at(&schedule, at("2018-06-06", "03:00:00")));
+        assert_eq!(windows_on(&schedule, NaiveDate::from_ymd(2018, 6, 11)), vec![]);
+    }
+
+    #[test]
+    fn solar_times() {
+        assert_eq!(parse_time(&"sunset+00:15".to_string()), Ok (TimeSpec::Solar(SolarEvent::Sunset, 900)));
+        assert_eq!(parse_time(&"civil_dusk-00:10".to_string()), Ok (TimeSpec::Solar(SolarEvent::CivilDusk, -600)));
+        assert_eq!(parse_time(&"sunrise".to_string()), Ok (TimeSpec::Solar(SolarEvent::Sunrise, 0)));
+        assert!(parse_time(&"sunset+15".to_string()).is_err());
+        assert!(parse_time(&"moonrise".to_string()).is_err());
+
+        // Sun times need a location
+        assert!(from_config(&test_config(Some (window("sunset", "23:00:00")), None)).is_err());
+
+        // Tromsø at midsummer has no sunset, so the window is skipped
+        let params = ScheduleParams {
+            location:   Some (Location { latitude: 69.65, longitude: 18.96 }),
+            weekly:     None,
+            seasons:    None,
+            exceptions: None,
+        };
+        let schedule = from_config(&test_config(Some (window("sunset", "23:00:00")), Some (params))).unwrap().unwrap();
+        assert_eq!(spans_on(&schedule, NaiveDate::from_ymd(2018, 6, 21)), vec![]);
+        assert_eq!(spans_on(&schedule, NaiveDate::from_ymd(2018, 9, 21)).len(), 1);
+    }
+
+    #[test]
+    fn solar_times_past_midnight() {
+        let params = ScheduleParams {
+            location:   Some (Location { latitude: 52.52, longitude: 13.40 }),
+            weekly:     None,
+            seasons:    None,
+            exceptions: None,
+        };
+        let mut config = test_config(Some (window("sunset+03:00", "04:00:00")), Some (params));
+        config.timezone = Some ("Europe/Berlin".to_string());
+        let schedule = from_config(&config).unwrap().unwrap();
+        // Berlin's midsummer sunset is around 21:33, so the window starts after midnight
+        let midsummer = NaiveDate::from_ymd(2018, 6, 21);
+        let spans = spans_on(&schedule, midsummer);
+        assert_eq!(spans.len(), 1);
+        let (start, end) = spans[0];
+        assert_eq!(start.date(), midsummer.succ());
+        assert_eq!(start.hour(), 0);
+        assert_eq!(end, at("2018-06-22", "04:00:00"));
+    }
+
+    fn berlin_nights() -> Schedule {
+        let mut config = test_config(Some (window("18:30:00", "02:30:00")), None);
+        config.timezone = Some ("Europe/Berlin".to_string());
+        from_config(&config).unwrap().unwrap()
+    }
+
+    fn utc(date: &str, time: &str) -> DateTime<Utc> {
+        DateTime::from_utc(at(date, time), Utc)
+    }
+
+    #[test]
+    fn daylight_saving_changes() {
+        let schedule = berlin_nights();
+        // 02:30 was skipped on 2018-03-25, the night runs on to 03:30 CEST
+        assert_eq!(next_end(&schedule, &utc("2018-03-24", "18:00:00")), Some (utc("2018-03-25", "01:30:00")));
+        assert!(is_live_at_instant(&schedule, &utc("2018-03-25", "01:15:00")));
+        assert!(!is_live_at_instant(&schedule, &utc("2018-03-25", "01:30:00")));
+        // 02:30 happened twice on 2018-10-28, the night ends the first time
+        assert_eq!(next_end(&schedule, &utc("2018-10-27", "18:00:00")), Some (utc("2018-10-28", "00:30:00")));
+        assert!(!is_live_at_instant(&schedule, &utc("2018-10-28", "01:15:00")));
+        // Starts follow the wall clock, not a fixed offset
+        assert_eq!(next_start(&schedule, &utc("2018-10-28", "12:00:00")), Some (utc("2018-10-28", "17:30:00")));
+        assert_eq!(next_start(&schedule, &utc("2018-10-27", "12:00:00")), Some (utc("2018-10-27", "16:30:00")));
+    }
+
+    quickcheck! {
+        // From any instant the next start is within a day and is at 18:30 on the wall clock
+        fn next_start_is_the_next_evening(seconds: u32) -> bool {
+            let schedule = berlin_nights();
+            let from = DateTime::from_utc(NaiveDateTime::from_timestamp(seconds as i64, 0), Utc);
+            match next_start(&schedule, &from) {
+                Some (start) => start > from
+                    && start - from <= Duration::hours(25)
+                    && localtime::to_local(&schedule.zone, &start).time() == NaiveTime::from_hms(18, 30, 0)
+                    && is_live_at_instant(&schedule, &start)
+                    && !is_live_at_instant(&schedule, &(start - Duration::seconds(1))),
+                None => false,
+            }
+        }
+
+        // Every night lasts eight hours, whichever way the clocks change
+        fn nights_last_eight_hours(seconds: u32) -> bool {
+            let schedule = berlin_nights();
+            let from = DateTime::from_utc(NaiveDateTime::from_timestamp(seconds as i64, 0), Utc);
+            let start = next_start(&schedule, &from).unwrap();
+            let end = next_end(&schedule, &start).unwrap();
+            let length = end - start;
+            length == Duration::hours(8)
+                && is_live_at_instant(&schedule, &(end - Duration::seconds(1)))
+                && !is_live_at_instant(&schedule, &end)
+                && localtime::to_local(&schedule.zone, &end).minute() == 30
+        }
+    }
+
+    #[test]
+    fn plans() {
+        let schedule = Some (berlin_nights());
+        let monotonic = Instant::now();
+        let noon = utc("2018-10-27", "10:00:00");
+        let noon_plan = plan(&schedule, &noon, monotonic);
+        assert!(!noon_plan.is_live);
+        assert_eq!(noon_plan.next_change, Some (utc("2018-10-27", "16:30:00")));
+        assert!(!is_due(&noon_plan, &utc("2018-10-27", "16:29:59")));
+        assert!(is_due(&noon_plan, &utc("2018-10-27", "16:30:00")));
+
+        // Planned again at local midnight even without a change
+        let evening = utc("2018-10-27", "17:00:00");
+        let evening_plan = plan(&schedule, &evening, monotonic);
+        assert!(evening_plan.is_live);
+        assert_eq!(evening_plan.next_change, Some (utc("2018-10-28", "00:30:00")));
+        assert_eq!(evening_plan.replan_at, Some (utc("2018-10-27", "22:00:00")));
+
+        // Wall and monotonic clocks moving together, then the wall clock stepped
+        assert!(!has_clock_jumped(&evening_plan, &(evening + Duration::seconds(60)), monotonic + StdDuration::from_secs(60)));
+        assert!(has_clock_jumped(&evening_plan, &(evening + Duration::hours(8)), monotonic + StdDuration::from_secs(60)));
+        assert!(has_clock_jumped(&evening_plan, &(evening - Duration::seconds(5)), monotonic + StdDuration::from_secs(1)));
+    }
+}
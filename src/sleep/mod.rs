@@ -0,0 +1,201 @@
+use config::{SleepMode, SleepParams, Soundscape, WakeMode};
+use soundscape;
+use soundscape::{Cmd, FutureCmd, Origin, SoundSource};
+
+use std::collections::BinaryHeap;
+
+mod sleep_tests;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Phase {
+    Awake,
+    Closing,    // playing the closing scene
+    FadingOut,
+    Asleep,
+    Waking,     // playing the opening scene or about to resume
+}
+
+// Sleep
+// Takes the soundscape into and out of sleep with the schedule. Closing and opening scenes are
+// loaded by index like any other scene, numbered after the scenes of the playlist.
+pub struct Sleep {
+    pub phase:              Phase,
+    pub fade_ms:            Option<f32>,
+    pub mode:               SleepMode,
+    pub wake:               WakeMode,
+    pub closing_scene:      Option<usize>,
+    pub opening_scene:      Option<usize>,
+    pub current_scene:      usize, // Last playlist scene loaded, to resume with
+    pub is_wake_pending:    bool, // Woken before falling asleep
+    pub is_paused_in_place: bool,
+    is_closing_played:      bool,
+    held:                   Vec<FutureCmd>, // Scene commands waiting for the soundscape to wake
+    held_at_ms:             i64,
+}
+
+pub fn new(params: Option<&SleepParams>, scene_count: usize) -> Sleep {
+    let closing_scene = params.and_then(|p| p.closing_scene.as_ref()).map(|_| scene_count);
+    let opening_scene = params.and_then(|p| p.opening_scene.as_ref())
+        .map(|_| scene_count + closing_scene.map(|_| 1).unwrap_or(0));
+    Sleep {
+        phase:              Phase::Awake,
+        fade_ms:            params.and_then(|p| p.fade_ms),
+        mode:               params.and_then(|p| p.resources).unwrap_or(SleepMode::Pause),
+        wake:               params.and_then(|p| p.wake).unwrap_or(WakeMode::Resume),
+        closing_scene:      closing_scene,
+        opening_scene:      opening_scene,
+        current_scene:      0,
+        is_wake_pending:    false,
+        is_paused_in_place: false,
+        is_closing_played:  false,
+        held:               Vec::new(),
+        held_at_ms:         0,
+    }
+}
+
+pub fn from_config(config: &Soundscape) -> Sleep {
+    new(config.sleep.as_ref(), config.scenes.len())
+}
+
+// Every scene file which can be loaded, the playlist's scenes then the closing and opening scenes
+pub fn scene_files(config: &Soundscape) -> Vec<String> {
+    let mut files = config.scenes.clone();
+    if let Some (ref params) = config.sleep {
+        files.extend(params.closing_scene.iter().cloned());
+        files.extend(params.opening_scene.iter().cloned());
+    }
+    files
+}
+
+pub fn is_closing_scene(sleep: &Sleep, scene_index: usize) -> bool {
+    sleep.closing_scene == Some(scene_index)
+}
+
+pub fn is_opening_scene(sleep: &Sleep, scene_index: usize) -> bool {
+    sleep.opening_scene == Some(scene_index)
+}
+
+// Should sources follow their curves, structures advance and events play
+pub fn is_audible(sleep: &Sleep) -> bool {
+    match sleep.phase {
+        Phase::Awake | Phase::Closing | Phase::Waking => true,
+        Phase::FadingOut | Phase::Asleep => false,
+    }
+}
+
+fn is_scene_command(command: &Cmd) -> bool {
+    match *command {
+        Cmd::Play | Cmd::Load (..) | Cmd::Retire (_) => true,
+        _ => false,
+    }
+}
+
+fn is_sleep_command(command: &Cmd) -> bool {
+    match *command {
+        Cmd::Sleep | Cmd::Asleep | Cmd::Resume => true,
+        _ => false,
+    }
+}
+
+// Take the scene's pending commands out of the queue so they wait for the soundscape to wake.
+// Pending steps into or out of sleep, such as the resume due at the end of an opening scene,
+// are dropped as going to sleep starts over.
+fn hold_scene_commands(sleep: &mut Sleep, commands: &mut BinaryHeap<FutureCmd>, now_ms: i64) {
+    let (held, kept): (Vec<FutureCmd>, Vec<FutureCmd>) = commands.drain()
+        .filter(|c| !is_sleep_command(&c.command))
+        .partition(|c| is_scene_command(&c.command));
+    commands.extend(kept);
+    sleep.held = held;
+    sleep.held_at_ms = now_ms;
+}
+
+// Start going to sleep, returning the command to run now.
+// The closing scene is skipped when starting up outside the schedule.
+pub fn begin(sleep: &mut Sleep, commands: &mut BinaryHeap<FutureCmd>, now_ms: i64, with_closing: bool) -> FutureCmd {
+    hold_scene_commands(sleep, commands, now_ms);
+    sleep.is_wake_pending = false;
+    match (with_closing, sleep.closing_scene) {
+        (true, Some (index)) => {
+            sleep.phase = Phase::Closing;
+            sleep.is_closing_played = true;
+            soundscape::load_at(index, Origin::Internal, now_ms)
+        },
+        _ => {
+            sleep.phase = Phase::FadingOut;
+            soundscape::sleep_at(now_ms)
+        },
+    }
+}
+
+// Fall asleep once faded out, returns true if sources should be paused to resume in place
+// and false if they should be released.
+pub fn settle(sleep: &mut Sleep) -> bool {
+    sleep.phase = Phase::Asleep;
+    // Nothing from before a closing scene is left to resume
+    sleep.is_paused_in_place = sleep.mode == SleepMode::Pause && !sleep.is_closing_played;
+    if !sleep.is_paused_in_place {
+        sleep.held.clear();
+    }
+    sleep.is_paused_in_place
+}
+
+// Start waking up, returning the command to run now.
+// A soundscape woken while closing or fading out wakes as soon as it is asleep.
+pub fn wake(sleep: &mut Sleep, now_ms: i64) -> Option<FutureCmd> {
+    match sleep.phase {
+        Phase::Asleep => {
+            sleep.is_wake_pending = false;
+            sleep.phase = Phase::Waking;
+            Some(match sleep.opening_scene {
+                Some (index) => soundscape::load_at(index, Origin::Internal, now_ms),
+                None => soundscape::resume_at(now_ms),
+            })
+        },
+        Phase::Closing | Phase::FadingOut => {
+            sleep.is_wake_pending = true;
+            None
+        },
+        Phase::Awake | Phase::Waking => None,
+    }
+}
+
+// Finish waking. Returns the held scene commands, moved on by the time asleep, when paused
+// sources resume in place, otherwise None and a scene should be loaded.
+pub fn resume(sleep: &mut Sleep, now_ms: i64) -> Option<Vec<FutureCmd>> {
+    sleep.phase = Phase::Awake;
+    sleep.is_closing_played = false;
+    let in_place = sleep.wake == WakeMode::Resume && sleep.is_paused_in_place && sleep.opening_scene.is_none();
+    sleep.is_paused_in_place = false;
+
+    let asleep_ms = now_ms - sleep.held_at_ms;
+    let held: Vec<FutureCmd> = sleep.held.drain(..)
+        .map(|c| FutureCmd { command: c.command, at_tick: c.at_tick + asleep_ms })
+        .collect();
+    match in_place {
+        true    => Some(held),
+        false   => None,
+    }
+}
+
+// Fade sources out with the sleep fade or their own, returning the longest fade
+pub fn fade_out(sources: &mut Vec<SoundSource>, fade_ms: Option<f32>) -> f32 {
+    let mut longest = 0f32;
+    for s in sources {
+        let ms = fade_ms.unwrap_or(s.fade_out_ms);
+        soundscape::volume_fade(s, 0.0, ms);
+        longest = longest.max(ms);
+    }
+    longest
+}
+
+// Unpause sources and fade the live ones back in
+pub fn fade_in(sources: &mut Vec<SoundSource>, fade_ms: Option<f32>, default_level: f32) {
+    for s in sources {
+        s.channel.play();
+        if s.is_live {
+            let volume = default_level + s.gain;
+            let ms = fade_ms.unwrap_or(s.fade_in_ms);
+            soundscape::volume_fade(s, volume, ms);
+        }
+    }
+}
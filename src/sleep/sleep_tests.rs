@@ -0,0 +1,101 @@
+#[cfg(test)]
+mod sleep_test {
+    use sleep::*;
+    use config::{SleepMode, SleepParams, WakeMode};
+    use soundscape;
+    use soundscape::{Cmd, Origin};
+    use std::collections::BinaryHeap;
+
+    fn params(closing: bool, opening: bool, resources: SleepMode, wake: WakeMode) -> SleepParams {
+        SleepParams {
+            fade_ms:        Some (4000.0),
+            closing_scene:  if closing { Some ("closing.yml".to_string()) } else { None },
+            opening_scene:  if opening { Some ("opening.yml".to_string()) } else { None },
+            resources:      Some (resources),
+            wake:           Some (wake),
+        }
+    }
+
+    fn queue() -> BinaryHeap<soundscape::FutureCmd> {
+        let mut commands = BinaryHeap::new();
+        commands.push(soundscape::load_at(1, Origin::Internal, 60_000));
+        commands.push(soundscape::retire_at(3, 62_000));
+        commands.push(soundscape::check_shedule(10_000));
+        commands
+    }
+
+    #[test]
+    fn special_scenes_follow_the_playlist() {
+        let sleep = new(Some (&params(true, true, SleepMode::Pause, WakeMode::Resume)), 3);
+        assert_eq!(sleep.closing_scene, Some (3));
+        assert_eq!(sleep.opening_scene, Some (4));
+        let sleep = new(Some (&params(false, true, SleepMode::Pause, WakeMode::Resume)), 3);
+        assert_eq!(sleep.opening_scene, Some (3));
+        assert!(is_opening_scene(&sleep, 3));
+    }
+
+    #[test]
+    fn resume_in_place() {
+        let mut sleep = new(Some (&params(false, false, SleepMode::Pause, WakeMode::Resume)), 2);
+        let mut commands = queue();
+        assert!(begin(&mut sleep, &mut commands, 50_000, true) == soundscape::sleep_at(50_000));
+        // Only the schedule check is left running
+        assert_eq!(commands.len(), 1);
+        assert!(!is_audible(&sleep));
+
+        assert!(settle(&mut sleep));
+        assert!(wake(&mut sleep, 150_000) == Some (soundscape::resume_at(150_000)));
+        let held = resume(&mut sleep, 150_000).unwrap();
+        assert_eq!(held.len(), 2);
+        assert!(held.iter().any(|c| c.command == Cmd::Load(1, Origin::Internal, 0) && c.at_tick == 160_000));
+        assert!(held.iter().any(|c| c.command == Cmd::Retire(3) && c.at_tick == 162_000));
+        assert!(is_audible(&sleep));
+    }
+
+    #[test]
+    fn closing_and_opening_scenes() {
+        let mut sleep = new(Some (&params(true, true, SleepMode::Pause, WakeMode::Resume)), 2);
+        let mut commands = queue();
+        assert!(begin(&mut sleep, &mut commands, 50_000, true) == soundscape::load_at(2, Origin::Internal, 50_000));
+        assert_eq!(sleep.phase, Phase::Closing);
+
+        // Woken while closing
+        assert!(wake(&mut sleep, 55_000) == None);
+        assert!(sleep.is_wake_pending);
+
+        // The closing scene isn't resumed, the interrupted scene is loaded again
+        assert!(!settle(&mut sleep));
+        assert!(wake(&mut sleep, 70_000) == Some (soundscape::load_at(3, Origin::Internal, 70_000)));
+        assert!(resume(&mut sleep, 80_000).is_none());
+    }
+
+    #[test]
+    fn sleep_during_the_opening_scene() {
+        let mut sleep = new(Some (&params(false, true, SleepMode::Pause, WakeMode::Resume)), 2);
+        let mut commands = queue();
+        begin(&mut sleep, &mut commands, 0, false);
+        settle(&mut sleep);
+        assert!(wake(&mut sleep, 10_000) == Some (soundscape::load_at(2, Origin::Internal, 10_000)));
+        // The opening scene plays and resumes once it ends
+        commands.push(soundscape::play_at(10_010));
+        commands.push(soundscape::resume_at(70_000));
+
+        // Asleep again before it ends, so the resume is dropped rather than waking the soundscape
+        assert!(begin(&mut sleep, &mut commands, 30_000, true) == soundscape::sleep_at(30_000));
+        assert!(!commands.iter().any(|c| c.command == Cmd::Resume));
+        assert!(!is_audible(&sleep));
+        settle(&mut sleep);
+        assert!(wake(&mut sleep, 100_000) == Some (soundscape::load_at(2, Origin::Internal, 100_000)));
+        assert!(resume(&mut sleep, 160_000).is_none());
+    }
+
+    #[test]
+    fn starting_outside_the_schedule_skips_closing() {
+        let mut sleep = new(Some (&params(true, false, SleepMode::Release, WakeMode::Restart)), 2);
+        let mut commands = queue();
+        assert!(begin(&mut sleep, &mut commands, 0, false) == soundscape::sleep_at(0));
+        assert!(!settle(&mut sleep));
+        wake(&mut sleep, 10_000);
+        assert!(resume(&mut sleep, 10_000).is_none());
+    }
+}
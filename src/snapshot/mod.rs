@@ -0,0 +1,159 @@
+use rosc::{OscMessage, OscPacket, OscType};
+use soundscape;
+use soundscape::FutureCmd;
+
+mod snapshot_tests;
+
+// Time between requests while no snapshot has arrived
+const RETRY_MS: i64 = 1000;
+
+// State snapshot
+// What a unit joining the leader needs to play along from part-way through a scene.
+// Overrides are the control inputs which are receiving values, and carry their latest value.
+// The next scene is the leader's queued load after the current scene, a joining unit queues it too.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub scene:              usize,
+    pub scene_start_ms:     i64, // on the leader's timeline
+    pub time_ms:            i64,
+    pub level:              f32,
+    pub is_schedule_live:   bool,
+    pub is_audible:         bool,
+    pub next_scene:         Option<(usize, i64)>, // scene and load time on the leader's timeline
+    pub overrides:          Vec<(String, f32)>,
+}
+
+// Asks the leader being followed for a snapshot, again if none arrives
+pub struct Requester {
+    pub leader:             Option<u32>,
+    pub is_answered:        bool,
+    pub since_request_ms:   i64,
+}
+
+pub fn new_requester() -> Requester {
+    Requester {
+        leader:             None,
+        is_answered:        false,
+        since_request_ms:   0,
+    }
+}
+
+// Returns true when a request should be sent to the leader
+pub fn tick(requester: &mut Requester, leader: Option<u32>, tick_ms: i64) -> bool {
+    if requester.leader != leader {
+        requester.leader = leader;
+        requester.is_answered = false;
+        requester.since_request_ms = RETRY_MS;
+    }
+    if leader.is_none() || requester.is_answered {
+        return false
+    }
+    requester.since_request_ms += tick_ms;
+    if requester.since_request_ms >= RETRY_MS {
+        requester.since_request_ms = 0;
+        true
+    }
+    else {
+        false
+    }
+}
+
+pub fn answered(requester: &mut Requester) {
+    requester.is_answered = true;
+}
+
+// Has the leader being followed sent its snapshot, reset when the leader changes
+pub fn is_joined(requester: &Requester) -> bool {
+    requester.leader.is_some() && requester.is_answered
+}
+
+pub fn request_packet(unit_id: u32) -> OscPacket {
+    OscPacket::Message(OscMessage {
+        addr: "/StateRequest".to_string(),
+        args: Some( vec![ OscType::Int(unit_id as i32) ] ),
+    })
+}
+
+// The snapshot names the unit which asked, as it goes to every subscriber. Without a next
+// scene its index is -1. Overrides follow the fixed arguments as name and value pairs.
+pub fn to_packet(unit_id: u32, snapshot: &Snapshot) -> OscPacket {
+    let mut args = vec![
+        OscType::Int(unit_id as i32),
+        OscType::Int(snapshot.scene as i32),
+        OscType::Long(snapshot.scene_start_ms),
+        OscType::Long(snapshot.time_ms),
+        OscType::Float(snapshot.level),
+        OscType::Bool(snapshot.is_schedule_live),
+        OscType::Bool(snapshot.is_audible),
+        OscType::Int(snapshot.next_scene.map(|(n, _)| n as i32).unwrap_or(-1)),
+        OscType::Long(snapshot.next_scene.map(|(_, at_ms)| at_ms).unwrap_or(0)),
+    ];
+    for &(ref name, value) in &snapshot.overrides {
+        args.push(OscType::String(name.clone()));
+        args.push(OscType::Float(value));
+    }
+    OscPacket::Message(OscMessage {
+        addr: "/StateSnapshot".to_string(),
+        args: Some(args),
+    })
+}
+
+// The unit the snapshot is for and the snapshot
+pub fn from_args(args: &[OscType]) -> Result<(u32, Snapshot), String> {
+    if args.len() < 9 || (args.len() - 9) % 2 != 0 {
+        return Err(format!("StateSnapshot expected 9 arguments and name, value pairs, received {}", args.len()))
+    }
+    let mut overrides = Vec::new();
+    for pair in args[9..].chunks(2) {
+        match (&pair[0], &pair[1]) {
+            (&OscType::String(ref name), &OscType::Float(value)) => overrides.push((name.clone(), value)),
+            _ => return Err(format!("StateSnapshot override expected String, Float, received: {:?}", pair)),
+        }
+    }
+    match &args[..9] {
+        [OscType::Int(unit_id), OscType::Int(scene), OscType::Long(scene_start_ms), OscType::Long(time_ms), OscType::Float(level), OscType::Bool(is_schedule_live), OscType::Bool(is_audible), OscType::Int(next_scene), OscType::Long(next_load_ms)] if *scene >= 0 => {
+            Ok((*unit_id as u32, Snapshot {
+                scene:              *scene as usize,
+                scene_start_ms:     *scene_start_ms,
+                time_ms:            *time_ms,
+                level:              *level,
+                is_schedule_live:   *is_schedule_live,
+                is_audible:         *is_audible,
+                next_scene:         if *next_scene >= 0 { Some ((*next_scene as usize, *next_load_ms)) } else { None },
+                overrides:          overrides,
+            }))
+        },
+        other => Err(format!("StateSnapshot requires Int, Int, Long, Long, Float, Bool, Bool, Int, Long, received: {:?}", other)),
+    }
+}
+
+// Loads which bring a unit in line with the leader's snapshot at now_ms: its current scene as far
+// in as the leader is, unless already playing it from the same start, and the scene after it.
+// Playing is the scene this unit is playing and when it started, if any.
+pub fn join(snapshot: &Snapshot, scene_count: usize, playing: Option<(usize, i64)>, now_ms: i64) -> Vec<FutureCmd> {
+    let mut commands = Vec::new();
+    if !snapshot.is_audible {
+        println!("Leader is asleep, not joining its scene.");
+        return commands
+    }
+    if snapshot.scene >= scene_count {
+        println!("Leader is playing scene {}, which this unit doesn't have.", snapshot.scene);
+        return commands
+    }
+    if playing == Some ((snapshot.scene, snapshot.scene_start_ms)) {
+        println!("Already playing scene {} with the leader.", snapshot.scene);
+    }
+    else {
+        // Loaded as far in as the leader is, so it lines up with the leader's
+        println!("Joining the leader {}ms into scene {}.", snapshot.time_ms - snapshot.scene_start_ms, snapshot.scene);
+        commands.push(soundscape::load_offset_at(snapshot.scene, soundscape::Origin::Remote, now_ms, now_ms - snapshot.scene_start_ms));
+    }
+    // The leader only announces the next scene as it loads the current one
+    match snapshot.next_scene {
+        Some ((next, next_load_ms)) if next < scene_count && next_load_ms > now_ms => {
+            commands.push(soundscape::load_at(next, soundscape::Origin::Remote, next_load_ms));
+        },
+        _ => (),
+    }
+    commands
+}
@@ -0,0 +1,88 @@
+#[cfg(test)]
+mod snapshot_test {
+    use snapshot::*;
+    use soundscape;
+    use rosc::{OscPacket, OscType};
+    use std::collections::BinaryHeap;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            scene:              2,
+            scene_start_ms:     120_000,
+            time_ms:            185_250,
+            level:              0.8,
+            is_schedule_live:   true,
+            is_audible:         true,
+            next_scene:         Some ((0, 240_000)),
+            overrides:          vec![("wind".to_string(), 0.25), ("crowd".to_string(), 1.5)],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        match to_packet(7, &snapshot()) {
+            OscPacket::Message(message) => {
+                let args = message.args.unwrap();
+                assert_eq!(from_args(&args), Ok((7, snapshot())));
+                // Without the last override's value
+                assert!(from_args(&args[..args.len() - 1]).is_err());
+            },
+            _ => panic!("Expected a message"),
+        }
+        assert!(from_args(&[OscType::Int(7)]).is_err());
+
+        let last_scene = Snapshot { next_scene: None, .. snapshot() };
+        match to_packet(7, &last_scene) {
+            OscPacket::Message(message) => assert_eq!(from_args(&message.args.unwrap()), Ok((7, last_scene))),
+            _ => panic!("Expected a message"),
+        }
+    }
+
+    #[test]
+    fn joins_mid_scene_then_loads_the_next() {
+        let state = snapshot();
+        let mut commands = BinaryHeap::new();
+        for command in join(&state, 3, None, 185_250) {
+            commands.push(command);
+        }
+        assert!(commands.pop() == Some (soundscape::load_offset_at(2, soundscape::Origin::Remote, 185_250, 65_250)));
+        // The next scene loads at the boundary, as it does on the leader
+        assert!(!soundscape::is_cmd_now(commands.peek(), &239_990));
+        assert!(soundscape::is_cmd_now(commands.peek(), &240_000));
+        assert!(commands.pop() == Some (soundscape::load_at(0, soundscape::Origin::Remote, 240_000)));
+        assert!(commands.is_empty());
+
+        // Already playing along, only the next scene is queued, which may be already
+        let next = join(&state, 3, Some ((2, 120_000)), 185_250);
+        assert!(next == vec![soundscape::load_at(0, soundscape::Origin::Remote, 240_000)]);
+        commands.push(next[0]);
+        assert!(soundscape::is_queued(&commands, &next[0]));
+
+        assert!(join(&Snapshot { is_audible: false, .. snapshot() }, 3, None, 185_250).is_empty());
+        assert!(join(&state, 2, None, 185_250).is_empty());
+        // A next scene this unit doesn't have, or which is already due, is left out
+        assert!(join(&Snapshot { next_scene: Some ((3, 240_000)), .. snapshot() }, 3, None, 185_250).len() == 1);
+        assert_eq!(join(&state, 3, None, 240_000).len(), 1);
+    }
+
+    #[test]
+    fn requests_until_answered() {
+        let mut requester = new_requester();
+        assert!(!tick(&mut requester, None, 10));
+        assert!(tick(&mut requester, Some (3), 10));
+        assert!(!tick(&mut requester, Some (3), 500));
+        assert!(tick(&mut requester, Some (3), 500));
+
+        assert!(!is_joined(&requester));
+        answered(&mut requester);
+        assert!(is_joined(&requester));
+        assert!(!tick(&mut requester, Some (3), 1000));
+        // A new leader is asked again, and joined afresh
+        assert!(tick(&mut requester, Some (4), 10));
+        assert!(!is_joined(&requester));
+        // Without a leader there is no one to join
+        answered(&mut requester);
+        tick(&mut requester, None, 10);
+        assert!(!is_joined(&requester));
+    }
+}
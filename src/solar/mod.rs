@@ -0,0 +1,99 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use std::f64::consts::PI;
+
+mod solar_tests;
+
+// Solar events
+// Times of sunrise, sunset and twilight from the sunrise equation, accurate to a minute or two
+// which is plenty for scheduling. Everything is computed locally from the date and location.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SolarEvent {
+    Sunrise,
+    Sunset,
+    CivilDawn,
+    CivilDusk,
+    NauticalDawn,
+    NauticalDusk,
+    AstronomicalDawn,
+    AstronomicalDusk,
+    Noon,
+}
+
+pub fn parse_event(name: &str) -> Option<SolarEvent> {
+    match name {
+        "sunrise"           => Some(SolarEvent::Sunrise),
+        "sunset"            => Some(SolarEvent::Sunset),
+        "civil_dawn"        => Some(SolarEvent::CivilDawn),
+        "civil_dusk"        => Some(SolarEvent::CivilDusk),
+        "nautical_dawn"     => Some(SolarEvent::NauticalDawn),
+        "nautical_dusk"     => Some(SolarEvent::NauticalDusk),
+        "astronomical_dawn" => Some(SolarEvent::AstronomicalDawn),
+        "astronomical_dusk" => Some(SolarEvent::AstronomicalDusk),
+        "noon"              => Some(SolarEvent::Noon),
+        _                   => None,
+    }
+}
+
+// Elevation of the sun's centre at the event, in degrees, and if it is in the morning
+fn elevation(event: SolarEvent) -> (f64, bool) {
+    match event {
+        // Refraction and the radius of the sun's disc
+        SolarEvent::Sunrise             => (-0.833, true),
+        SolarEvent::Sunset              => (-0.833, false),
+        SolarEvent::CivilDawn           => (-6.0, true),
+        SolarEvent::CivilDusk           => (-6.0, false),
+        SolarEvent::NauticalDawn        => (-12.0, true),
+        SolarEvent::NauticalDusk        => (-12.0, false),
+        SolarEvent::AstronomicalDawn    => (-18.0, true),
+        SolarEvent::AstronomicalDusk    => (-18.0, false),
+        SolarEvent::Noon                => (90.0, true),
+    }
+}
+
+const UNIX_EPOCH_JULIAN: f64 = 2440587.5;
+const J2000: f64 = 2451545.0;
+
+fn to_radians(degrees: f64) -> f64 {
+    degrees * PI / 180.0
+}
+
+fn to_degrees(radians: f64) -> f64 {
+    radians * 180.0 / PI
+}
+
+// UTC time of the event on the date at the location, longitude is positive to the east.
+// None when the sun doesn't reach the event's elevation, such as a summer night near the poles.
+pub fn event_utc(event: SolarEvent, date: NaiveDate, latitude: f64, longitude: f64) -> Option<NaiveDateTime> {
+    let epoch_days = date.signed_duration_since(NaiveDate::from_ymd(1970, 1, 1)).num_days() as f64;
+    let n = (epoch_days + UNIX_EPOCH_JULIAN + 0.5 - J2000 + 0.0008).round();
+
+    // Mean solar noon
+    let j_star = n - longitude / 360.0;
+    let mean_anomaly = (357.5291 + 0.98560028 * j_star) % 360.0;
+    let m = to_radians(mean_anomaly);
+    let centre = 1.9148 * m.sin() + 0.0200 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+    let ecliptic_longitude = to_radians((mean_anomaly + centre + 180.0 + 102.9372) % 360.0);
+    let transit = J2000 + j_star + 0.0053 * m.sin() - 0.0069 * (2.0 * ecliptic_longitude).sin();
+
+    let (event_elevation, is_morning) = elevation(event);
+    let julian = match event {
+        SolarEvent::Noon => transit,
+        _ => {
+            let declination = (ecliptic_longitude.sin() * to_radians(23.4397).sin()).asin();
+            let phi = to_radians(latitude);
+            let cos_hour_angle = (to_radians(event_elevation).sin() - phi.sin() * declination.sin())
+                / (phi.cos() * declination.cos());
+            if cos_hour_angle < -1.0 || cos_hour_angle > 1.0 {
+                return None
+            }
+            let hour_angle = to_degrees(cos_hour_angle.acos());
+            match is_morning {
+                true    => transit - hour_angle / 360.0,
+                false   => transit + hour_angle / 360.0,
+            }
+        },
+    };
+
+    let unix_seconds = ((julian - UNIX_EPOCH_JULIAN) * 86_400.0).round() as i64;
+    Some(NaiveDateTime::from_timestamp(unix_seconds, 0))
+}
@@ -0,0 +1,37 @@
+#[cfg(test)]
+mod solar_test {
+    use solar::*;
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    fn assert_near(actual: Option<NaiveDateTime>, expected: &str) {
+        let expected = NaiveDateTime::parse_from_str(expected, "%Y-%m-%d %H:%M").unwrap();
+        let difference = actual.unwrap().signed_duration_since(expected).num_seconds().abs();
+        assert!(difference <= 120, "{:?} is not near {}", actual, expected);
+    }
+
+    #[test]
+    fn sunrise_and_sunset() {
+        let midsummer = NaiveDate::from_ymd(2018, 6, 21);
+        // London, 04:43 and 21:21 BST
+        assert_near(event_utc(SolarEvent::Sunrise, midsummer, 51.5074, -0.1278), "2018-06-21 03:43");
+        assert_near(event_utc(SolarEvent::Sunset, midsummer, 51.5074, -0.1278), "2018-06-21 20:21");
+        // Sydney, 07:00 and 16:54 AEST with dusk at 17:21, the sunrise is the day before in UTC
+        assert_near(event_utc(SolarEvent::Sunrise, midsummer, -33.8688, 151.2093), "2018-06-20 21:00");
+        assert_near(event_utc(SolarEvent::Sunset, midsummer, -33.8688, 151.2093), "2018-06-21 06:54");
+        assert_near(event_utc(SolarEvent::CivilDusk, midsummer, -33.8688, 151.2093), "2018-06-21 07:21");
+    }
+
+    #[test]
+    fn midnight_sun() {
+        let midsummer = NaiveDate::from_ymd(2018, 6, 21);
+        // Tromsø
+        assert_eq!(event_utc(SolarEvent::Sunset, midsummer, 69.65, 18.96), None);
+        assert!(event_utc(SolarEvent::Noon, midsummer, 69.65, 18.96).is_some());
+    }
+
+    #[test]
+    fn event_names() {
+        assert_eq!(parse_event("civil_dusk"), Some (SolarEvent::CivilDusk));
+        assert_eq!(parse_event("dusk"), None);
+    }
+}
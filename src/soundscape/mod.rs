@@ -1,94 +1,369 @@
 
 use rodiox::diffusion_sink::DiffusionSink;
-use rodio::Device;
+use rodiox::master_bus::MasterBus;
+use rodiox::source::{FadeShape, VariantControl};
+use rodiox::source::limiter::db_to_amplitude;
 
-use bspline;
+use curves;
+use variants;
+use variants::VariantPicker;
+use curves::Curve;
 
 use config;
-use config::SoundResource;
+use config::{ModulationTarget, ResponseShape, SoundResource, VariantChange};
 
 use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::f32;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+mod soundscape_tests;
 
 pub struct SoundSource {
+    pub name:           String,
+    pub curve:          String, // Name of the curve which drives activation
     pub channel:        DiffusionSink,
     pub min_threshold:  f32,
     pub max_threshold:  f32,
+    pub hysteresis:     f32,
+    pub crossfade:      f32,
+    pub min_on_ms:      i64,
+    pub min_off_ms:     i64,
+    pub since_change_ms: i64, // Time since is_live last changed
+    pub band_gain:      f32, // Gain from crossfading at the threshold edges
     pub gain:           f32,
-    pub volume:         f32,
-    pub volume_step:    f32,
-    pub volume_updates: u32,
-    pub fade_out_steps: u32,
-    pub fade_in_steps:  u32,
+    pub fade_in_ms:     f32,
+    pub fade_out_ms:    f32,
+    pub fade_shape:     FadeShape,
+    pub position:       [f32; 3],
+    pub modulations:    Vec<Modulation>,
+    pub variants:       VariantPicker,
+    pub variant_control: Arc<VariantControl>,
+    pub variants_started: usize, // Variant starts seen by update_variants
+    pub pending_change: Option<(bool, i64)>, // Change of is_live waiting for a beat or bar
+    pub transition_fade: Option<(FadeShape, i64)>, // Shape and end of the crossfade the scene came in on
     pub is_live:        bool, // Is the suound within threshhold bounds
 }
 
-pub fn resource_to_sound_source(res: &SoundResource, output_device: &Device, speakers: &Vec<[f32; 3]>) -> SoundSource {
+// Continuous control of a source parameter by a curve
+pub struct Modulation {
+    pub target:     ModulationTarget,
+    pub curve:      String,
+    pub input:      [f32; 2],
+    pub output:     [f32; 2],
+    pub shape:      ResponseShape,
+}
+
+pub fn resource_to_sound_source(res: &SoundResource, curve: String, master_bus: &MasterBus, speakers: &Vec<[f32; 3]>, metro_step_ms: u32) -> SoundSource {
     let position = match res.position {
         Some (pos)  => pos,
         None        => [0.0, 1.0, 1.0],
     };
 
-    let fade_in = match res.fade_in_steps {
-        Some (duration)     => duration,
-        None                => 500,
+    let fade_shape = match res.fade_shape {
+        Some (ref curve)    => config::to_fade_shape(curve),
+        None                => FadeShape::Linear,
+    };
+
+    let name = match Path::new(&res.path).file_stem().and_then(|stem| stem.to_str()) {
+        Some (stem) => stem.to_string(),
+        None        => res.path.clone(),
     };
 
-    let fade_out = match res.fade_out_steps {
-        Some (duration)     => duration,
-        None                => 500,
+    let modulations = match res.modulations {
+        Some (ref params) => params.iter().map(|m| Modulation {
+                target: m.target,
+                curve:  m.curve.clone().unwrap_or(curve.clone()),
+                input:  m.input,
+                output: m.output,
+                shape:  m.shape.unwrap_or(ResponseShape::Linear),
+            }).collect(),
+        None => Vec::new(),
     };
 
+    let mut variants = variants::from_resource(res);
+    let first_variant = variants::pick(&mut variants);
+
     SoundSource {
-        channel:        DiffusionSink::new(output_device, position, speakers.to_vec()),
+        name:           name,
+        curve:          curve,
+        channel:        DiffusionSink::new(master_bus, position, speakers.to_vec()),
         min_threshold:  res.min_threshold,
         max_threshold:  res.max_threshold,
+        hysteresis:     res.hysteresis.unwrap_or(0.0).max(0.0),
+        crossfade:      res.crossfade.unwrap_or(0.0).max(0.0),
+        min_on_ms:      res.min_on_ms.unwrap_or(0) as i64,
+        min_off_ms:     res.min_off_ms.unwrap_or(0) as i64,
+        since_change_ms: i64::max_value(),
+        band_gain:      1.0,
         gain:           res.gain,
-        volume:         0f32,
-        volume_step:    0.01,
-        volume_updates: 0,
-        fade_in_steps:  fade_in,
-        fade_out_steps: fade_out,
+        fade_in_ms:     config::fade_in_ms(res, metro_step_ms),
+        fade_out_ms:    config::fade_out_ms(res, metro_step_ms),
+        fade_shape:     fade_shape,
+        position:       position,
+        modulations:    modulations,
+        variants:       variants,
+        variant_control: VariantControl::new(first_variant),
+        variants_started: 1,
+        pending_change: None,
+        transition_fade: None,
         is_live:        false,
     }
 }
 
-pub fn update(source: &mut SoundSource) {
-    if source.volume_updates > 0 {
-        source.volume           += source.volume_step;
-        source.volume_updates   -= 1;
-        source.channel.set_volume(source.volume)
-    }
+// Fades are run per sample in the audio thread
+pub fn volume_fade(source: &mut SoundSource, volume_target: f32, duration_ms: f32) {
+    let shape = source.fade_shape;
+    source.channel.fade_to(volume_target, Duration::from_millis(duration_ms.max(0.0) as u64), shape)
+}
+
+// Fade in as the source goes live. During the crossfade from the previous scene the fade follows
+// the transition's shape and ends with the outgoing scene's fade, unless its own fade is longer.
+pub fn fade_in(source: &mut SoundSource, volume_target: f32, now_ms: i64) {
+    let (duration_ms, shape) = match source.transition_fade {
+        Some ((shape, end_ms)) if now_ms < end_ms => (((end_ms - now_ms) as f32).max(source.fade_in_ms), shape),
+        _ => (source.fade_in_ms, source.fade_shape),
+    };
+    source.channel.fade_to(volume_target, Duration::from_millis(duration_ms.max(0.0) as u64), shape)
 }
 
-pub fn volume_fade(source: &mut SoundSource, volume_target: f32, steps: u32) {
-    source.volume_updates = steps;
-    let steps = steps as f32;
-    let fade_step = (source.volume - volume_target) / steps;
-    if volume_target > source.volume {
-        source.volume_step = fade_step.abs()
+pub fn is_fading(source: &SoundSource) -> bool {
+    source.channel.is_fading()
+}
+
+// Decide if a source should be live for the curve value, returns the new state when it changes.
+// Once live a source stays live until the curve passes a threshold by the hysteresis margin, and
+// no change is made until the source has held its current state for min_on_ms or min_off_ms.
+// Hold times count from when a change is heard, so a change waiting for a beat or bar is held.
+pub fn update_activity(source: &mut SoundSource, value: f32, is_schedule_live: bool, step_ms: i64) -> Option<bool> {
+    source.since_change_ms = source.since_change_ms.saturating_add(step_ms);
+
+    let half_crossfade = source.crossfade / 2.0;
+    let margin = match source.is_live {
+        true    => source.hysteresis,
+        false   => 0.0,
+    };
+    let in_band = source.min_threshold - half_crossfade - margin < value
+        && value < source.max_threshold + half_crossfade + margin;
+
+    let hold_ms = match source.is_live {
+        true    => source.min_on_ms,
+        false   => source.min_off_ms,
+    };
+    let is_held = hold_ms > 0 && (source.pending_change.is_some() || source.since_change_ms < hold_ms);
+
+    // Leaving the schedule is never held back
+    let is_live = match (is_schedule_live, is_held) {
+        (false, _)      => false,
+        (true, true)    => source.is_live,
+        (true, false)   => in_band,
+    };
+
+    if is_live != source.is_live {
+        source.is_live = is_live;
+        source.since_change_ms = 0;
+        Some(is_live)
     }
     else {
-        source.volume_step = fade_step.abs() * -1f32;
+        None
+    }
+}
+
+// Hold a change of activity until at_ms.
+// A change made while the opposite change is still waiting cancels it, as neither is heard.
+pub fn schedule_change(source: &mut SoundSource, is_live: bool, at_ms: i64) {
+    match source.pending_change.take() {
+        Some (_) => (),
+        None => source.pending_change = Some((is_live, at_ms)),
+    }
+}
+
+// Take the waiting change of activity once it is due, hold times start from here
+pub fn take_due_change(source: &mut SoundSource, now_ms: i64) -> Option<bool> {
+    match source.pending_change {
+        Some ((is_live, at_ms)) if at_ms <= now_ms => {
+            source.pending_change = None;
+            source.since_change_ms = 0;
+            Some(is_live)
+        },
+        _ => None,
+    }
+}
+
+// Start a new variant as the source goes live, it is silent so the change can't be heard.
+// Given a time on the audio clock the source is held until then, so the variant can start on a
+// beat or bar rather than whenever the audio thread next reads it.
+pub fn change_variant_on_activation(source: &mut SoundSource, start_audio_ms: Option<i64>) {
+    if source.variants.change == VariantChange::Activation && source.variants.weights.len() > 1 {
+        let index = variants::pick(&mut source.variants);
+        // Held before the restart is asked for, so it can't be taken early
+        if let Some (audio_ms) = start_audio_ms {
+            source.channel.start_at(audio_ms);
+        }
+        source.variant_control.restart(index);
+    }
+}
+
+// Queue the variant to follow the one playing, once the previously queued variant has started
+pub fn update_variants(source: &mut SoundSource) {
+    if source.variants.change != VariantChange::Cycle || source.variants.weights.len() < 2 {
+        return
+    }
+    let started = source.variant_control.started();
+    if started != source.variants_started {
+        source.variants_started = started;
+        let index = variants::pick(&mut source.variants);
+        source.variant_control.select(index);
+    }
+}
+
+// Equal power gain ramps across each threshold, so sources sharing a threshold crossfade
+pub fn band_gain(source: &SoundSource, value: f32) -> f32 {
+    if source.crossfade <= 0.0 {
+        return 1.0
+    }
+    let half_crossfade = source.crossfade / 2.0;
+    let rising = (value - (source.min_threshold - half_crossfade)) / source.crossfade;
+    let falling = ((source.max_threshold + half_crossfade) - value) / source.crossfade;
+    let t = rising.min(falling).max(0.0).min(1.0);
+    (t * f32::consts::FRAC_PI_2).sin()
+}
+
+// How sharply the exponential and logarithmic responses bend, the exponential response rises
+// by e^4, about 35 dB, across the input range
+const RESPONSE_CURVATURE: f32 = 4.0;
+
+// Map a curve value through a modulation's input range, response shape and output range
+pub fn modulate(modulation: &Modulation, value: f32) -> f32 {
+    let span = modulation.input[1] - modulation.input[0];
+    let t = match span == 0.0 {
+        true    => if value >= modulation.input[1] { 1.0 } else { 0.0 },
+        false   => ((value - modulation.input[0]) / span).max(0.0).min(1.0),
+    };
+    let shaped = match modulation.shape {
+        ResponseShape::Linear       => t,
+        ResponseShape::Exponential  => (RESPONSE_CURVATURE * t).exp_m1() / RESPONSE_CURVATURE.exp_m1(),
+        ResponseShape::Logarithmic  => (RESPONSE_CURVATURE.exp_m1() * t).ln_1p() / RESPONSE_CURVATURE,
+        ResponseShape::SCurve       => t * t * (3.0 - 2.0 * t),
+    };
+    modulation.output[0] + (modulation.output[1] - modulation.output[0]) * shaped
+}
+
+// Apply a source's modulations from the current curve values, along with its band gain
+pub fn apply_modulations(source: &mut SoundSource, values: &Vec<(String, f32)>) {
+    if source.modulations.is_empty() {
+        source.channel.set_modulation_gain(source.band_gain);
+        return
+    }
+
+    let mut gain_db = 0f32;
+    let mut position = None;
+    let mut reverb_mix = None;
+    let mut speed = None;
+    for modulation in &source.modulations {
+        let value = modulate(modulation, curve_value(values, &modulation.curve));
+        match modulation.target {
+            ModulationTarget::GainDb    => gain_db += value,
+            ModulationTarget::X         => position.get_or_insert(source.position)[0] = value,
+            ModulationTarget::Y         => position.get_or_insert(source.position)[1] = value,
+            ModulationTarget::Z         => position.get_or_insert(source.position)[2] = value,
+            ModulationTarget::ReverbMix => reverb_mix = Some(value),
+            ModulationTarget::Speed     => speed = Some(speed.unwrap_or(1.0) * value),
+        }
+    }
+
+    source.channel.set_modulation_gain(db_to_amplitude(gain_db) * source.band_gain);
+    if let Some (pos) = position {
+        source.channel.set_emitter_position(pos);
+    }
+    if let Some (mix) = reverb_mix {
+        source.channel.set_reverb_mix(mix);
+    }
+    if let Some (factor) = speed {
+        source.channel.set_speed(factor);
     }
 }
 
 // Structure
 pub struct Structure {
-    pub spline:         bspline::BSpline<f32>,
+    pub name:           String,
+    pub curve:          Box<dyn Curve>,
     pub duration:       f32,
-    pub step_t:         f32,
     pub step:           f32,
 }
 
-pub fn structure_from_scene(scene: &config::Scene) -> Structure {
-    let spline      = config::to_b_spline(&scene.structure);
-    let duration    = scene.cycle_duration_ms as f32;
-    let step_t      = spline.knot_domain().1 / duration;
-    Structure {
-        spline:     spline,
-        duration:   duration,
-        step_t:     step_t,
-        step:       0.0,
+// Build a structure for each curve in the scene, in the order given by config::scene_curve_names
+pub fn structures_from_scene(scene: &config::Scene) -> Vec<Structure> {
+    let named = curves::scene_curves(scene);
+    named.iter()
+        .map(|curve| Structure {
+            name:       curve.name.clone(),
+            curve:      curves::from_structure(curve.params, curve.duration_ms, &named),
+            duration:   curve.duration_ms,
+            step:       0.0,
+        })
+        .collect()
+}
+
+// Move each structure forward, each cycles over its own duration
+pub fn advance_structures(structures: &mut Vec<Structure>, step_ms: f32) {
+    for structure in structures {
+        structure.step += step_ms;
+        if structure.step > structure.duration {
+            structure.step = 0f32;
+        }
+    }
+}
+
+// Set each structure to where it would be after position_ms of the scene
+pub fn seek_structures(structures: &mut Vec<Structure>, position_ms: f32) {
+    for structure in structures {
+        structure.step = match structure.duration > 0.0 {
+            true    => position_ms.max(0.0) % structure.duration,
+            false   => 0.0,
+        };
+    }
+}
+
+pub fn structure_value(structure: &Structure) -> f32 {
+    structure.curve.value_at(structure.step)
+}
+
+// Current value of each curve, by name
+pub fn structure_values(structures: &Vec<Structure>) -> Vec<(String, f32)> {
+    structures.iter()
+        .map(|s| (s.name.clone(), structure_value(s)))
+        .collect()
+}
+
+// Start each structure where it is closest to the value of the curve with the same name
+pub fn match_structures(structures: &mut Vec<Structure>, values: &Vec<(String, f32)>) {
+    // Positions tried across each cycle
+    const SEARCH_STEPS: usize = 256;
+    for structure in structures {
+        let target = match values.iter().find(|&&(ref n, _)| n == &structure.name) {
+            Some (&(_, value)) => value,
+            None => continue,
+        };
+        let mut best = (0f32, f32::MAX);
+        for i in 0..SEARCH_STEPS {
+            let step = structure.duration * i as f32 / SEARCH_STEPS as f32;
+            let distance = (structure.curve.value_at(step) - target).abs();
+            if distance < best.1 {
+                best = (step, distance);
+            }
+        }
+        structure.step = best.0;
+    }
+}
+
+// Value of the named curve, falls back to the first curve if the name is not present
+pub fn curve_value(values: &Vec<(String, f32)>, name: &str) -> f32 {
+    match values.iter().find(|&&(ref n, _)| n == name) {
+        Some (&(_, value))  => value,
+        None                => values.first().map(|&(_, value)| value).unwrap_or(0.0),
     }
 }
 
@@ -102,10 +377,13 @@ pub enum Origin {
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum Cmd {
     Play,
-    Load (usize, Origin),
+    Load (usize, Origin, i64), // Scene, origin and how far into the scene to start
     LoadBackground,
     CheckSchedule,
-    Retire,
+    Retire (usize), // Retires the scene from this load, if it is still playing
+    Sleep,          // Fades everything out
+    Asleep,         // Pauses or releases sources once faded out
+    Resume,         // Carries on or restarts after waking
 }
 
 
@@ -120,21 +398,37 @@ pub fn play_at(tick: i64) -> FutureCmd {
 }
 
 pub fn load_at(scene_index: usize, origin: Origin, tick: i64) -> FutureCmd {
-    FutureCmd { command: Cmd::Load(scene_index, origin), at_tick: tick }
+    load_offset_at(scene_index, origin, tick, 0)
+}
+
+pub fn load_offset_at(scene_index: usize, origin: Origin, tick: i64, offset_ms: i64) -> FutureCmd {
+    FutureCmd { command: Cmd::Load(scene_index, origin, offset_ms.max(0)), at_tick: tick }
 }
 
 pub fn load_background(tick: i64) -> FutureCmd {
     FutureCmd { command: Cmd::LoadBackground, at_tick: tick }
 }
 
-pub fn retire_at(tick: i64) -> FutureCmd {
-    FutureCmd { command: Cmd::Retire, at_tick: tick }
+pub fn retire_at(scene_load: usize, tick: i64) -> FutureCmd {
+    FutureCmd { command: Cmd::Retire(scene_load), at_tick: tick }
 }
 
 pub fn check_shedule(tick: i64) -> FutureCmd {
     FutureCmd { command: Cmd::CheckSchedule, at_tick: tick }
 }
 
+pub fn sleep_at(tick: i64) -> FutureCmd {
+    FutureCmd { command: Cmd::Sleep, at_tick: tick }
+}
+
+pub fn asleep_at(tick: i64) -> FutureCmd {
+    FutureCmd { command: Cmd::Asleep, at_tick: tick }
+}
+
+pub fn resume_at(tick: i64) -> FutureCmd {
+    FutureCmd { command: Cmd::Resume, at_tick: tick }
+}
+
 // Explicitly implement the trait so the queue becomes a min-heap instead of a max-heap.
 impl Ord for FutureCmd {
     fn cmp(&self, other: &FutureCmd) -> Ordering {
@@ -159,3 +453,8 @@ pub fn is_cmd_now(command: Option<&FutureCmd>, ticks: &i64) -> bool {
         None        => false,
     }
 }
+
+// Is the same command already queued for the same time
+pub fn is_queued(commands: &BinaryHeap<FutureCmd>, command: &FutureCmd) -> bool {
+    commands.iter().any(|queued| queued == command)
+}
@@ -0,0 +1,196 @@
+#[cfg(test)]
+mod soundscape_test {
+    use soundscape::*;
+    use config::{CurveParams, ModulationTarget, ResponseShape, SoundResource};
+    use curves;
+    use rodiox::master_bus::MasterBus;
+    use rodiox::source::limiter::{db_to_amplitude, LimiterSettings};
+
+    fn test_source(hysteresis: f32, crossfade: f32, min_on_ms: u32) -> SoundSource {
+        let (bus, _output) = MasterBus::new(2, 44100, &LimiterSettings::default());
+        let res = SoundResource {
+            path:           "example/samples/pad-loop2a.flac".to_string(),
+            curve:          None,
+            min_threshold:  2.7,
+            max_threshold:  4.7,
+            hysteresis:     Some (hysteresis),
+            crossfade:      Some (crossfade),
+            min_on_ms:      Some (min_on_ms),
+            min_off_ms:     None,
+            gain:           0.0,
+            fade_in_steps:  None,
+            fade_out_steps: None,
+            fade_in_ms:     None,
+            fade_out_ms:    None,
+            fade_shape:     None,
+            reverb:         None,
+            position:       None,
+            modulations:    None,
+            weight:         None,
+            variants:       None,
+            variant_mode:   None,
+            variant_change: None,
+            seed:           None,
+        };
+        resource_to_sound_source(&res, "structure".to_string(), &bus, &vec![[-1.0, 1.0, 1.0], [1.0, 1.0, 1.0]], 10)
+    }
+
+    #[test]
+    fn activity_hysteresis() {
+        let mut source = test_source(0.2, 0.0, 0);
+        assert_eq!(update_activity(&mut source, 4.6, true, 10), Some (true));
+        // Within the margin beyond the threshold the source stays live
+        assert_eq!(update_activity(&mut source, 4.8, true, 10), None);
+        assert_eq!(update_activity(&mut source, 4.6, true, 10), None);
+        assert_eq!(update_activity(&mut source, 5.0, true, 10), Some (false));
+        // but must be back within the thresholds to go live again
+        assert_eq!(update_activity(&mut source, 4.8, true, 10), None);
+        assert_eq!(update_activity(&mut source, 4.6, true, 10), Some (true));
+        assert_eq!(update_activity(&mut source, 4.6, false, 10), Some (false));
+    }
+
+    #[test]
+    fn activity_holds() {
+        let mut source = test_source(0.0, 0.0, 100);
+        assert_eq!(update_activity(&mut source, 3.0, true, 10), Some (true));
+        assert_eq!(update_activity(&mut source, 6.0, true, 50), None);
+        assert_eq!(update_activity(&mut source, 6.0, true, 50), Some (false));
+    }
+
+    #[test]
+    fn changes_wait_until_due() {
+        let mut source = test_source(0.0, 0.0, 0);
+        schedule_change(&mut source, true, 200);
+        assert_eq!(take_due_change(&mut source, 100), None);
+        assert_eq!(take_due_change(&mut source, 200), Some (true));
+        assert_eq!(take_due_change(&mut source, 300), None);
+
+        // The opposite change while one waits cancels both
+        schedule_change(&mut source, false, 400);
+        schedule_change(&mut source, true, 400);
+        assert_eq!(source.pending_change, None);
+        assert_eq!(take_due_change(&mut source, 500), None);
+    }
+
+    #[test]
+    fn holds_count_from_applied_change() {
+        let mut source = test_source(0.0, 0.0, 100);
+        assert_eq!(update_activity(&mut source, 3.0, true, 10), Some (true));
+        schedule_change(&mut source, true, 300);
+        // Past min_on_ms since the flip but not yet heard
+        assert_eq!(update_activity(&mut source, 6.0, true, 150), None);
+        assert_eq!(take_due_change(&mut source, 160), None);
+        assert_eq!(update_activity(&mut source, 6.0, true, 140), None);
+        assert_eq!(take_due_change(&mut source, 300), Some (true));
+        assert_eq!(update_activity(&mut source, 6.0, true, 50), None);
+        assert_eq!(update_activity(&mut source, 6.0, true, 50), Some (false));
+    }
+
+    #[test]
+    fn band_crossfade() {
+        let source = test_source(0.0, 0.4, 0);
+        assert_eq!(band_gain(&source, 3.5), 1.0);
+        assert_eq!(band_gain(&source, 2.4), 0.0);
+        assert_eq!(band_gain(&source, 5.0), 0.0);
+        // Equal power at a shared threshold
+        assert!((band_gain(&source, 4.7) - 0.5f32.sqrt()).abs() < 0.0001);
+    }
+
+    fn modulation(target: ModulationTarget, curve: &str, output: [f32; 2], shape: ResponseShape) -> Modulation {
+        Modulation { target: target, curve: curve.to_string(), input: [0.0, 10.0], output: output, shape: shape }
+    }
+
+    #[test]
+    fn modulation_shapes() {
+        let linear = modulation(ModulationTarget::GainDb, "structure", [-20.0, 0.0], ResponseShape::Linear);
+        assert_eq!(modulate(&linear, 5.0), -10.0);
+        // Clamped to the input range
+        assert_eq!(modulate(&linear, -3.0), -20.0);
+        assert_eq!(modulate(&linear, 12.0), 0.0);
+
+        let exponential = modulation(ModulationTarget::Speed, "structure", [0.0, 1.0], ResponseShape::Exponential);
+        let logarithmic = modulation(ModulationTarget::Speed, "structure", [0.0, 1.0], ResponseShape::Logarithmic);
+        let s_curve = modulation(ModulationTarget::Speed, "structure", [0.0, 1.0], ResponseShape::SCurve);
+        for shape in [&exponential, &logarithmic, &s_curve].iter() {
+            assert!(modulate(shape, 0.0).abs() < 1e-6);
+            assert!((modulate(shape, 10.0) - 1.0).abs() < 1e-6);
+        }
+        // Equal steps in the input multiply the exponential response's distance from its floor
+        let rise = |value: f32| modulate(&exponential, value) + 1.0 / 4f32.exp_m1();
+        assert!((rise(7.5) / rise(5.0) - rise(5.0) / rise(2.5)).abs() < 1e-4);
+        assert!((modulate(&exponential, 5.0) - 0.1192).abs() < 1e-4);
+        // Logarithmic undoes exponential
+        assert!((modulate(&logarithmic, modulate(&exponential, 3.0) * 10.0) - 0.3).abs() < 1e-4);
+        assert!((modulate(&s_curve, 5.0) - 0.5).abs() < 1e-6);
+
+        // A zero width input range switches at its edge
+        let switch = Modulation { input: [4.0, 4.0], ..modulation(ModulationTarget::ReverbMix, "structure", [0.0, 1.0], ResponseShape::Linear) };
+        assert_eq!(modulate(&switch, 3.9), 0.0);
+        assert_eq!(modulate(&switch, 4.0), 1.0);
+    }
+
+    #[test]
+    fn modulations_combine() {
+        let mut source = test_source(0.0, 0.0, 0);
+        source.band_gain = 0.5;
+        apply_modulations(&mut source, &vec![]);
+        assert_eq!(source.channel.modulation_gain(), 0.5);
+
+        source.position = [0.0, 2.0, 1.0];
+        source.modulations = vec![
+            modulation(ModulationTarget::GainDb, "structure", [-20.0, 0.0], ResponseShape::Linear),
+            modulation(ModulationTarget::GainDb, "wind", [0.0, -6.0], ResponseShape::Linear),
+            modulation(ModulationTarget::X, "wind", [-1.0, 1.0], ResponseShape::Linear),
+            modulation(ModulationTarget::Speed, "structure", [0.5, 1.5], ResponseShape::Linear),
+            modulation(ModulationTarget::Speed, "wind", [1.0, 3.0], ResponseShape::Linear),
+        ];
+        apply_modulations(&mut source, &vec![("structure".to_string(), 5.0), ("wind".to_string(), 10.0)]);
+        // Gains in dB add up, speeds multiply and unmodulated axes keep the source's position
+        assert!((source.channel.modulation_gain() - db_to_amplitude(-16.0) * 0.5).abs() < 1e-6);
+        assert_eq!(source.channel.emitter_position(), [1.0, 2.0, 1.0]);
+        assert!((source.channel.speed() - 3.0).abs() < 1e-6);
+
+        // A curve missing from the scene falls back to the first curve
+        apply_modulations(&mut source, &vec![("structure".to_string(), 0.0)]);
+        assert_eq!(source.channel.emitter_position(), [-1.0, 2.0, 1.0]);
+    }
+
+    fn ramp(name: &str, step: f32) -> Structure {
+        Structure {
+            name:       name.to_string(),
+            curve:      curves::from_params(&CurveParams::Breakpoints { points: vec![ [0.0, 0.0], [1000.0, 10.0] ], interpolation: None }, 1000.0, &[]),
+            duration:   1000.0,
+            step:       step,
+        }
+    }
+
+    #[test]
+    fn structures_match_previous_values() {
+        let mut structures = vec![ramp("structure", 0.0), ramp("density", 500.0), ramp("drift", 100.0)];
+        match_structures(&mut structures, &vec![ ("structure".to_string(), 2.5), ("density".to_string(), 9.0), ("wind".to_string(), 1.0) ]);
+        assert_eq!(structures[0].step, 250.0);
+        assert!((structure_value(&structures[1]) - 9.0).abs() < 0.05);
+        // Curves the previous scene didn't have are left alone
+        assert_eq!(structures[2].step, 100.0);
+    }
+
+    #[test]
+    fn structures_seek_within_their_duration() {
+        let mut structures = vec![ramp("structure", 0.0), ramp("density", 700.0)];
+        seek_structures(&mut structures, 2_250.0);
+        assert_eq!(structures[0].step, 250.0);
+        assert_eq!(structures[1].step, 250.0);
+        seek_structures(&mut structures, -10.0);
+        assert_eq!(structures[0].step, 0.0);
+    }
+
+    #[test]
+    fn loads_part_way_through() {
+        let load = load_offset_at(2, Origin::Remote, 60_000, 15_000);
+        assert!(load.command == Cmd::Load(2, Origin::Remote, 15_000));
+        assert_eq!(load.at_tick, 60_000);
+        // Offsets before the start load from the beginning
+        assert!(load_offset_at(2, Origin::Remote, 60_000, -500).command == Cmd::Load(2, Origin::Remote, 0));
+        assert!(load_at(1, Origin::Internal, 0).command == Cmd::Load(1, Origin::Internal, 0));
+    }
+}
@@ -0,0 +1,56 @@
+use config::{Quantize, Scene};
+
+mod tempo_tests;
+
+// Tempo grid
+// Beats and bars counted on the transport from the time a scene starts playing.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Grid {
+    pub origin_ms:      i64,
+    pub beat_ms:        f64,
+    pub beats_per_bar:  u32,
+    pub quantize:       Quantize,
+}
+
+// A grid for scenes which declare a tempo
+pub fn from_scene(scene: &Scene, origin_ms: i64) -> Option<Grid> {
+    match scene.bpm {
+        Some (bpm) if bpm > 0.0 => Some(Grid {
+            origin_ms:      origin_ms,
+            beat_ms:        60_000.0 / bpm as f64,
+            beats_per_bar:  scene.beats_per_bar.unwrap_or(4).max(1),
+            quantize:       scene.quantize.unwrap_or(Quantize::Bar),
+        }),
+        _ => None,
+    }
+}
+
+pub fn bar_ms(grid: &Grid) -> f64 {
+    grid.beat_ms * grid.beats_per_bar as f64
+}
+
+// The first boundary of the given period at or after time_ms
+fn next_boundary(grid: &Grid, period_ms: f64, time_ms: i64) -> i64 {
+    let since_origin = (time_ms - grid.origin_ms) as f64;
+    let periods = (since_origin / period_ms).ceil().max(0.0);
+    grid.origin_ms + (periods * period_ms).round() as i64
+}
+
+pub fn next_beat(grid: &Grid, time_ms: i64) -> i64 {
+    next_boundary(grid, grid.beat_ms, time_ms)
+}
+
+pub fn next_bar(grid: &Grid, time_ms: i64) -> i64 {
+    next_boundary(grid, bar_ms(grid), time_ms)
+}
+
+// Snap to the grid's quantize setting, times are left alone without a grid
+pub fn snap(grid: &Option<Grid>, time_ms: i64) -> i64 {
+    match *grid {
+        Some (ref grid) => match grid.quantize {
+            Quantize::Beat  => next_beat(grid, time_ms),
+            Quantize::Bar   => next_bar(grid, time_ms),
+        },
+        None => time_ms,
+    }
+}
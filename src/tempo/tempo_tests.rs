@@ -0,0 +1,21 @@
+#[cfg(test)]
+mod tempo_test {
+    use tempo::*;
+    use config::Quantize;
+
+    fn grid(quantize: Quantize) -> Option<Grid> {
+        // 120 bpm in 3/4, a beat every 500ms and a bar every 1500ms
+        Some (Grid { origin_ms: 1000, beat_ms: 500.0, beats_per_bar: 3, quantize: quantize })
+    }
+
+    #[test]
+    fn snapping() {
+        assert_eq!(snap(&grid(Quantize::Bar), 1000), 1000);
+        assert_eq!(snap(&grid(Quantize::Bar), 1001), 2500);
+        assert_eq!(snap(&grid(Quantize::Bar), 4000), 4000);
+        assert_eq!(snap(&grid(Quantize::Beat), 1001), 1500);
+        // Times before the grid starts wait for its first bar
+        assert_eq!(snap(&grid(Quantize::Beat), 0), 1000);
+        assert_eq!(snap(&None, 1234), 1234);
+    }
+}
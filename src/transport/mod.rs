@@ -0,0 +1,86 @@
+use rodiox::source::clock::AudioClock;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+mod transport_tests;
+
+// Transport
+// Maps time on the audio clock to the soundscape's timeline.
+// The offset lets a slave follow its master's timeline without touching the audio clock.
+pub struct Transport {
+    pub offset_ms:      i64,
+    pub last_audio_ms:  i64,
+}
+
+pub fn new() -> Transport {
+    Transport {
+        offset_ms:      0,
+        last_audio_ms:  0,
+    }
+}
+
+// Advance to a new reading of the audio clock, returns the milliseconds passed since the last reading
+pub fn advance(transport: &mut Transport, audio_ms: i64) -> i64 {
+    let delta = audio_ms - transport.last_audio_ms;
+    transport.last_audio_ms = audio_ms;
+    delta
+}
+
+// Current position on the timeline
+pub fn now(transport: &Transport) -> i64 {
+    transport.last_audio_ms + transport.offset_ms
+}
+
+// Time on the audio clock at which the timeline reaches time_ms
+pub fn to_audio_ms(transport: &Transport, time_ms: i64) -> i64 {
+    time_ms - transport.offset_ms
+}
+
+// Time on the timeline when the audio clock reads audio_ms
+pub fn from_audio_ms(transport: &Transport, audio_ms: i64) -> i64 {
+    audio_ms + transport.offset_ms
+}
+
+// Move the timeline so the current position reads as new_time
+pub fn set_time(transport: &mut Transport, new_time: i64) {
+    transport.offset_ms = new_time - transport.last_audio_ms;
+}
+
+// Move the timeline forward, or back with a negative delta_ms
+pub fn shift(transport: &mut Transport, delta_ms: i64) {
+    transport.offset_ms += delta_ms;
+}
+
+// Send a tick each time the audio clock passes a multiple of step_ms.
+// Ticks carry the audio clock time and only one is queued at a time, a slow receiver sees fewer
+// ticks rather than a backlog of stale ones. The receiver must clear the pending flag on receipt.
+pub fn start_metronome<T, F>(clock: Arc<AudioClock>, step_ms: i64, pending: Arc<AtomicBool>, tx: mpsc::Sender<T>, tick: F) -> thread::JoinHandle<()>
+where
+    T: Send + 'static,
+    F: Fn(i64) -> T + Send + 'static,
+{
+    let step_ms = step_ms.max(1);
+    // Poll a few times per step so ticks land close to the step boundary
+    let poll = Duration::from_millis(((step_ms / 4).max(1)) as u64);
+
+    thread::spawn(move || {
+        let mut next_tick = 0i64;
+        loop {
+            let audio_ms = clock.elapsed_ms();
+            if audio_ms >= next_tick {
+                next_tick = (audio_ms / step_ms + 1) * step_ms;
+                if !pending.swap(true, Ordering::SeqCst) {
+                    if tx.send(tick(audio_ms)).is_err() {
+                        println!("Metronome receiver has gone, stopping metronome.");
+                        break;
+                    }
+                }
+            }
+            thread::sleep(poll);
+        }
+    })
+}
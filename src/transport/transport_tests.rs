@@ -0,0 +1,31 @@
+#[cfg(test)]
+mod transport_test {
+    use transport::*;
+
+    #[test]
+    fn follows_audio_clock() {
+        let mut transport = new();
+        assert_eq!(advance(&mut transport, 40), 40);
+        assert_eq!(advance(&mut transport, 50), 10);
+        assert_eq!(now(&transport), 50);
+        assert_eq!(to_audio_ms(&transport, 80), 80);
+    }
+
+    #[test]
+    fn set_time_moves_timeline() {
+        let mut transport = new();
+        advance(&mut transport, 1_000);
+        set_time(&mut transport, 60_000);
+        assert_eq!(now(&transport), 60_000);
+        assert_eq!(to_audio_ms(&transport, 60_500), 1_500);
+        assert_eq!(from_audio_ms(&transport, 1_500), 60_500);
+
+        // The offset holds as the audio clock moves on
+        assert_eq!(advance(&mut transport, 1_250), 250);
+        assert_eq!(now(&transport), 60_250);
+
+        shift(&mut transport, -50);
+        assert_eq!(now(&transport), 60_200);
+        assert_eq!(to_audio_ms(&transport, now(&transport)), 1_250);
+    }
+}
@@ -0,0 +1,101 @@
+use config;
+use config::{SoundResource, VariantChange, VariantMode};
+use rng;
+
+mod variants_tests;
+
+// Chooses which of a resource's files plays next
+pub struct VariantPicker {
+    pub weights:    Vec<f32>,
+    pub mode:       VariantMode,
+    pub change:     VariantChange,
+    rng:            rng::Rng,
+    last:           Option<usize>,
+    bag:            Vec<usize>,
+}
+
+pub fn new(weights: Vec<f32>, mode: VariantMode, change: VariantChange, rng: rng::Rng) -> VariantPicker {
+    VariantPicker {
+        weights:    weights.iter().map(|w| w.max(0.0)).collect(),
+        mode:       mode,
+        change:     change,
+        rng:        rng,
+        last:       None,
+        bag:        Vec::new(),
+    }
+}
+
+pub fn from_resource(res: &SoundResource) -> VariantPicker {
+    let rng = match res.seed {
+        Some (seed) => rng::seeded(seed),
+        None => rng::from_time(),
+    };
+    new(
+        config::variant_weights(res),
+        res.variant_mode.unwrap_or(VariantMode::Weighted),
+        res.variant_change.unwrap_or(VariantChange::Activation),
+        rng,
+    )
+}
+
+// Weighted choice from the candidates, None if every candidate has no weight
+fn weighted_choice(picker: &mut VariantPicker, candidates: &Vec<usize>) -> Option<usize> {
+    let total: f32 = candidates.iter().map(|&i| picker.weights[i]).sum();
+    if total <= 0.0 {
+        return None
+    }
+    let mut target = rng::range(&mut picker.rng, 0.0, total);
+    for &i in candidates {
+        if target < picker.weights[i] {
+            return Some(i)
+        }
+        target -= picker.weights[i];
+    }
+    candidates.iter().cloned().filter(|&i| picker.weights[i] > 0.0).last()
+}
+
+// Fill the bag with each variant, weights are the number of times a variant is in the bag
+fn refill_bag(picker: &mut VariantPicker) {
+    for (i, weight) in picker.weights.iter().enumerate() {
+        let count = weight.round() as usize;
+        let count = if count == 0 && *weight > 0.0 { 1 } else { count };
+        for _ in 0..count {
+            picker.bag.push(i);
+        }
+    }
+}
+
+// Choose the next variant
+pub fn pick(picker: &mut VariantPicker) -> usize {
+    let everything: Vec<usize> = (0..picker.weights.len()).collect();
+    let choice = match picker.mode {
+        VariantMode::Weighted => weighted_choice(picker, &everything),
+        VariantMode::NoRepeat => {
+            let last = picker.last;
+            let others: Vec<usize> = everything.iter().cloned().filter(|&i| Some(i) != last).collect();
+            weighted_choice(picker, &others).or_else(|| weighted_choice(picker, &everything))
+        },
+        VariantMode::ShuffleBag => {
+            if picker.bag.is_empty() {
+                refill_bag(picker);
+            }
+            if picker.bag.is_empty() {
+                None
+            }
+            else {
+                // Avoid playing the same variant twice in a row where the bag allows
+                let mut index = (rng::next_u64(&mut picker.rng) % picker.bag.len() as u64) as usize;
+                if Some(picker.bag[index]) == picker.last {
+                    if let Some (other) = picker.bag.iter().position(|&i| Some(i) != picker.last) {
+                        index = other;
+                    }
+                }
+                Some(picker.bag.swap_remove(index))
+            }
+        },
+    };
+
+    let choice = choice.unwrap_or(0);
+    picker.last = Some(choice);
+    choice
+}
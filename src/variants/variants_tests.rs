@@ -0,0 +1,36 @@
+#[cfg(test)]
+mod variants_test {
+    use variants::*;
+    use config::{VariantChange, VariantMode};
+    use rng;
+
+    fn picks(mode: VariantMode, weights: Vec<f32>, seed: u64, count: usize) -> Vec<usize> {
+        let mut picker = new(weights, mode, VariantChange::Activation, rng::seeded(seed));
+        (0..count).map(|_| pick(&mut picker)).collect()
+    }
+
+    #[test]
+    fn seeded_picks_repeat() {
+        let weights = vec![1.0, 2.0, 0.5];
+        assert_eq!(picks(VariantMode::Weighted, weights.clone(), 42, 20), picks(VariantMode::Weighted, weights.clone(), 42, 20));
+        // A variant with no weight is never chosen
+        assert!(!picks(VariantMode::Weighted, vec![1.0, 0.0, 1.0], 42, 100).contains(&1));
+    }
+
+    #[test]
+    fn no_repeat() {
+        let picked = picks(VariantMode::NoRepeat, vec![1.0, 1.0, 5.0], 7, 100);
+        for pair in picked.windows(2) {
+            assert!(pair[0] != pair[1]);
+        }
+        // A single variant is still played
+        assert_eq!(picks(VariantMode::NoRepeat, vec![1.0], 7, 3), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn shuffle_bag() {
+        let mut picked = picks(VariantMode::ShuffleBag, vec![1.0, 2.0, 1.0], 3, 4);
+        picked.sort();
+        assert_eq!(picked, vec![0, 1, 1, 2]);
+    }
+}